@@ -1,124 +1,19 @@
-use clap::{Parser, Subcommand};
+use camino::Utf8PathBuf;
 use glob::glob;
-use log::LevelFilter;
 use snafu::prelude::*;
 
 use yunohost::{
     error::*,
-    helpers::{apt::*, file::*, group::*, process::*, service::*, user::*},
+    helpers::{
+        apt::*, file::*, group::*, process::*, regenconf::hook::*, service::*, template::*, user::*,
+    },
 };
 
 use std::fs::{copy, create_dir_all as mkdir, read_dir, write};
 use std::os::unix::fs::symlink;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::process::exit;
 
-// Static configuration, can be overriden at runtime (TODO)
-const LOGIND_OVERRIDE: &'static str = include_str!("logind.service.override.conf");
-const NFTABLES_OVERRIDE: &'static str = include_str!("nftables.service.override.conf");
-const NTP_OVERRIDE: &'static str = include_str!("ntp.service.override.conf");
-
-// Use me with complete name whatever.foo.d in /etc/systemd
-pub struct SystemdOverride {
-    dir: PathBuf,
-}
-
-impl SystemdOverride {
-    fn service_dir_name(name: &str) -> String {
-        if name.ends_with(".service.d") {
-            name.to_string()
-        } else if name.ends_with(".service") {
-            format!("{name}.d")
-        } else {
-            format!("{name}.service.d")
-        }
-    }
-
-    /// Handle ynh-override.conf for service *name* in `/etc/systemd/system`.
-    ///
-    /// For use in `pending_dir`, see [`Self::new_service_pending`].
-    /// Acceptable `name` forms:
-    /// - `SERVICE`
-    /// - `SERVICE.service`
-    /// - `SERVICE.service.d`
-    pub fn new_service(name: &str) -> Self {
-        let name = Self::service_dir_name(name.as_ref());
-
-        Self {
-            dir: PathBuf::from(&format!("/etc/systemd/system/{name}")),
-        }
-    }
-
-    /// Handle ynh-override.conf for service *name* in `pending_dir`.
-    ///
-    /// For use on the OS globally, see [`Self::new_service`].
-    /// Acceptable `name` forms:
-    /// - `SERVICE`
-    /// - `SERVICE.service`
-    /// - `SERVICE.service.d`
-    pub fn new_service_pending<U: AsRef<Path>>(name: &str, pending_dir: U) -> Self {
-        let name = Self::service_dir_name(name);
-
-        Self {
-            dir: pending_dir
-                .as_ref()
-                .join(&format!("etc/systemd/system/{name}")),
-        }
-    }
-
-    /// Handle ynh-override.conf for other systemd config *name*.
-    ///
-    /// For use in `pending_dir`, see [`Self::new_other_pending`].
-    /// The foler `name` is appended without modification to `pending_dir/etc/systemd/`,
-    /// then the ynh-override.conf is placed there.
-    pub fn new_other(name: &str) -> Self {
-        Self {
-            dir: PathBuf::from(&format!("/etc/systemd/{name}")),
-        }
-    }
-
-    /// Handle ynh-override.conf for other systemd config *name*.
-    ///
-    /// For use in OS globally, see [`Self::new_other`].
-    /// The foler `name` is appended without modification to `/etc/systemd/`,
-    /// then the ynh-override.conf is placed there.
-    pub fn new_other_pending<U: AsRef<Path>>(name: &str, pending_dir: U) -> Self {
-        Self {
-            dir: pending_dir.as_ref().join(&format!("etc/systemd/{name}")),
-        }
-    }
-
-    pub fn pre(&self, content: &str) {
-        path(self.dir.to_str().unwrap()).mkdir_p().unwrap();
-        write(self.dir.join("ynh-override.conf"), content).unwrap();
-    }
-
-    pub fn post(&self) {}
-}
-
-#[derive(Clone, Debug, Parser)]
-#[command(version, about, long_about = None)]
-struct Cli {
-    /// Enable debug logging
-    #[arg(short, long)]
-    debug: bool,
-    #[command(subcommand)]
-    command: SubCommand,
-}
-
-#[derive(Clone, Debug, Subcommand)]
-enum SubCommand {
-    Init,
-    Pre {
-        #[arg()]
-        files: PathBuf,
-    },
-    Post {
-        #[arg()]
-        files: Option<String>,
-    },
-}
-
 fn do_init_regen() -> Result<(), Error> {
     if !is_root() {
         eprintln!("You need to be root to run this script");
@@ -211,60 +106,44 @@ fn do_init_regen() -> Result<(), Error> {
     Ok(())
 }
 
-fn do_pre_regen(pending_dir: PathBuf) -> Result<(), Error> {
+fn do_pre_regen(pending_dir: Utf8PathBuf) -> Result<(), Error> {
     for dir in ["etc/systemd/system", "etc/cron.d", "etc/cron.daily"] {
-        let dir = StrPath::from(pending_dir.join(dir).to_str().unwrap());
+        let dir = StrPath::from(pending_dir.join(dir));
         dir.mkdir_p().unwrap();
     }
 
-    // add cron job for diagnosis to be ran at 7h and 19h + a random delay between
-    // 0 and 20min, meant to avoid every instances running their diagnosis at
-    // exactly the same time, which may overload the diagnosis server.
-    write(
-        pending_dir.join("etc/cron.d/yunohost-diagnosis"),
-        r#""
-SHELL=/bin/bash
-0 7,19 * * * root : YunoHost Automatic Diagnosis; sleep \$((RANDOM\\%1200)); yunohost diagnosis run --email > /dev/null 2>/dev/null || echo "Running the automatic diagnosis failed miserably"
-        ""#,
-    ).unwrap();
-
-    // Cron job that upgrade the app list everyday
-    write(
-        pending_dir.join("etc/cron.daily/yunohost-fetch-apps-catalog"),
-        r#""
-#!/bin/bash
-sleep \$((RANDOM%3600)); yunohost tools update apps > /dev/null
-        ""#,
-    )
-    .unwrap();
-
-    // Cron job that renew lets encrypt certificates if there's any that needs renewal
-    write(
-        pending_dir.join("etc/cron.daily/yunohost-certificate-renew"),
-        r#""
-#!/bin/bash
-yunohost domain cert renew --email
-        ""#,
-    )
-    .unwrap();
+    let templates = Templates::new("yunohost");
+    for (template, dest) in [
+        // add cron job for diagnosis to be ran at 7h and 19h + a random delay between
+        // 0 and 20min, meant to avoid every instances running their diagnosis at
+        // exactly the same time, which may overload the diagnosis server.
+        (
+            "yunohost-diagnosis.cron.j2",
+            "etc/cron.d/yunohost-diagnosis",
+        ),
+        // Cron job that upgrade the app list everyday
+        (
+            "yunohost-fetch-apps-catalog.j2",
+            "etc/cron.daily/yunohost-fetch-apps-catalog",
+        ),
+        // Cron job that renew lets encrypt certificates if there's any that needs renewal
+        (
+            "yunohost-certificate-renew.j2",
+            "etc/cron.daily/yunohost-certificate-renew",
+        ),
+    ] {
+        templates.render_to(template, &(), &pending_dir.join(dest))?;
+    }
 
     // If we subscribed to a dyndns domain, add the corresponding cron
     // - delay between 0 and 60 secs to spread the check over a 1 min window
     // - do not run the command if some process already has the lock, to avoid queuing hundreds of commands...
     if glob("/etc/yunohost/dyndns/K*.key").unwrap().count() != 0 {
-        write(
-            pending_dir.join("etc/cron.d/yunohost-dyndns"),
-            r#""
-SHELL=/bin/bash
-# Every 10 minutes,
-#   - (sleep random 60 is here to spread requests over a 1-min window)
-#   - if ip.yunohost.org answers ping (basic check to validate that we're connected to the internet and yunohost infra aint down)
-#   - and if lock ain't already taken by another command
-#   - trigger yunohost dyndns update
-*/10 * * * * root : YunoHost DynDNS update; sleep \$((RANDOM\\%60)); ! ping -q -W5 -c1 ip.yunohost.org >/dev/null 2>&1 || test -e /var/run/moulinette_yunohost.lock || yunohost dyndns update >> /dev/null
-
-            ""#,
-        ).unwrap();
+        templates.render_to(
+            "yunohost-dyndns.cron.j2",
+            &(),
+            &pending_dir.join("etc/cron.d/yunohost-dyndns"),
+        )?;
     } else {
         // (Delete cron if no dyndns domain found)
         write(pending_dir.join("etc/cron.d/yunohost-dyndns"), "").unwrap();
@@ -273,16 +152,16 @@ SHELL=/bin/bash
     // Skip ntp if inside a container (inspired from the conf of systemd-timesyncd)
     if SystemCtl::exists("ntp.service") {
         let so = SystemdOverride::new_service_pending("ntp.service", &pending_dir);
-        so.pre(NTP_OVERRIDE);
+        so.pre(&templates.render("ntp.override.conf.j2", &())?);
     }
 
     // Make nftable conflict with yunohost-firewall
     let so = SystemdOverride::new_service_pending("nftables.service", &pending_dir);
-    so.pre(NFTABLES_OVERRIDE);
+    so.pre(&templates.render("nftables.override.conf.j2", &())?);
 
     // Don't suspend computer on LidSwitch
     let so = SystemdOverride::new_other_pending("logind.conf.d", &pending_dir);
-    so.pre(LOGIND_OVERRIDE);
+    so.pre(&templates.render("logind.override.conf.j2", &())?);
 
     let conf_dir = PathBuf::from("/usr/share/yunohost/conf/yunohost");
 
@@ -520,28 +399,53 @@ fn do_post_regen(regen_conf_files: Option<String>) -> Result<(), Error> {
 
     if path("/etc/yunohost/installed").exists() {
         ensure_file_remove("/etc/profile.d/check_yunohost_is_installed.sh")
+            .context(FileSnafu)
             .context(ConfRegenYunohostPreIsInstalledCheckSnafu)?;
     }
 
     Ok(())
 }
 
-fn main() -> Result<(), Error> {
-    let cli = Cli::parse();
+struct YunohostHook;
 
-    if cli.debug {
-        pretty_env_logger::formatted_builder()
-            .filter_level(LevelFilter::Debug)
-            .init();
-    } else {
-        pretty_env_logger::formatted_builder()
-            .filter_level(LevelFilter::Info)
-            .init();
+impl ConfRegenHook for YunohostHook {
+    fn init() -> Result<(), Error> {
+        do_init_regen()
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        do_pre_regen(pending_dir)
     }
 
-    match cli.command {
-        SubCommand::Init => do_init_regen(),
-        SubCommand::Pre { files } => do_pre_regen(files),
-        SubCommand::Post { files } => do_post_regen(files),
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        do_post_regen(regen_conf_files)
+    }
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<YunohostHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/yunohost"));
+        for template in [
+            "yunohost-diagnosis.cron.j2",
+            "yunohost-dyndns.cron.j2",
+            "yunohost-fetch-apps-catalog.j2",
+            "yunohost-certificate-renew.j2",
+            "ntp.override.conf.j2",
+            "nftables.override.conf.j2",
+            "logind.override.conf.j2",
+        ] {
+            insta::assert_snapshot!(
+                template.trim_end_matches(".j2"),
+                templates.render(template, &()).unwrap()
+            );
+        }
     }
 }
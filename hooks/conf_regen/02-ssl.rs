@@ -0,0 +1,113 @@
+use camino::Utf8PathBuf;
+use log::{info, warn};
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{file::*, process::*, regenconf::hook::*, ssl::*},
+};
+
+use std::process::exit;
+
+/// Where the local CA was kept before Yunohost 11.
+const LEGACY_SSL_DIR: &str = "/usr/share/yunohost/yunohost-config/ssl/yunoCA";
+
+struct SslHook;
+
+impl ConfRegenHook for SslHook {
+    fn init() -> Result<(), Error> {
+        if !is_root() {
+            eprintln!("You need to be root to run this script");
+            exit(1);
+        }
+
+        let paths = SslPaths::system();
+        paths.install_openssl_conf()?;
+
+        // Create default certificates
+        if !paths.default_cert_dir().join("ca.pem").is_file() {
+            paths.regen_local_ca("yunohost.org")?;
+            _update_ca_certificates()?;
+        }
+
+        if !paths.default_cert_dir().join("crt.pem").is_file() {
+            paths.create_default_cert()?;
+            _link_system_cert(&paths.default_cert_dir())?;
+        }
+
+        paths.enforce_permissions()
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        let content = SslPaths::system().openssl_conf_content()?;
+        let pending_conf = path(pending_dir.join(SSL_DIR.trim_start_matches('/')));
+
+        pending_conf
+            .mkdir_p()
+            .and_then(|_| path(pending_conf.join("openssl.cnf")).write(content))
+            .context(FileSnafu)
+    }
+
+    fn post(_regen_conf_files: Option<String>) -> Result<(), Error> {
+        let paths = SslPaths::system();
+
+        // Automigrate legacy folder
+        let legacy_dir = path(LEGACY_SSL_DIR);
+        if legacy_dir.is_dir() {
+            info!("Moving the local CA from {LEGACY_SSL_DIR} to {SSL_DIR}");
+            let res = cmd("cp", ["-a", &format!("{LEGACY_SSL_DIR}/."), SSL_DIR])
+                .context(ConfRegenSslPostLegacySnafu)?;
+            ensure!(res.status.success(), ConfRegenSslPostLegacyCopySnafu);
+            std::fs::remove_dir_all("/usr/share/yunohost/yunohost-config")
+                .context(ConfRegenSslPostLegacyRemoveSnafu)?;
+            // Overwrite openssl.cnf because it may still contain references to the old yunoCA dir
+            paths.install_openssl_conf()?;
+        }
+
+        paths.enforce_permissions()?;
+
+        let main_domain = path("/etc/yunohost/current_host")
+            .read()
+            .context(FileSnafu)
+            .context(ConfRegenSslPostMainDomainSnafu)?
+            .trim()
+            .to_string();
+
+        if paths.local_ca_domain()?.as_deref() != Some(&main_domain) {
+            paths.regen_local_ca(&main_domain)?;
+            _update_ca_certificates()?;
+
+            // Idk how useful this is, but this was in the previous python code (domain.main_domain())
+            _link_system_cert(&path(format!("/etc/yunohost/certs/{main_domain}")))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Points the system-wide `yunohost_crt.pem` and `yunohost_key.pem` to the certificate in `cert_dir`.
+fn _link_system_cert(cert_dir: &StrPath) -> Result<(), Error> {
+    path("/etc/ssl/certs/yunohost_crt.pem")
+        .symlink_to_target(&cert_dir.join("crt.pem"), true)
+        .and_then(|_| {
+            path("/etc/ssl/private/yunohost_key.pem")
+                .symlink_to_target(&cert_dir.join("key.pem"), true)
+        })
+        .context(FileSnafu)
+}
+
+fn _update_ca_certificates() -> Result<(), Error> {
+    let res = cmd("update-ca-certificates", Vec::<&str>::new())
+        .context(ConfRegenSslUpdateCaCertificatesSnafu)?;
+    if !res.status.success() {
+        warn!(
+            "update-ca-certificates failed: {}",
+            String::from_utf8_lossy(&res.stderr)
+        );
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<SslHook>()
+}
@@ -0,0 +1,128 @@
+use camino::Utf8PathBuf;
+use log::info;
+use serde::Serialize;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{file::*, regenconf::hook::*, service::*, settings::*, template::*},
+};
+
+const SSHD_CONFIG: &str = "/etc/ssh/sshd_config";
+
+/// The host keys to use, in order of preference, when they exist.
+const HOST_KEYS: [&str; 3] = [
+    "/etc/ssh/ssh_host_ed25519_key",
+    "/etc/ssh/ssh_host_rsa_key",
+    "/etc/ssh/ssh_host_ecdsa_key",
+];
+
+struct SshHook;
+
+impl ConfRegenHook for SshHook {
+    fn init() -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        Templates::new("ssh").render_to(
+            "sshd_config.j2",
+            &SshdConfig::from_settings()?,
+            &pending_dir.join(SSHD_CONFIG.trim_start_matches('/')),
+        )
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        if !conf_changed(regen_conf_files.as_deref(), SSHD_CONFIG) {
+            return Ok(());
+        }
+
+        // Enforce permissions for /etc/ssh/sshd_config
+        path(SSHD_CONFIG)
+            .chown_and_mode(0o644, "root", Some("root"))
+            .context(FileSnafu)?;
+
+        info!("Reloading ssh because {SSHD_CONFIG} changed");
+        ensure!(SystemCtl::reload("ssh", &[]), ConfRegenSshPostReloadSnafu);
+
+        Ok(())
+    }
+}
+
+/// The variables of the `sshd_config` template.
+#[derive(Serialize)]
+struct SshdConfig {
+    port: String,
+    ipv6_enabled: bool,
+    host_keys: Vec<&'static str>,
+    /// `intermediate` also allows the older key exchanges, ciphers and MACs, for old SSH clients
+    compatibility: String,
+    password_authentication: bool,
+}
+
+impl SshdConfig {
+    /// Reads the `security.ssh` settings, and the host keys available on the system.
+    ///
+    /// Errors when:
+    /// - reading the settings failed
+    fn from_settings() -> Result<Self, Error> {
+        let mut settings = SettingsConfigPanel::new()
+            .context(ConfigPanelSnafu)
+            .context(ConfRegenSshPreSettingsSnafu)?;
+
+        Ok(Self {
+            port: settings
+                .get_string("security.ssh.ssh_port")
+                .context(ConfigPanelSnafu)
+                .context(ConfRegenSshPreSettingsSnafu)?,
+            ipv6_enabled: ipv6_available(),
+            host_keys: HOST_KEYS
+                .into_iter()
+                .filter(|key| path(key).is_file())
+                .collect(),
+            compatibility: settings
+                .get_string("security.ssh.ssh_compatibility")
+                .context(ConfigPanelSnafu)
+                .context(ConfRegenSshPreSettingsSnafu)?,
+            password_authentication: settings
+                .get_bool("security.ssh.ssh_password_authentication")
+                .context(ConfigPanelSnafu)
+                .context(ConfRegenSshPreSettingsSnafu)?,
+        })
+    }
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<SshHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sshd_config() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/ssh"));
+        let mut sshd_config = SshdConfig {
+            port: "22".to_string(),
+            ipv6_enabled: true,
+            host_keys: HOST_KEYS.to_vec(),
+            compatibility: "modern".to_string(),
+            password_authentication: true,
+        };
+        insta::assert_snapshot!(
+            "modern",
+            templates.render("sshd_config.j2", &sshd_config).unwrap()
+        );
+
+        sshd_config.port = "2222".to_string();
+        sshd_config.ipv6_enabled = false;
+        sshd_config.host_keys.truncate(1);
+        sshd_config.compatibility = "intermediate".to_string();
+        sshd_config.password_authentication = false;
+        insta::assert_snapshot!(
+            "intermediate",
+            templates.render("sshd_config.j2", &sshd_config).unwrap()
+        );
+    }
+}
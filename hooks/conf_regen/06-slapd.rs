@@ -0,0 +1,311 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::Local;
+use log::{info, warn};
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{file::*, process::*, regenconf::hook::*, service::*, ssl::SSL_CERT_GROUP},
+};
+
+use std::process::exit;
+use std::thread::sleep;
+use std::time::Duration;
+
+const LDAP_DIR: &str = "/etc/ldap";
+const SCHEMA_DIR: &str = "/etc/ldap/schema";
+/// The `cn=config` database of slapd, generated from [`SLAPD_LDIF`].
+const SLAPD_D: &str = "/etc/ldap/slapd.d";
+/// Where the new `cn=config` database is tested, before replacing [`SLAPD_D`].
+const SLAPD_NEW_D: &str = "/etc/ldap/slapd_new.d";
+/// The configuration of slapd, in LDIF format.
+const SLAPD_LDIF: &str = "/etc/ldap/slapd.ldif";
+/// The initial entries of the Yunohost LDAP database.
+const DB_INIT_LDIF: &str = "db_init.ldif";
+const LDAP_DB_DIR: &str = "/var/lib/ldap";
+/// The LDAP schemas added by Yunohost.
+const SCHEMAS: [&str; 3] = ["sudo.ldif", "mailserver.ldif", "permission.ldif"];
+/// The configuration files of older Yunohost versions, which should be removed.
+const LEGACY_CONFS: [&str; 3] = [
+    "/etc/ldap/slapd-yuno.conf",
+    "/etc/ldap/slapd.conf",
+    "/etc/ldap/schema/yunohost.schema",
+];
+/// How many times to check whether the admin can log in after reloading slapd, every 5 seconds.
+const ADMIN_WAIT_TRIES: u32 = 60;
+
+struct SlapdHook;
+
+impl ConfRegenHook for SlapdHook {
+    fn init() -> Result<(), Error> {
+        if !is_root() {
+            eprintln!("You need to be root to run this script");
+            exit(1);
+        }
+
+        let conf_dir = Utf8PathBuf::from(format!("{YUNOHOST_CONF_DIR}/slapd"));
+        _pre_regen(&conf_dir, Utf8Path::new("/"))?;
+
+        // Drop the existing slapd data
+        for backup in glob("/var/backups/*.ldapdb")
+            .and_then(|mut backups| {
+                backups.extend(glob("/var/backups/slapd-*")?);
+                Ok(backups)
+            })
+            .context(FileSnafu)?
+        {
+            _remove_all(&backup)?;
+        }
+
+        _run("dpkg-reconfigure", &["-fnoninteractive", "slapd"])?;
+        _enforce_permissions()?;
+
+        info!("Initializing LDAP with YunoHost DB structure");
+        _remove_all(Utf8Path::new(SLAPD_D))?;
+        path(SLAPD_D).mkdir_p().context(FileSnafu)?;
+        _run(
+            "slapadd",
+            &["-F", SLAPD_D, "-b", "cn=config", "-l", SLAPD_LDIF],
+        )?;
+        _run("chown", &["-R", "openldap:openldap", SLAPD_D])?;
+
+        _remove_all(Utf8Path::new(LDAP_DB_DIR))?;
+        path(LDAP_DB_DIR).mkdir_p().context(FileSnafu)?;
+        _run(
+            "slapadd",
+            &[
+                "-F",
+                SLAPD_D,
+                "-b",
+                "dc=yunohost,dc=org",
+                "-l",
+                conf_dir.join(DB_INIT_LDIF).as_str(),
+            ],
+        )?;
+        _run("chown", &["-R", "openldap:openldap", LDAP_DB_DIR])?;
+
+        // The caches may still contain users and groups of the previous database
+        let _ = cmd("nscd", ["-i", "group"]);
+        let _ = cmd("nscd", ["-i", "passwd"]);
+
+        _service("restart", SystemCtl::restart("slapd", &[]))
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        let conf_dir = Utf8PathBuf::from(format!("{YUNOHOST_CONF_DIR}/slapd"));
+        _pre_regen(&conf_dir, &pending_dir)
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        info!("Enforce permissions on ldap/slapd directories and certs ...");
+        _enforce_permissions()?;
+        _run("chown", &["-R", "openldap:openldap", SLAPD_D])?;
+
+        let regen_conf_files = regen_conf_files.as_deref();
+        if conf_changed(
+            regen_conf_files,
+            "/etc/systemd/system/slapd.service.d/ynh-override.conf",
+        ) {
+            SystemCtl::daemon_reload();
+            _service("restart", SystemCtl::restart("slapd", &[]))?;
+            sleep(Duration::from_secs(3));
+        }
+
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        let schema_changed = SCHEMAS
+            .iter()
+            .any(|schema| conf_changed(regen_conf_files, &format!("{SCHEMA_DIR}/{schema}")));
+        if schema_changed || conf_changed(regen_conf_files, SLAPD_LDIF) {
+            _regenerate_slapd_conf()?;
+        }
+
+        if schema_changed {
+            info!("Rebuilding the LDAP database indexes");
+            _run("sudo", &["-u", "openldap", "slapindex"])?;
+        }
+
+        info!("Reloading slapd");
+        // force-reload behaves like a restart anyway
+        _service("restart", SystemCtl::restart("slapd", &[]))?;
+
+        // On slow hardware, slapd takes some time to restart, and the next hooks need the admin user
+        // from LDAP, so wait until we are able to log in as admin
+        for _ in 0..ADMIN_WAIT_TRIES {
+            if cmd("su", ["admin", "-c", ""]).is_ok_and(|res| res.status.success()) {
+                return Ok(());
+            }
+            sleep(Duration::from_secs(5));
+        }
+        warn!("The admin user is still not available after reloading slapd");
+
+        Ok(())
+    }
+}
+
+/// Installs the slapd configuration shipped in `conf_dir` to `pending_dir`. This does not require root.
+///
+/// Errors when:
+/// - a configuration file could not be copied
+fn _pre_regen(conf_dir: &Utf8Path, pending_dir: &Utf8Path) -> Result<(), Error> {
+    let pending = |system_path: &str| pending_dir.join(system_path.trim_start_matches('/'));
+
+    for legacy in LEGACY_CONFS {
+        if path(legacy).exists() {
+            path(pending(legacy)).write("").context(FileSnafu)?;
+        }
+    }
+
+    install_conf(
+        &conf_dir.join("ldap.conf"),
+        &pending(LDAP_DIR).join("ldap.conf"),
+    )
+    .and_then(|_| install_conf(&conf_dir.join("config.ldif"), &pending(SLAPD_LDIF)))
+    .and_then(|_| {
+        install_conf(
+            &conf_dir.join("slapd.default"),
+            &pending("/etc/default/slapd"),
+        )
+    })
+    .context(FileSnafu)?;
+
+    for schema in SCHEMAS {
+        install_conf(&conf_dir.join(schema), &pending(SCHEMA_DIR).join(schema))
+            .context(FileSnafu)?;
+    }
+
+    let systemd_override = path(conf_dir.join("systemd-override.conf"))
+        .read()
+        .context(FileSnafu)?;
+    SystemdOverride::new_service_pending("slapd", pending_dir).pre(&systemd_override);
+
+    Ok(())
+}
+
+/// Generates the new `cn=config` database from [`SLAPD_LDIF`] in [`SLAPD_NEW_D`], and tests it with
+/// `slapd -Tt` before replacing [`SLAPD_D`]. The previous database is backed up in /var/backups.
+///
+/// Errors when:
+/// - the new configuration is invalid
+/// - the configuration directories could not be replaced
+fn _regenerate_slapd_conf() -> Result<(), Error> {
+    _remove_all(Utf8Path::new(SLAPD_NEW_D))?;
+    path(SLAPD_NEW_D).mkdir_p().context(FileSnafu)?;
+
+    let res = _run(
+        "slapadd",
+        &["-b", "cn=config", "-l", SLAPD_LDIF, "-F", SLAPD_NEW_D],
+    )
+    .and_then(|_| _run("slapd", &["-Tt", "-F", SLAPD_NEW_D]));
+    if let Err(e) = res {
+        _remove_all(Utf8Path::new(SLAPD_NEW_D))?;
+        return Err(e).context(ConfRegenSlapdPostConfigTestSnafu);
+    }
+
+    let backup = format!(
+        "/var/backups/slapd-{}.d",
+        Local::now().format("%Y%m%d.%H%M%S")
+    );
+    info!("Backing up the previous slapd configuration to {backup}");
+    _run("cp", &["-a", SLAPD_D, &backup])?;
+
+    _remove_all(Utf8Path::new(SLAPD_D))?;
+    std::fs::rename(SLAPD_NEW_D, SLAPD_D).context(ConfRegenSlapdPostReplaceSnafu)?;
+    _run("chown", &["-R", "openldap:openldap", SLAPD_D])?;
+
+    Ok(())
+}
+
+/// The openldap user needs to read the certificates for TLS, and to own its schemas.
+fn _enforce_permissions() -> Result<(), Error> {
+    _run("usermod", &["-aG", SSL_CERT_GROUP, "openldap"])?;
+    _run("chown", &["-R", "openldap:openldap", SCHEMA_DIR])?;
+    Ok(())
+}
+
+/// Removes a file, or a directory and its contents, if it exists.
+fn _remove_all(path: &Utf8Path) -> Result<(), Error> {
+    let res = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else if path.exists() {
+        std::fs::remove_file(path)
+    } else {
+        return Ok(());
+    };
+
+    res.context(ConfRegenSlapdRemoveSnafu {
+        path: path.to_path_buf(),
+    })
+}
+
+/// Runs a command, returning its standard output.
+///
+/// Errors when:
+/// - the command could not be run, or failed
+fn _run(command: &str, args: &[&str]) -> Result<String, Error> {
+    let res = cmd(command, args).context(ConfRegenSlapdCmdRunSnafu { cmd: command })?;
+    ensure!(
+        res.status.success(),
+        ConfRegenSlapdCmdSnafu {
+            cmd: command,
+            stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+        }
+    );
+    Ok(String::from_utf8_lossy(&res.stdout).to_string())
+}
+
+fn _service(action: &str, success: bool) -> Result<(), Error> {
+    ensure!(success, ConfRegenSlapdServiceSnafu { action });
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<SlapdHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pre_regen_in_tempdir() {
+        let conf_dir = tempfile::tempdir().unwrap();
+        let conf_dir = Utf8Path::from_path(conf_dir.path()).unwrap();
+        for name in [
+            "ldap.conf",
+            "config.ldif",
+            "slapd.default",
+            "systemd-override.conf",
+        ]
+        .into_iter()
+        .chain(SCHEMAS)
+        {
+            path(conf_dir.join(name)).write(name).unwrap();
+        }
+
+        let pending_dir = tempfile::tempdir().unwrap();
+        let pending_dir = Utf8Path::from_path(pending_dir.path()).unwrap();
+        _pre_regen(conf_dir, pending_dir).unwrap();
+
+        assert_eq!(
+            "config.ldif",
+            path(pending_dir.join("etc/ldap/slapd.ldif"))
+                .read()
+                .unwrap()
+        );
+        assert_eq!(
+            "permission.ldif",
+            path(pending_dir.join("etc/ldap/schema/permission.ldif"))
+                .read()
+                .unwrap()
+        );
+        assert_eq!(
+            "systemd-override.conf",
+            path(pending_dir.join("etc/systemd/system/slapd.service.d/ynh-override.conf"))
+                .read()
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use camino::Utf8PathBuf;
+use log::info;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{regenconf::hook::*, service::*},
+};
+
+const NSLCD_CONF: &str = "/etc/nslcd.conf";
+
+struct NslcdHook;
+
+impl ConfRegenHook for NslcdHook {
+    fn init() -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        install_conf(
+            &Utf8PathBuf::from(format!("{YUNOHOST_CONF_DIR}/nslcd/nslcd.conf")),
+            &pending_dir.join(NSLCD_CONF.trim_start_matches('/')),
+        )
+        .context(FileSnafu)
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        info!("Restarting nslcd");
+        ensure!(
+            SystemCtl::restart("nslcd", &[]),
+            ConfRegenNslcdPostRestartSnafu
+        );
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<NslcdHook>()
+}
@@ -0,0 +1,224 @@
+use camino::Utf8PathBuf;
+use log::{info, warn};
+use serde::Serialize;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{
+        distro::{debian_version, DebianRelease},
+        file::*,
+        process::*,
+        regenconf::hook::*,
+        template::*,
+    },
+};
+
+const PREFERENCES_D: &str = "/etc/apt/preferences.d";
+const SOURCES_LIST_D: &str = "/etc/apt/sources.list.d";
+const TRUSTED_GPG_D: &str = "/etc/apt/trusted.gpg.d";
+/// The packages from sury which would replace packages of the Debian release.
+const SURY_BANNED_PACKAGES: [&str; 5] = ["php", "php-*", "openssl", "libssl1.1", "libssl-dev"];
+
+/// An extra APT repository, with the URL of its signing key. The variables of the
+/// `repository.list` template.
+#[derive(Serialize)]
+struct Repository {
+    name: &'static str,
+    url: &'static str,
+    suite: &'static str,
+    key_url: &'static str,
+}
+
+impl Repository {
+    /// The sury repository provides the PHP versions which are not in the Debian release.
+    fn sury(release: DebianRelease) -> Self {
+        Self {
+            name: "extra_php_version",
+            url: "https://packages.sury.org/php/",
+            suite: release.codename(),
+            key_url: "https://packages.sury.org/php/apt.gpg",
+        }
+    }
+
+    fn yarn() -> Self {
+        Self {
+            name: "yarn",
+            url: "https://dl.yarnpkg.com/debian/",
+            suite: "stable",
+            key_url: "https://dl.yarnpkg.com/debian/pubkey.gpg",
+        }
+    }
+
+    fn key_file(&self) -> StrPath {
+        path(format!("{TRUSTED_GPG_D}/{}.gpg", self.name))
+    }
+}
+
+/// The variables of the APT preferences templates.
+#[derive(Serialize)]
+struct AptPreferences {
+    /// The codename of the Debian release, whose backports are only installed on demand
+    codename: &'static str,
+    sury_banned_packages: [&'static str; 5],
+}
+
+/// The preferences templates: `extra_php_version` only allows the extra PHP versions from sury,
+/// `yarn` bans everything from the yarn repository except yarn, and `ban_packages` bans the packages
+/// which conflict with Yunohost.
+const PREFERENCES: [&str; 3] = ["extra_php_version", "yarn", "ban_packages"];
+
+struct AptHook;
+
+impl ConfRegenHook for AptHook {
+    fn init() -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        let release = *debian_version()?;
+        let preferences_d = pending_dir.join(PREFERENCES_D.trim_start_matches('/'));
+        let sources_list_d = pending_dir.join(SOURCES_LIST_D.trim_start_matches('/'));
+        let templates = Templates::new("apt");
+
+        for repository in [Repository::sury(release), Repository::yarn()] {
+            templates.render_to(
+                "repository.list.j2",
+                &repository,
+                &sources_list_d.join(format!("{}.list", repository.name)),
+            )?;
+        }
+
+        let preferences = AptPreferences {
+            codename: release.codename(),
+            sury_banned_packages: SURY_BANNED_PACKAGES,
+        };
+        for name in PREFERENCES {
+            templates.render_to(
+                &format!("{name}.j2"),
+                &preferences,
+                &preferences_d.join(name),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        let release = *debian_version()?;
+
+        // Make sure the PHP version of the Debian release is used for php in the command line
+        let php = format!("/usr/bin/php{}", _default_php_version(release));
+        if path(&php).exists() {
+            let _ = cmd("update-alternatives", ["--set", "php", &php]);
+        }
+
+        for repository in [Repository::sury(release), Repository::yarn()] {
+            let key_file = repository.key_file();
+            if !key_file.is_file() || key_file.read_bytes().context(FileSnafu)?.is_empty() {
+                _download_key(&repository)?;
+            }
+        }
+
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        info!("Updating the list of packages");
+        let res = cmd("apt-get", ["update", "--quiet"]).context(ConfRegenAptPostUpdateRunSnafu)?;
+        ensure!(
+            res.status.success(),
+            ConfRegenAptPostUpdateSnafu {
+                stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+            }
+        );
+
+        Ok(())
+    }
+}
+
+/// The PHP version shipped with each Debian release.
+fn _default_php_version(release: DebianRelease) -> &'static str {
+    match release {
+        DebianRelease::Bullseye => "7.4",
+        DebianRelease::Bookworm => "8.2",
+    }
+}
+
+/// Downloads the signing key of `repository`, and converts it to the binary format of APT.
+///
+/// Errors when:
+/// - the key could not be downloaded
+/// - the key could not be converted or saved
+fn _download_key(repository: &Repository) -> Result<(), Error> {
+    info!(
+        "Downloading the signing key of the {} repository",
+        repository.name
+    );
+    let res = cmd(
+        "curl",
+        [
+            "--max-time",
+            "900",
+            "--silent",
+            "--fail",
+            repository.key_url,
+        ],
+    )
+    .context(ConfRegenAptPostKeyRunSnafu)?;
+    if !res.status.success() {
+        // The repository is unusable without its key, but the other confs can still be applied
+        warn!(
+            "Could not download the signing key from {}",
+            repository.key_url
+        );
+        return Ok(());
+    }
+
+    let res = cmd_stdin("gpg", ["--dearmor"], &res.stdout).context(ConfRegenAptPostKeyRunSnafu)?;
+    ensure!(
+        res.status.success(),
+        ConfRegenAptPostKeySnafu {
+            url: repository.key_url,
+            stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+        }
+    );
+
+    repository.key_file().write(&res.stdout).context(FileSnafu)
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<AptHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/apt"));
+        for repository in [
+            Repository::sury(DebianRelease::Bookworm),
+            Repository::yarn(),
+        ] {
+            insta::assert_snapshot!(
+                format!("{}.list", repository.name),
+                templates.render("repository.list.j2", &repository).unwrap()
+            );
+        }
+
+        let preferences = AptPreferences {
+            codename: DebianRelease::Bookworm.codename(),
+            sury_banned_packages: SURY_BANNED_PACKAGES,
+        };
+        for name in PREFERENCES {
+            insta::assert_snapshot!(
+                name,
+                templates
+                    .render(&format!("{name}.j2"), &preferences)
+                    .unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use camino::Utf8PathBuf;
+use log::info;
+use serde::Serialize;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{
+        apt::is_installed, domain::*, file::*, process::*, regenconf::hook::*, service::*,
+        ssl::SSL_CERT_GROUP, template::*,
+    },
+};
+
+const METRONOME_DIR: &str = "/etc/metronome";
+/// The configuration of each XMPP domain, in `DOMAIN.cfg.lua`.
+const METRONOME_CONF_D: &str = "/etc/metronome/conf.d";
+const METRONOME_DATA_DIR: &str = "/var/lib/metronome";
+/// The files shared with http_upload, served by nginx.
+const XMPP_UPLOAD_DIR: &str = "/var/xmpp-upload";
+const METRONOME_USER: &str = "metronome";
+
+/// The variables of the `metronome.cfg.lua.j2` template.
+#[derive(Serialize)]
+struct MetronomeConf {
+    main_domain: String,
+}
+
+/// The variables of the `domain.tpl.cfg.lua.j2` template.
+#[derive(Serialize)]
+struct DomainConf<'a> {
+    domain: &'a str,
+}
+
+struct MetronomeHook;
+
+impl ConfRegenHook for MetronomeHook {
+    fn init() -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        let templates = Templates::new("metronome");
+        let metronome_dir = pending_dir.join(METRONOME_DIR.trim_start_matches('/'));
+        let conf_d = path(pending_dir.join(METRONOME_CONF_D.trim_start_matches('/')));
+        conf_d.mkdir_p().context(FileSnafu)?;
+
+        let conf = MetronomeConf {
+            main_domain: YunohostDomain::main().context(ConfRegenMetronomePreDomainsSnafu)?,
+        };
+        templates.render_to(
+            "metronome.cfg.lua.j2",
+            &conf,
+            &metronome_dir.join("metronome.cfg.lua"),
+        )?;
+
+        let domains = _xmpp_domains().context(ConfRegenMetronomePreDomainsSnafu)?;
+        for domain in &domains {
+            templates.render_to(
+                "domain.tpl.cfg.lua.j2",
+                &DomainConf { domain },
+                &conf_d.join(format!("{domain}.cfg.lua")),
+            )?;
+        }
+
+        remove_old_domain_confs(METRONOME_CONF_D, ".cfg.lua", &pending_dir, |domain| {
+            domains.iter().any(|xmpp_domain| xmpp_domain == domain)
+        })
+        .context(FileSnafu)?;
+
+        Ok(())
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        if !is_installed("metronome") {
+            info!("Metronome is not installed, skipping");
+            return Ok(());
+        }
+
+        for domain in _xmpp_domains().context(ConfRegenMetronomePostDomainsSnafu)? {
+            path(format!(
+                "{METRONOME_DATA_DIR}/{}/pep",
+                domain.replace('.', "%2e")
+            ))
+            .mkdir_p()
+            .context(FileSnafu)?;
+
+            // The uploads must be writable by metronome and readable by nginx. The setgid bit keeps
+            // the www-data group on the uploaded files, even though metronome is not in this group.
+            let upload_dir = path(format!("{XMPP_UPLOAD_DIR}/{domain}/upload"));
+            upload_dir
+                .mkdir_p()
+                .and_then(|_| upload_dir.mode_get())
+                .and_then(|mode| upload_dir.chmod(mode & 0o7777 | 0o2000))
+                .context(FileSnafu)?;
+        }
+
+        // Fix permissions
+        let upload_dir = path(XMPP_UPLOAD_DIR);
+        if upload_dir.exists() {
+            upload_dir
+                .chown_recurse(METRONOME_USER, "www-data")
+                .and_then(|_| upload_dir.chmod(0o750))
+                .context(FileSnafu)?;
+        }
+
+        // Metronome needs to read the certificates for TLS
+        let res = cmd("usermod", ["-aG", SSL_CERT_GROUP, METRONOME_USER])
+            .context(ConfRegenMetronomePostUsermodRunSnafu)?;
+        ensure!(
+            res.status.success(),
+            ConfRegenMetronomePostUsermodSnafu {
+                stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+            }
+        );
+        path(METRONOME_DATA_DIR)
+            .chown_recurse(METRONOME_USER, METRONOME_USER)
+            .and_then(|_| path(METRONOME_CONF_D).chown_recurse(METRONOME_USER, METRONOME_USER))
+            .context(FileSnafu)?;
+
+        // Only run metronome when at least one domain has XMPP enabled
+        if glob(&format!("{METRONOME_CONF_D}/*.cfg.lua"))
+            .context(FileSnafu)?
+            .is_empty()
+        {
+            if SystemCtl::is_enabled("metronome") {
+                info!("Disabling metronome, no domain has XMPP enabled");
+                SystemCtl::disable("metronome", &["--now", "--quiet"]);
+            }
+            return Ok(());
+        }
+
+        if !SystemCtl::is_enabled("metronome") {
+            info!("Enabling metronome");
+            SystemCtl::enable("metronome", &["--now", "--quiet"]);
+        }
+
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        info!("Restarting metronome");
+        ensure!(
+            SystemCtl::restart("metronome", &[]),
+            ConfRegenMetronomePostRestartSnafu
+        );
+
+        Ok(())
+    }
+}
+
+/// Lists the domains with the `xmpp` feature enabled, like `yunohost domain list --features xmpp`.
+///
+/// Errors when:
+/// - reading the domains or their settings failed
+fn _xmpp_domains() -> Result<Vec<String>, Error> {
+    let mut domains = vec![];
+    for domain in YunohostDomain::list(false)? {
+        if YunohostDomain::feature_enabled(&domain, "xmpp")? {
+            domains.push(domain);
+        }
+    }
+    Ok(domains)
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<MetronomeHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/metronome"));
+        let conf = MetronomeConf {
+            main_domain: "example.com".to_string(),
+        };
+        insta::assert_snapshot!(
+            "metronome.cfg.lua",
+            templates.render("metronome.cfg.lua.j2", &conf).unwrap()
+        );
+
+        let conf = DomainConf {
+            domain: "example.com",
+        };
+        insta::assert_snapshot!(
+            "domain.tpl.cfg.lua",
+            templates.render("domain.tpl.cfg.lua.j2", &conf).unwrap()
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use log::info;
+use rayon::prelude::*;
+use serde::Serialize;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{
+        domain::*, file::*, process::*, regenconf::hook::*, service::*, settings::*, ssl::*,
+        template::*,
+    },
+};
+
+const NGINX_CONF_DIR: &str = "/etc/nginx/conf.d";
+/// The configuration snippets which are copied without templating.
+const NGINX_PLAIN_DIR: &str = "/usr/share/yunohost/conf/nginx/plain";
+/// The configuration shared by all domains, rendered from `NAME.j2` with [`NginxSettings`].
+const GLOBAL_CONFS: [&str; 5] = [
+    "global.conf",
+    "security.conf.inc",
+    "yunohost_admin.conf",
+    "yunohost_admin.conf.inc",
+    "yunohost_api.conf.inc",
+];
+
+struct NginxHook;
+
+impl ConfRegenHook for NginxHook {
+    fn init() -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        let conf_dir = path(pending_dir.join(NGINX_CONF_DIR.trim_start_matches('/')));
+        conf_dir.mkdir_p().context(FileSnafu)?;
+
+        install_plain_confs(Utf8Path::new(NGINX_PLAIN_DIR), &conf_dir).context(FileSnafu)?;
+
+        let templates = Templates::new("nginx");
+        let settings = NginxSettings::from_settings()?;
+        for name in GLOBAL_CONFS {
+            templates.render_to(&format!("{name}.j2"), &settings, &conf_dir.join(name))?;
+        }
+
+        // Checking the certificates runs openssl for every domain, so it's done in parallel
+        let domains = YunohostDomain::list(false).context(ConfRegenNginxPreDomainsSnafu)?;
+        let ssl_paths = SslPaths::system();
+        let servers = domains
+            .par_iter()
+            .map(|domain| {
+                Ok(ServerConf {
+                    domain,
+                    cert_ca: ssl_paths.cert_ca(domain)?,
+                    redirect_to_https: settings.redirect_to_https,
+                })
+            })
+            .collect::<Result<Vec<ServerConf>, Error>>()
+            .context(ConfRegenNginxPreCertsSnafu)?;
+
+        for server in servers {
+            path(conf_dir.join(format!("{}.d", server.domain)))
+                .mkdir_p()
+                .context(FileSnafu)?;
+            templates.render_to(
+                "server.tpl.conf.j2",
+                &server,
+                &conf_dir.join(format!("{}.conf", server.domain)),
+            )?;
+        }
+
+        remove_old_domain_confs(NGINX_CONF_DIR, ".conf", &pending_dir, |domain| {
+            domains.iter().any(|d| d == domain)
+        })
+        .context(FileSnafu)?;
+
+        Ok(())
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        for snippet in glob(&format!("{NGINX_CONF_DIR}/*.d/*.conf")).context(FileSnafu)? {
+            path(snippet)
+                .chown_and_mode(0o644, "root", Some("root"))
+                .context(FileSnafu)?;
+        }
+
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        // Create the conf directories of the domains, for the apps to put their snippets
+        for domain in YunohostDomain::list(false).context(ConfRegenNginxPostDomainsSnafu)? {
+            path(format!("{NGINX_CONF_DIR}/{domain}.d"))
+                .mkdir_p()
+                .context(FileSnafu)?;
+        }
+
+        // Reload nginx only if the configuration looks good
+        let res = cmd("nginx", ["-t"]).context(ConfRegenNginxPostTestRunSnafu)?;
+        ensure!(
+            res.status.success(),
+            ConfRegenNginxPostTestSnafu {
+                stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+            }
+        );
+
+        info!("Reloading nginx");
+        ensure!(
+            SystemCtl::reload("nginx", &[]),
+            ConfRegenNginxPostReloadSnafu
+        );
+
+        Ok(())
+    }
+}
+
+/// The `security.nginx` and `security.webadmin` settings.
+#[derive(Serialize)]
+struct NginxSettings {
+    redirect_to_https: bool,
+    /// `modern` only accepts TLS 1.3, `intermediate` also accepts TLS 1.2 with forward secrecy
+    compatibility: String,
+    /// The IPs allowed to reach the webadmin, when the allowlist is enabled
+    webadmin_allowlist: Option<Vec<String>>,
+}
+
+impl NginxSettings {
+    /// Errors when:
+    /// - reading the settings failed
+    fn from_settings() -> Result<Self, Error> {
+        let mut settings = SettingsConfigPanel::new()
+            .context(ConfigPanelSnafu)
+            .context(ConfRegenNginxPreSettingsSnafu)?;
+
+        let webadmin_allowlist = if settings
+            .get_bool("security.webadmin.webadmin_allowlist_enabled")
+            .context(ConfigPanelSnafu)
+            .context(ConfRegenNginxPreSettingsSnafu)?
+        {
+            let allowlist = settings
+                .get_string("security.webadmin.webadmin_allowlist")
+                .context(ConfigPanelSnafu)
+                .context(ConfRegenNginxPreSettingsSnafu)?;
+            Some(
+                allowlist
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|ip| !ip.is_empty())
+                    .map(String::from)
+                    .collect(),
+            )
+        } else {
+            None
+        };
+
+        Ok(Self {
+            redirect_to_https: settings
+                .get_bool("security.nginx.nginx_redirect_to_https")
+                .context(ConfigPanelSnafu)
+                .context(ConfRegenNginxPreSettingsSnafu)?,
+            compatibility: settings
+                .get_string("security.nginx.nginx_compatibility")
+                .context(ConfigPanelSnafu)
+                .context(ConfRegenNginxPreSettingsSnafu)?,
+            webadmin_allowlist,
+        })
+    }
+}
+
+/// The variables of the server blocks of a domain, in `server.tpl.conf.j2`.
+#[derive(Serialize)]
+struct ServerConf<'a> {
+    domain: &'a str,
+    cert_ca: CertCa,
+    redirect_to_https: bool,
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<NginxHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_confs() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/nginx"));
+        let settings = NginxSettings {
+            redirect_to_https: true,
+            compatibility: "intermediate".to_string(),
+            webadmin_allowlist: Some(vec!["192.168.1.0/24".to_string(), "::1".to_string()]),
+        };
+        for name in GLOBAL_CONFS {
+            insta::assert_snapshot!(
+                name,
+                templates.render(&format!("{name}.j2"), &settings).unwrap()
+            );
+        }
+
+        let settings = NginxSettings {
+            redirect_to_https: true,
+            compatibility: "modern".to_string(),
+            webadmin_allowlist: None,
+        };
+        insta::assert_snapshot!(
+            "security.conf.inc.modern",
+            templates.render("security.conf.inc.j2", &settings).unwrap()
+        );
+        insta::assert_snapshot!(
+            "yunohost_api.conf.inc.no_allowlist",
+            templates
+                .render("yunohost_api.conf.inc.j2", &settings)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn server_conf() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/nginx"));
+        for (name, cert_ca, redirect_to_https) in [
+            ("self_signed", CertCa::SelfSigned, true),
+            ("lets_encrypt", CertCa::LetsEncrypt, true),
+            ("other_no_redirect", CertCa::Other, false),
+        ] {
+            let server = ServerConf {
+                domain: "example.com",
+                cert_ca,
+                redirect_to_https,
+            };
+            insta::assert_snapshot!(
+                name,
+                templates.render("server.tpl.conf.j2", &server).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use log::info;
+use serde::Serialize;
+use serde_yaml_ng::Value;
+use snafu::prelude::*;
+
+use std::collections::HashMap;
+
+use yunohost::{
+    error::*,
+    helpers::{
+        domain::*, file::*, process::*, regenconf::hook::*, service::*, settings::*, template::*,
+    },
+};
+
+const POSTFIX_DIR: &str = "/etc/postfix";
+/// The configuration files which are copied without templating.
+const POSTFIX_PLAIN_DIR: &str = "/usr/share/yunohost/conf/postfix/plain";
+const SASL_PASSWD: &str = "/etc/postfix/sasl_passwd";
+/// Maps every domain to its certificate. The comment in the template ensures that the file is never
+/// empty, which would make regen-conf remove it.
+const SNI: &str = "/etc/postfix/sni";
+/// Maps the sender addresses of the apps to their system user, so that apps can send emails.
+/// main.cf always references it, so it's generated even when no app sends emails.
+const APP_SENDERS_LOGIN_MAPS: &str = "/etc/postfix/app_senders_login_maps";
+const APPS_DIR: &str = "/etc/yunohost/apps";
+/// The configuration files rendered from `NAME.j2` with [`PostfixConf`].
+const TEMPLATES: [&str; 3] = ["main.cf", "master.cf", "sni"];
+
+struct PostfixHook;
+
+impl ConfRegenHook for PostfixHook {
+    fn init() -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        let postfix_dir = path(pending_dir.join(POSTFIX_DIR.trim_start_matches('/')));
+        postfix_dir.mkdir_p().context(FileSnafu)?;
+
+        install_plain_confs(Utf8Path::new(POSTFIX_PLAIN_DIR), &postfix_dir).context(FileSnafu)?;
+
+        let templates = Templates::new("postfix");
+        let conf = PostfixConf::from_settings()?;
+        for name in TEMPLATES {
+            templates.render_to(&format!("{name}.j2"), &conf, &postfix_dir.join(name))?;
+        }
+
+        let sasl_passwd = path(postfix_dir.join("sasl_passwd"));
+        if let Some(relay) = &conf.relay {
+            _write_sasl_passwd(&sasl_passwd, relay)?;
+        } else if path(SASL_PASSWD).exists() {
+            // The relay was disabled, so the password should not stay around
+            sasl_passwd.write("").context(FileSnafu)?;
+        }
+
+        Ok(())
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        let sasl_passwd = path(SASL_PASSWD);
+        let sasl_passwd_db = path(format!("{SASL_PASSWD}.db"));
+        if sasl_passwd.is_file() {
+            sasl_passwd
+                .chown_and_mode(0o600, "root", Some("root"))
+                .context(FileSnafu)?;
+
+            if conf_changed(regen_conf_files.as_deref(), SASL_PASSWD) || !sasl_passwd_db.is_file() {
+                _postmap(&[SASL_PASSWD])?;
+            }
+
+            sasl_passwd_db
+                .chown_and_mode(0o600, "root", Some("root"))
+                .context(FileSnafu)?;
+        } else if sasl_passwd_db.is_file() {
+            sasl_passwd_db.file_remove().context(FileSnafu)?;
+        }
+
+        if conf_changed(regen_conf_files.as_deref(), SNI) || !path(format!("{SNI}.db")).is_file() {
+            _postmap(&["-F", &format!("hash:{SNI}")])?;
+        }
+
+        _regen_app_senders_login_maps()?;
+
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        for service in ["postfix", "postsrsd"] {
+            info!("Restarting {service}");
+            ensure!(
+                SystemCtl::restart(service, &[]),
+                ConfRegenPostfixPostRestartSnafu { service }
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the sender addresses of the installed apps to [`APP_SENDERS_LOGIN_MAPS`] and builds its
+/// database when it changed or is missing, like Python's
+/// `regen_mail_app_user_config_for_dovecot_and_postfix(only="postfix")`.
+///
+/// Errors when:
+/// - reading the settings of an app failed
+/// - writing the map or changing its permissions failed
+/// - postmap failed
+fn _regen_app_senders_login_maps() -> Result<(), Error> {
+    let mut apps = Vec::new();
+    if path(APPS_DIR).is_dir() {
+        for settings in glob(&format!("{APPS_DIR}/*/settings.yml")).context(FileSnafu)? {
+            // UNWRAP NOTE: Globbed settings are always in an app directory
+            let app = settings.parent().unwrap().file_name().unwrap().to_string();
+            let settings: HashMap<String, Value> =
+                path(&settings).read_yaml().context(FileSnafu)?;
+            apps.push((app, settings));
+        }
+    }
+    apps.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let maps = path(APP_SENDERS_LOGIN_MAPS);
+    let maps_db = path(format!("{APP_SENDERS_LOGIN_MAPS}.db"));
+    let content = _app_senders_login_maps(&apps);
+    if maps.is_file() && maps.read().context(FileSnafu)? == content && maps_db.is_file() {
+        return Ok(());
+    }
+
+    maps.write(&content)
+        .and_then(|_| maps.chown_and_mode(0o440, "postfix", Some("root")))
+        .context(FileSnafu)?;
+    _postmap(&[APP_SENDERS_LOGIN_MAPS])?;
+    maps_db
+        .chown_and_mode(0o640, "postfix", Some("root"))
+        .context(FileSnafu)
+}
+
+/// Builds the content of [`APP_SENDERS_LOGIN_MAPS`] from the settings of the apps, mapping
+/// `mail_user@mail_domain` to the app for every app which has a `mail_pwd`.
+fn _app_senders_login_maps(apps: &[(String, HashMap<String, Value>)]) -> String {
+    let mut content = "# This file is regenerated automatically.\n# Please DO NOT edit manually ... changes will be overwritten!".to_string();
+    content.push('\n');
+
+    let senders: Vec<String> = apps
+        .iter()
+        .filter_map(|(app, settings)| {
+            let setting = |key: &str| settings.get(key).and_then(Value::as_str);
+            let domain = setting("domain")?;
+            setting("mail_pwd")?;
+            let mail_user = setting("mail_user").unwrap_or(app);
+            let mail_domain = setting("mail_domain").unwrap_or(domain);
+            Some(format!("{mail_user}@{mail_domain} {app}"))
+        })
+        .collect();
+    content.push_str(&senders.join("\n"));
+    content
+}
+
+/// Writes the relay password to the pending `sasl_passwd`. The file is created with mode 0600 before
+/// writing the password, so that other users never see it. regen-conf installs it with this mode,
+/// and post makes it owned by root.
+///
+/// Errors when:
+/// - writing the file or changing its permissions failed
+fn _write_sasl_passwd(sasl_passwd: &StrPath, relay: &SmtpRelay) -> Result<(), Error> {
+    sasl_passwd
+        .write_private(relay.sasl_passwd())
+        .context(FileSnafu)
+}
+
+/// Runs `postmap` to build the database of a lookup table.
+///
+/// Errors when:
+/// - postmap could not be run, or failed
+fn _postmap(args: &[&str]) -> Result<(), Error> {
+    let res = cmd("postmap", args).context(ConfRegenPostfixPostmapRunSnafu)?;
+    ensure!(
+        res.status.success(),
+        ConfRegenPostfixPostmapSnafu {
+            stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+        }
+    );
+    Ok(())
+}
+
+/// The SMTP relay which all outgoing emails go through, from the `email.smtp` settings.
+#[derive(Serialize)]
+struct SmtpRelay {
+    host: String,
+    port: String,
+    user: String,
+    /// Only written to sasl_passwd, and never given to the templates
+    #[serde(skip)]
+    password: String,
+}
+
+impl SmtpRelay {
+    fn sasl_passwd(&self) -> String {
+        format!(
+            "[{}]:{} {}:{}\n",
+            self.host, self.port, self.user, self.password
+        )
+    }
+}
+
+/// The variables of the postfix templates.
+#[derive(Serialize)]
+struct PostfixConf {
+    main_domain: String,
+    domains: Vec<String>,
+    /// `intermediate` also accepts TLS 1.2 for the incoming emails, which `modern` refuses
+    compatibility: String,
+    /// Whether IPv6 is allowed in the settings, and available on the system
+    ipv6: bool,
+    relay: Option<SmtpRelay>,
+}
+
+impl PostfixConf {
+    /// Errors when:
+    /// - reading the settings failed
+    /// - reading the domains failed
+    fn from_settings() -> Result<Self, Error> {
+        let mut settings = SettingsConfigPanel::new()
+            .context(ConfigPanelSnafu)
+            .context(ConfRegenPostfixPreSettingsSnafu)?;
+        let mut get_string = |key: &str| {
+            settings
+                .get_string(key)
+                .context(ConfigPanelSnafu)
+                .context(ConfRegenPostfixPreSettingsSnafu)
+        };
+
+        let compatibility = get_string("security.postfix.postfix_compatibility")?;
+        let relay_host = get_string("email.smtp.smtp_relay_host")?;
+        let relay = if relay_host.is_empty() {
+            None
+        } else {
+            Some(SmtpRelay {
+                host: relay_host,
+                port: get_string("email.smtp.smtp_relay_port")?,
+                user: get_string("email.smtp.smtp_relay_user")?,
+                password: get_string("email.smtp.smtp_relay_password")?,
+            })
+        };
+
+        let allow_ipv6 = settings
+            .get_bool("email.smtp.smtp_allow_ipv6")
+            .context(ConfigPanelSnafu)
+            .context(ConfRegenPostfixPreSettingsSnafu)?;
+
+        Ok(Self {
+            main_domain: YunohostDomain::main().context(ConfRegenPostfixPreDomainsSnafu)?,
+            domains: YunohostDomain::list(false).context(ConfRegenPostfixPreDomainsSnafu)?,
+            compatibility,
+            ipv6: allow_ipv6 && ipv6_available(),
+            relay,
+        })
+    }
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<PostfixHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/postfix"));
+        let mut conf = PostfixConf {
+            main_domain: "example.com".to_string(),
+            domains: vec!["example.com".to_string(), "sub.example.com".to_string()],
+            compatibility: "intermediate".to_string(),
+            ipv6: true,
+            relay: None,
+        };
+        for name in TEMPLATES {
+            insta::assert_snapshot!(
+                name,
+                templates.render(&format!("{name}.j2"), &conf).unwrap()
+            );
+        }
+
+        conf.compatibility = "modern".to_string();
+        conf.ipv6 = false;
+        conf.relay = Some(SmtpRelay {
+            host: "smtp.relay.example".to_string(),
+            port: "587".to_string(),
+            user: "user".to_string(),
+            password: "secret".to_string(),
+        });
+        let main_cf = templates.render("main.cf.j2", &conf).unwrap();
+        assert!(!main_cf.contains("secret"));
+        insta::assert_snapshot!("main.cf.relay", main_cf);
+    }
+
+    #[test]
+    fn app_senders_login_maps() {
+        let settings = |yaml: &str| serde_yaml_ng::from_str(yaml).unwrap();
+        let apps = vec![
+            (
+                "nextcloud".to_string(),
+                settings("domain: cloud.example.com\nmail_pwd: secret"),
+            ),
+            (
+                "wordpress".to_string(),
+                settings("domain: blog.example.com\nmail_pwd: secret\nmail_user: blog\nmail_domain: example.com"),
+            ),
+            ("nomail".to_string(), settings("domain: example.com")),
+        ];
+        assert_eq!(
+            "# This file is regenerated automatically.\n# Please DO NOT edit manually ... changes will be overwritten!\nnextcloud@cloud.example.com nextcloud\nblog@example.com wordpress",
+            _app_senders_login_maps(&apps)
+        );
+        assert!(_app_senders_login_maps(&[]).ends_with("overwritten!\n"));
+    }
+
+    #[test]
+    fn sasl_passwd_install() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let relay = SmtpRelay {
+            host: "smtp.relay.example".to_string(),
+            port: "587".to_string(),
+            user: "user".to_string(),
+            password: "secret".to_string(),
+        };
+        let pending = path(dir.join("pending_sasl_passwd"));
+        _write_sasl_passwd(&pending, &relay).unwrap();
+        assert_eq!(0o600, pending.mode_get().unwrap() & 0o777);
+        assert_eq!(relay.sasl_passwd(), pending.read().unwrap());
+
+        // A new file, and an existing world-readable file, both get the mode of the pending file
+        let system = path(dir.join("sasl_passwd"));
+        system.install_atomic(&pending).unwrap();
+        assert_eq!(0o600, system.mode_get().unwrap() & 0o777);
+
+        system.chmod(0o644).unwrap();
+        system.install_atomic(&pending).unwrap();
+        assert_eq!(0o600, system.mode_get().unwrap() & 0o777);
+        assert_eq!(relay.sasl_passwd(), system.read().unwrap());
+    }
+}
@@ -0,0 +1,199 @@
+use camino::Utf8PathBuf;
+use log::info;
+use serde::Serialize;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{
+        domain::*, file::*, group::*, process::*, regenconf::hook::*, service::*, settings::*,
+        template::*,
+    },
+};
+
+const DOVECOT_DIR: &str = "/etc/dovecot";
+const GLOBAL_SCRIPT_DIR: &str = "/etc/dovecot/global_script";
+const MAIL_DIR: &str = "/var/mail";
+/// The system user owning all the mailboxes.
+const VMAIL_USER: &str = "vmail";
+const VMAIL_UID: u32 = 500;
+/// The group of [`VMAIL_USER`], which exists on every Debian system.
+const MAIL_GROUP: &str = "mail";
+/// The templates rendered with [`DovecotConf`], and their path in [`DOVECOT_DIR`].
+const TEMPLATES: [(&str, &str); 5] = [
+    ("dovecot.conf.j2", "dovecot.conf"),
+    // Users are looked up in LDAP, and their quota is set from their `mailuserquota` attribute
+    ("dovecot-ldap.conf.j2", "dovecot-ldap.conf"),
+    // Moves the emails flagged by rspamd to the Junk folder
+    ("dovecot.sieve.j2", "global_script/dovecot.sieve"),
+    ("pre-ext.conf.j2", "yunohost.d/pre-ext.conf"),
+    ("post-ext.conf.j2", "yunohost.d/post-ext.conf"),
+];
+
+struct DovecotHook;
+
+impl ConfRegenHook for DovecotHook {
+    fn init() -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        let dovecot_dir = pending_dir.join(DOVECOT_DIR.trim_start_matches('/'));
+        let conf = DovecotConf::from_settings()?;
+
+        let templates = Templates::new("dovecot");
+        for (template, name) in TEMPLATES {
+            templates.render_to(template, &conf, &dovecot_dir.join(name))?;
+        }
+
+        Ok(())
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        for dir in ["pre-ext.d", "post-ext.d"] {
+            path(format!("{DOVECOT_DIR}/yunohost.d/{dir}"))
+                .mkdir_p()
+                .context(FileSnafu)?;
+        }
+
+        _ensure_vmail_user()?;
+
+        // Fix permissions
+        let global_script = path(GLOBAL_SCRIPT_DIR);
+        global_script
+            .chown_recurse(VMAIL_USER, MAIL_GROUP)
+            .and_then(|_| global_script.chmod(0o770))
+            .and_then(|_| path(MAIL_DIR).chown_and_mode(0o1775, "root", Some(MAIL_GROUP)))
+            .context(FileSnafu)?;
+
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        // Compile the sieve script
+        let sieve = format!("{GLOBAL_SCRIPT_DIR}/dovecot.sieve");
+        if conf_changed(regen_conf_files.as_deref(), &sieve) {
+            let res = cmd("sievec", [&sieve]).context(ConfRegenDovecotPostSievecRunSnafu)?;
+            ensure!(
+                res.status.success(),
+                ConfRegenDovecotPostSievecSnafu {
+                    stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+                }
+            );
+            global_script
+                .chown_recurse(VMAIL_USER, MAIL_GROUP)
+                .context(FileSnafu)?;
+        }
+
+        info!("Restarting dovecot");
+        ensure!(
+            SystemCtl::restart("dovecot", &[]),
+            ConfRegenDovecotPostRestartSnafu
+        );
+
+        Ok(())
+    }
+}
+
+/// Creates the [`VMAIL_USER`] system user, if it doesn't exist yet.
+///
+/// Errors when:
+/// - reading /etc/passwd failed
+/// - creating the user failed
+fn _ensure_vmail_user() -> Result<(), Error> {
+    YunohostGroup::ensure_exists(MAIL_GROUP)?;
+
+    let vmail_exists = path("/etc/passwd")
+        .read_lines()
+        .context(FileSnafu)?
+        .iter()
+        .any(|line| line.starts_with(&format!("{VMAIL_USER}:")));
+    if vmail_exists {
+        return Ok(());
+    }
+
+    info!("Creating the {VMAIL_USER} user");
+    path("/var/vmail").mkdir_p().context(FileSnafu)?;
+    let res = cmd(
+        "adduser",
+        [
+            "--system",
+            "--ingroup",
+            MAIL_GROUP,
+            "--uid",
+            &VMAIL_UID.to_string(),
+            "--home",
+            "/var/vmail",
+            "--no-create-home",
+            VMAIL_USER,
+        ],
+    )
+    .context(ConfRegenDovecotPostVmailRunSnafu)?;
+    ensure!(
+        res.status.success(),
+        ConfRegenDovecotPostVmailSnafu {
+            stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+        }
+    );
+
+    Ok(())
+}
+
+/// The variables of the `dovecot.conf` template.
+#[derive(Serialize)]
+struct DovecotConf {
+    main_domain: String,
+    domains: Vec<String>,
+    pop3_enabled: bool,
+    ipv6_enabled: bool,
+}
+
+impl DovecotConf {
+    /// Errors when:
+    /// - reading the settings failed
+    /// - reading the domains failed
+    fn from_settings() -> Result<Self, Error> {
+        let pop3_enabled = SettingsConfigPanel::new()
+            .and_then(|mut settings| settings.get_bool("email.pop3.pop3_enabled"))
+            .context(ConfigPanelSnafu)
+            .context(ConfRegenDovecotPreSettingsSnafu)?;
+
+        Ok(Self {
+            main_domain: YunohostDomain::main().context(ConfRegenDovecotPreDomainsSnafu)?,
+            domains: YunohostDomain::list(false).context(ConfRegenDovecotPreDomainsSnafu)?,
+            pop3_enabled,
+            ipv6_enabled: ipv6_available(),
+        })
+    }
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<DovecotHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/dovecot"));
+        let mut conf = DovecotConf {
+            main_domain: "example.com".to_string(),
+            domains: vec!["example.com".to_string(), "sub.example.com".to_string()],
+            pop3_enabled: true,
+            ipv6_enabled: true,
+        };
+        for (template, name) in TEMPLATES {
+            insta::assert_snapshot!(name, templates.render(template, &conf).unwrap());
+        }
+
+        conf.domains.truncate(1);
+        conf.pop3_enabled = false;
+        conf.ipv6_enabled = false;
+        insta::assert_snapshot!(
+            "dovecot.conf.single_domain",
+            templates.render("dovecot.conf.j2", &conf).unwrap()
+        );
+    }
+}
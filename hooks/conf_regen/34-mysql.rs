@@ -0,0 +1,135 @@
+use camino::Utf8PathBuf;
+use log::info;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{
+        apt::is_installed, credentials::Password, file::*, process::*, regenconf::hook::*,
+        service::*,
+    },
+};
+
+const MY_CNF: &str = "/etc/mysql/my.cnf";
+/// The MySQL root password, for the apps which don't log in through the unix socket.
+const ROOT_PASSWORD_FILE: &str = "/etc/yunohost/mysql";
+const ROOT_PASSWORD_LENGTH: usize = 32;
+const MARIADB_PACKAGE: &str = "mariadb-server";
+/// The alias of the mariadb service, which some apps still use.
+const MYSQL_SERVICE_ALIAS: &str = "/etc/systemd/system/mysql.service";
+
+struct MysqlHook;
+
+impl ConfRegenHook for MysqlHook {
+    fn init() -> Result<(), Error> {
+        if !is_installed(MARIADB_PACKAGE) {
+            info!("MariaDB is not installed, skipping");
+            return Ok(());
+        }
+
+        _ensure_root_password()
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        if !is_installed(MARIADB_PACKAGE) {
+            return Ok(());
+        }
+
+        install_conf(
+            &Utf8PathBuf::from(format!("{YUNOHOST_CONF_DIR}/mysql/my.cnf")),
+            &pending_dir.join(MY_CNF.trim_start_matches('/')),
+        )
+        .context(FileSnafu)
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        if !is_installed(MARIADB_PACKAGE) {
+            info!("MariaDB is not installed, skipping");
+            return Ok(());
+        }
+
+        // MariaDB may have been installed after Yunohost, by an app
+        _ensure_root_password()?;
+
+        // In some weird cases the mysql alias is missing, and re-enabling mariadb creates it again
+        // c.f. https://forum.yunohost.org/t/mysql-ne-fonctionne-pas/11661
+        if !path(MYSQL_SERVICE_ALIAS).exists() {
+            SystemCtl::stop("mysql", &["--quiet"]);
+            SystemCtl::disable("mysql", &["--quiet"]);
+            SystemCtl::disable("mariadb", &["--quiet"]);
+            SystemCtl::enable("mariadb", &["--quiet"]);
+            _ensure_active()?;
+        }
+
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        info!("Restarting mariadb");
+        ensure!(
+            SystemCtl::restart("mariadb", &[]),
+            ConfRegenMysqlPostRestartSnafu
+        );
+
+        Ok(())
+    }
+}
+
+/// Generates the MySQL root password on first run, and stores it in [`ROOT_PASSWORD_FILE`]. Root
+/// can still log in without password through the unix socket, like on a fresh Debian install.
+///
+/// The password is written to stdin of mariadb, so that it's never visible in the process list
+/// or in the error messages.
+///
+/// Errors when:
+/// - mariadb could not be started
+/// - the password could not be generated or saved
+/// - the password could not be set in MariaDB
+fn _ensure_root_password() -> Result<(), Error> {
+    let password_file = path(ROOT_PASSWORD_FILE);
+    if password_file.exists() {
+        return Ok(());
+    }
+
+    _ensure_active()?;
+
+    info!("Generating the MySQL root password");
+    let password = Password::random(ROOT_PASSWORD_LENGTH)?;
+    password_file
+        .write_private(password.as_str())
+        .context(FileSnafu)?;
+
+    let sql = format!(
+        "ALTER USER 'root'@'localhost' IDENTIFIED VIA unix_socket OR mysql_native_password USING PASSWORD('{}');\nFLUSH PRIVILEGES;\n",
+        password.as_str()
+    );
+    let res = cmd_stdin("mariadb", ["--user=root"], sql.as_bytes());
+    if !res.as_ref().is_ok_and(|res| res.status.success()) {
+        // The password was not set, so generate a new one on the next run
+        password_file.file_remove().context(FileSnafu)?;
+    }
+
+    let res = res.context(ConfRegenMysqlPasswordRunSnafu)?;
+    ensure!(
+        res.status.success(),
+        ConfRegenMysqlPasswordSnafu {
+            // MariaDB may quote the failing query, which contains the password
+            stderr: String::from_utf8_lossy(&res.stderr).replace(password.as_str(), "<redacted>"),
+        }
+    );
+
+    Ok(())
+}
+
+fn _ensure_active() -> Result<(), Error> {
+    if !SystemCtl::is_active("mariadb") {
+        info!("Starting mariadb");
+        ensure!(SystemCtl::start("mariadb", &[]), ConfRegenMysqlStartSnafu);
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<MysqlHook>()
+}
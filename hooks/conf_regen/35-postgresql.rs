@@ -0,0 +1,175 @@
+use camino::Utf8PathBuf;
+use log::info;
+use regex::Regex;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{
+        apt::is_installed, credentials::Password, file::*, process::*, regenconf::hook::*,
+        service::*,
+    },
+};
+
+/// The password of the postgres superuser, for the apps which don't log in as the postgres user.
+const ROOT_PASSWORD_FILE: &str = "/etc/yunohost/psql";
+const ROOT_PASSWORD_LENGTH: usize = 32;
+const POSTGRESQL_PACKAGE: &str = "postgresql";
+/// The client authentication of every installed PostgreSQL version.
+const PG_HBA_GLOB: &str = "/etc/postgresql/*/main/pg_hba.conf";
+
+struct PostgresqlHook;
+
+impl ConfRegenHook for PostgresqlHook {
+    fn init() -> Result<(), Error> {
+        if !is_installed(POSTGRESQL_PACKAGE) {
+            info!("PostgreSQL is not installed, skipping");
+            return Ok(());
+        }
+
+        _ensure_root_password()
+    }
+
+    fn pre(_pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        // pg_hba.conf is edited in place by post, like Python, because its path depends on the
+        // installed PostgreSQL version
+        Ok(())
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        if !is_installed(POSTGRESQL_PACKAGE) {
+            info!("PostgreSQL is not installed, skipping");
+            return Ok(());
+        }
+
+        // PostgreSQL may have been installed after Yunohost, by an app
+        _ensure_root_password()?;
+
+        // The app users have no login shell, so they can't use peer authentication, and log in
+        // with their password instead
+        let mut pg_hba_changed = false;
+        for pg_hba in glob(PG_HBA_GLOB).context(FileSnafu)? {
+            let pg_hba = path(pg_hba);
+            let content = pg_hba.read().context(FileSnafu)?;
+            let md5 = _local_auth_md5(&content);
+            if md5 != content {
+                info!("Using md5 authentication for the local users in {pg_hba}");
+                pg_hba.write(md5).context(FileSnafu)?;
+                pg_hba_changed = true;
+            }
+        }
+
+        if regen_conf_files.is_none() && !pg_hba_changed {
+            return Ok(());
+        }
+
+        info!("Reloading postgresql");
+        ensure!(
+            SystemCtl::reload("postgresql", &[]),
+            ConfRegenPostgresqlPostReloadSnafu
+        );
+
+        Ok(())
+    }
+}
+
+/// Generates the password of the postgres superuser on first run, and stores it in
+/// [`ROOT_PASSWORD_FILE`].
+///
+/// The password is written to stdin of psql, so that it's never visible in the process list
+/// or in the error messages.
+///
+/// Errors when:
+/// - postgresql could not be started
+/// - the password could not be generated or saved
+/// - the password could not be set in PostgreSQL
+fn _ensure_root_password() -> Result<(), Error> {
+    let password_file = path(ROOT_PASSWORD_FILE);
+    // Like Python, an empty password file is generated again
+    if password_file.is_file() && !password_file.read().context(FileSnafu)?.trim().is_empty() {
+        return Ok(());
+    }
+
+    SystemCtl::enable("postgresql", &["--quiet"]);
+    if !SystemCtl::is_active("postgresql") {
+        info!("Starting postgresql");
+        ensure!(
+            SystemCtl::start("postgresql", &[]),
+            ConfRegenPostgresqlStartSnafu
+        );
+    }
+
+    info!("Generating the PostgreSQL root password");
+    let password = Password::random(ROOT_PASSWORD_LENGTH)?;
+    password_file
+        .write_private(password.as_str())
+        .context(FileSnafu)?;
+
+    let sql = format!(
+        "ALTER USER postgres WITH PASSWORD '{}';\n",
+        password.as_str()
+    );
+    // Without ON_ERROR_STOP, psql exits successfully even when the query failed
+    let res = cmd_stdin(
+        "sudo",
+        [
+            "--login",
+            "--user=postgres",
+            "psql",
+            "--quiet",
+            "--set=ON_ERROR_STOP=1",
+            "postgres",
+        ],
+        sql.as_bytes(),
+    );
+    if !res.as_ref().is_ok_and(|res| res.status.success()) {
+        // The password was not set, so generate a new one on the next run
+        password_file.file_remove().context(FileSnafu)?;
+    }
+
+    let res = res.context(ConfRegenPostgresqlPasswordRunSnafu)?;
+    ensure!(
+        res.status.success(),
+        ConfRegenPostgresqlPasswordSnafu {
+            // psql quotes the failing query, which contains the password
+            stderr: String::from_utf8_lossy(&res.stderr).replace(password.as_str(), "<redacted>"),
+        }
+    );
+
+    Ok(())
+}
+
+/// Replaces the peer authentication of all the local users with md5 in the content of a pg_hba.conf,
+/// like the `ynh_replace_string` of Python.
+fn _local_auth_md5(pg_hba: &str) -> String {
+    // UNWRAP NOTE: The regex is valid
+    let peer = Regex::new(r"local(\s*)all(\s*)all(\s*)peer").unwrap();
+    peer.replace_all(pg_hba, "local${1}all${2}all${3}md5")
+        .to_string()
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<PostgresqlHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_auth_md5() {
+        let pg_hba = "local   all             postgres                                peer\n\
+                      local   all             all                                     peer\n\
+                      host    all             all             127.0.0.1/32            scram-sha-256\n";
+        assert_eq!(
+            "local   all             postgres                                peer\n\
+             local   all             all                                     md5\n\
+             host    all             all             127.0.0.1/32            scram-sha-256\n",
+            _local_auth_md5(pg_hba)
+        );
+        assert_eq!(
+            _local_auth_md5(pg_hba),
+            _local_auth_md5(&_local_auth_md5(pg_hba))
+        );
+    }
+}
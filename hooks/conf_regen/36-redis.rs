@@ -0,0 +1,56 @@
+use camino::Utf8PathBuf;
+use log::info;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{apt::is_installed, file::*, regenconf::hook::*, service::*},
+};
+
+const REDIS_LOG_DIR: &str = "/var/log/redis";
+const REDIS_PACKAGE: &str = "redis-server";
+
+struct RedisHook;
+
+impl ConfRegenHook for RedisHook {
+    fn init() -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre(_pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        // The configuration shipped by Debian is used as is
+        Ok(())
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        if !is_installed(REDIS_PACKAGE) {
+            info!("Redis is not installed, skipping");
+            return Ok(());
+        }
+
+        // In some weird cases the log directory has wrong permissions, and redis can't start
+        let log_dir = path(REDIS_LOG_DIR);
+        if log_dir.is_dir() {
+            log_dir
+                .chown_recurse("redis", "adm")
+                .and_then(|_| log_dir.chmod(0o750))
+                .context(FileSnafu)?;
+        }
+
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        info!("Restarting redis-server");
+        ensure!(
+            SystemCtl::restart("redis-server", &[]),
+            ConfRegenRedisPostRestartSnafu
+        );
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<RedisHook>()
+}
@@ -0,0 +1,189 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{info, warn};
+use serde::Serialize;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{domain::*, file::*, process::*, regenconf::hook::*, service::*, template::*},
+};
+
+/// The domains announced by yunomdns. 01-yunohost leaves its ownership alone, because it belongs
+/// to [`MDNS_USER`].
+const MDNS_YML: &str = "/etc/yunohost/mdns.yml";
+/// Extra names to announce, one per line, without the `.local` suffix.
+const MDNS_ALIASES: &str = "/etc/yunohost/mdns.aliases";
+const YUNOMDNS_SERVICE: &str = "/etc/systemd/system/yunomdns.service";
+const MDNS_USER: &str = "mdns";
+/// Announced even when there's no .local domain, so that the server can always be found.
+const DEFAULT_DOMAIN: &str = "yunohost.local";
+
+/// The variables of the `mdns.yml` template.
+#[derive(Serialize)]
+struct MdnsConf {
+    domains: Vec<String>,
+}
+
+struct MdnsHook;
+
+impl ConfRegenHook for MdnsHook {
+    fn init() -> Result<(), Error> {
+        _pre_regen(Utf8Path::new("/"))?;
+        Self::post(Some(YUNOMDNS_SERVICE.to_string()))?;
+        SystemCtl::enable("yunomdns", &["--quiet"]);
+        Ok(())
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        _pre_regen(&pending_dir)
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        _ensure_mdns_user()?;
+        path(MDNS_YML)
+            .chown(MDNS_USER, MDNS_USER)
+            .context(FileSnafu)?;
+
+        if conf_changed(regen_conf_files.as_deref(), YUNOMDNS_SERVICE) {
+            SystemCtl::daemon_reload();
+        }
+
+        // yunomdns replaces avahi
+        SystemCtl::disable("avahi-daemon.socket", &["--quiet", "--now"]);
+        SystemCtl::disable("avahi-daemon", &["--quiet", "--now"]);
+
+        // Enable the yunomdns service on systems which were using avahi
+        if path("/etc/avahi/avahi-daemon.conf").exists() && !SystemCtl::is_enabled("yunomdns") {
+            SystemCtl::enable("yunomdns", &["--now", "--quiet"]);
+        }
+
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        info!("Restarting yunomdns");
+        ensure!(
+            SystemCtl::restart("yunomdns", &[]),
+            ConfRegenMdnsPostRestartSnafu
+        );
+
+        Ok(())
+    }
+}
+
+/// Installs the yunomdns service and the list of domains to announce in `pending_dir`.
+///
+/// Errors when:
+/// - reading the domains or the aliases failed
+/// - writing the configuration failed
+fn _pre_regen(pending_dir: &Utf8Path) -> Result<(), Error> {
+    let pending = |system_path: &str| pending_dir.join(system_path.trim_start_matches('/'));
+
+    install_conf(
+        &Utf8PathBuf::from(format!("{YUNOHOST_CONF_DIR}/mdns/yunomdns.service")),
+        &pending(YUNOMDNS_SERVICE),
+    )
+    .context(FileSnafu)?;
+
+    let domains = YunohostDomain::list(false).context(ConfRegenMdnsPreDomainsSnafu)?;
+    let aliases = if path(MDNS_ALIASES).is_file() {
+        path(MDNS_ALIASES).read_lines().context(FileSnafu)?
+    } else {
+        vec![]
+    };
+
+    let conf = MdnsConf {
+        domains: _mdns_domains(&domains, &aliases),
+    };
+    Templates::new("mdns").render_to("mdns.yml.j2", &conf, &pending(MDNS_YML))
+}
+
+/// Lists the .local domains to announce, which can't have subdomains with mDNS.
+fn _mdns_domains(domains: &[String], aliases: &[String]) -> Vec<String> {
+    let mut mdns_domains = vec![];
+    if !domains.iter().any(|domain| domain == DEFAULT_DOMAIN) {
+        mdns_domains.push(DEFAULT_DOMAIN.to_string());
+    }
+
+    for domain in domains {
+        let Some(name) = domain.strip_suffix(".local") else {
+            continue;
+        };
+        if name.contains('.') {
+            warn!("Subdomain {domain} cannot be handled by Bonjour/Zeroconf/mDNS");
+            continue;
+        }
+        mdns_domains.push(domain.to_string());
+    }
+
+    for alias in aliases.iter().map(|alias| alias.trim()) {
+        if !alias.is_empty() {
+            mdns_domains.push(format!("{alias}.local"));
+        }
+    }
+
+    mdns_domains
+}
+
+/// Creates the [`MDNS_USER`] system user running yunomdns, if it doesn't exist yet.
+///
+/// Errors when:
+/// - creating the user failed
+fn _ensure_mdns_user() -> Result<(), Error> {
+    if cmd("getent", ["passwd", MDNS_USER]).is_ok_and(|res| res.status.success()) {
+        return Ok(());
+    }
+
+    info!("Creating the {MDNS_USER} user");
+    let res = cmd(
+        "useradd",
+        [
+            "--no-create-home",
+            "--shell",
+            "/usr/sbin/nologin",
+            "--system",
+            "--user-group",
+            MDNS_USER,
+        ],
+    )
+    .context(ConfRegenMdnsPostUserRunSnafu)?;
+    ensure!(
+        res.status.success(),
+        ConfRegenMdnsPostUserSnafu {
+            stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+        }
+    );
+
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<MdnsHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mdns_domains() {
+        let domains = ["example.com", "box.local", "sub.box.local"].map(String::from);
+        let aliases = ["nas", " "].map(String::from);
+        assert_eq!(
+            vec!["yunohost.local", "box.local", "nas.local"],
+            _mdns_domains(&domains, &aliases)
+        );
+
+        let domains = ["yunohost.local".to_string()];
+        assert_eq!(vec!["yunohost.local"], _mdns_domains(&domains, &[]));
+    }
+
+    #[test]
+    fn templates() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/mdns"));
+        let conf = MdnsConf {
+            domains: vec!["yunohost.local".to_string(), "box.local".to_string()],
+        };
+        insta::assert_snapshot!("mdns.yml", templates.render("mdns.yml.j2", &conf).unwrap());
+    }
+}
@@ -0,0 +1,211 @@
+use camino::Utf8PathBuf;
+use log::{debug, info};
+use serde::Serialize;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{domain::*, file::*, process::*, regenconf::hook::*, service::*, template::*},
+};
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+const DNSMASQ_D: &str = "/etc/dnsmasq.d";
+const RESOLV_DNSMASQ_CONF: &str = "/etc/resolv.dnsmasq.conf";
+const DHCLIENT_CONF: &str = "/etc/dhcp/dhclient.conf";
+/// Prevents dhclient from adding the search domain of the ISP to resolv.conf.
+const DHCLIENT_SUPERSEDE: &str = "supersede domain-name \"\";";
+/// The DNS servers which are likely to conflict with dnsmasq.
+const CONFLICTING_SERVICES: [&str; 2] = ["systemd-resolved", "bind9"];
+
+/// The variables of the `dnsmasq.conf` template.
+#[derive(Serialize)]
+struct DnsmasqConf {
+    interfaces: Vec<String>,
+}
+
+/// The variables of the `domain.tpl` template, which resolves `domain` to the public IPs of the
+/// server, from the local network.
+#[derive(Serialize)]
+struct DomainConf<'a> {
+    domain: &'a str,
+    ipv4: &'a str,
+    ipv6: Option<&'a str>,
+}
+
+struct DnsmasqHook;
+
+impl ConfRegenHook for DnsmasqHook {
+    fn init() -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        let conf_dir = Utf8PathBuf::from(format!("{YUNOHOST_CONF_DIR}/dnsmasq"));
+        let pending = |system_path: &str| pending_dir.join(system_path.trim_start_matches('/'));
+
+        install_conf(
+            &conf_dir.join("plain/etcdefault"),
+            &pending("/etc/default/dnsmasq"),
+        )
+        .context(FileSnafu)?;
+
+        // Only keep the resolvers. Unlike the Python version they are not shuffled, so that dnsmasq
+        // is not restarted at every regen-conf.
+        let resolvers: String = path(conf_dir.join("plain/resolv.dnsmasq.conf"))
+            .read_lines()
+            .context(FileSnafu)?
+            .into_iter()
+            .filter(|line| line.starts_with("nameserver"))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        path(pending(RESOLV_DNSMASQ_CONF))
+            .write(resolvers)
+            .context(FileSnafu)?;
+
+        let templates = Templates::new("dnsmasq");
+        templates.render_to(
+            "dnsmasq.conf.j2",
+            &DnsmasqConf {
+                interfaces: _interfaces()?,
+            },
+            &pending("/etc/dnsmasq.conf"),
+        )?;
+
+        let ipv4 = _public_ip("-4", "https://ip.yunohost.org")
+            .and_then(|ip| ip.parse::<Ipv4Addr>().ok())
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+        let ipv6 = _public_ip("-6", "https://ip6.yunohost.org")
+            .and_then(|ip| ip.parse::<Ipv6Addr>().ok())
+            .map(|ip| ip.to_string());
+
+        let dnsmasq_d = path(pending(DNSMASQ_D));
+        dnsmasq_d.mkdir_p().context(FileSnafu)?;
+
+        // .local domains are resolved by mDNS
+        let domains: Vec<String> = YunohostDomain::list(false)
+            .context(ConfRegenDnsmasqPreDomainsSnafu)?
+            .into_iter()
+            .filter(|domain| !domain.ends_with(".local"))
+            .collect();
+        for domain in &domains {
+            let conf = DomainConf {
+                domain,
+                ipv4: &ipv4,
+                ipv6: ipv6.as_deref(),
+            };
+            templates.render_to("domain.tpl.j2", &conf, &dnsmasq_d.join(domain))?;
+        }
+
+        // Like Python, the files of .local domains are never removed
+        remove_old_domain_confs(DNSMASQ_D, "", &pending_dir, |domain| {
+            domain.ends_with(".local") || domains.iter().any(|d| d == domain)
+        })
+        .context(FileSnafu)?;
+
+        Ok(())
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        // Cover the edge cases where the umask of root is like 027, and dnsmasq can't read this file
+        path(RESOLV_DNSMASQ_CONF).chmod(0o644).context(FileSnafu)?;
+
+        // The domain/search entries from dhclient are usually set by the ISP and only get in the way
+        let dhclient_conf = path(DHCLIENT_CONF);
+        if dhclient_conf.is_file() {
+            let mut content = dhclient_conf.read().context(FileSnafu)?;
+            if !content.lines().any(|line| line == DHCLIENT_SUPERSEDE) {
+                if !content.is_empty() && !content.ends_with('\n') {
+                    content.push('\n');
+                }
+                content.push_str(DHCLIENT_SUPERSEDE);
+                content.push('\n');
+                dhclient_conf.write(content).context(FileSnafu)?;
+            }
+        }
+
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        for service in CONFLICTING_SERVICES {
+            if SystemCtl::exists(service) {
+                info!("Disabling {service}, which conflicts with dnsmasq");
+                SystemCtl::disable(service, &["--quiet"]);
+                if SystemCtl::is_active(service) {
+                    SystemCtl::stop(service, &[]);
+                }
+            }
+        }
+
+        info!("Restarting dnsmasq");
+        ensure!(
+            SystemCtl::restart("dnsmasq", &[]),
+            ConfRegenDnsmasqPostRestartSnafu
+        );
+
+        Ok(())
+    }
+}
+
+/// Finds the public IP of the server with an IP `version` flag for curl (`-4` or `-6`), or `None`
+/// when it could not be found, for example because the server has no IPv6.
+fn _public_ip(version: &str, url: &str) -> Option<String> {
+    let res = cmd("curl", ["--max-time", "10", "-s", version, url]).ok()?;
+    if !res.status.success() {
+        debug!("Could not find the public IP of the server with {url}");
+        return None;
+    }
+    Some(String::from_utf8_lossy(&res.stdout).trim().to_string())
+}
+
+/// Lists the network interfaces of the server.
+///
+/// Errors when:
+/// - reading /sys/class/net failed
+fn _interfaces() -> Result<Vec<String>, Error> {
+    let mut interfaces: Vec<String> = glob("/sys/class/net/*")
+        .context(FileSnafu)?
+        .into_iter()
+        .filter_map(|interface| interface.file_name().map(String::from))
+        .collect();
+    interfaces.sort();
+    Ok(interfaces)
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<DnsmasqHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/dnsmasq"));
+        let conf = DnsmasqConf {
+            interfaces: vec!["eth0".to_string(), "lo".to_string()],
+        };
+        insta::assert_snapshot!(
+            "dnsmasq.conf",
+            templates.render("dnsmasq.conf.j2", &conf).unwrap()
+        );
+
+        let mut conf = DomainConf {
+            domain: "example.com",
+            ipv4: "203.0.113.1",
+            ipv6: None,
+        };
+        insta::assert_snapshot!(
+            "domain_ipv4",
+            templates.render("domain.tpl.j2", &conf).unwrap()
+        );
+        conf.ipv6 = Some("2001:db8::1");
+        insta::assert_snapshot!(
+            "domain_ipv6",
+            templates.render("domain.tpl.j2", &conf).unwrap()
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use log::info;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{regenconf::hook::*, service::*},
+};
+
+/// Users and groups are looked up in LDAP too, through nslcd.
+const NSSWITCH_CONF: &str = "/etc/nsswitch.conf";
+
+struct NsswitchHook;
+
+impl ConfRegenHook for NsswitchHook {
+    fn init() -> Result<(), Error> {
+        install_conf(&_conf(), Utf8Path::new(NSSWITCH_CONF)).context(FileSnafu)?;
+        _restart_unscd()
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        install_conf(
+            &_conf(),
+            &pending_dir.join(NSSWITCH_CONF.trim_start_matches('/')),
+        )
+        .context(FileSnafu)
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        _restart_unscd()
+    }
+}
+
+/// Restarts unscd, because the cached lookups may come from the previous configuration.
+///
+/// Errors when:
+/// - unscd could not be restarted
+fn _restart_unscd() -> Result<(), Error> {
+    info!("Restarting unscd");
+    ensure!(
+        SystemCtl::restart("unscd", &[]),
+        ConfRegenNsswitchRestartSnafu
+    );
+    Ok(())
+}
+
+fn _conf() -> Utf8PathBuf {
+    Utf8PathBuf::from(format!("{YUNOHOST_CONF_DIR}/nsswitch/nsswitch.conf"))
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<NsswitchHook>()
+}
@@ -0,0 +1,169 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{info, warn};
+use serde::Serialize;
+use snafu::prelude::*;
+
+use yunohost::{
+    error::*,
+    helpers::{file::*, process::*, regenconf::hook::*, service::*, settings::*, template::*},
+};
+
+const FAIL2BAN_DIR: &str = "/etc/fail2ban";
+const APPS_DIR: &str = "/etc/yunohost/apps";
+/// The jails shipped by Debian and Yunohost, which are not app jails.
+const SYSTEM_JAILS: [&str; 2] = ["defaults-debian.conf", "yunohost-jails.conf"];
+
+/// The variables of the `yunohost-jails.conf` template, which enables the jails of Yunohost. The jails
+/// of the installed apps are loaded by fail2ban from their own file in `jail.d`, and are only listed
+/// there so that fail2ban is reloaded when the list changes.
+#[derive(Serialize)]
+struct YunohostJails {
+    ssh_port: String,
+    app_jails: Vec<String>,
+}
+
+struct Fail2banHook;
+
+impl ConfRegenHook for Fail2banHook {
+    fn init() -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error> {
+        let conf_dir = Utf8PathBuf::from(format!("{YUNOHOST_CONF_DIR}/fail2ban"));
+        let fail2ban_dir = pending_dir.join(FAIL2BAN_DIR.trim_start_matches('/'));
+
+        install_conf(
+            &conf_dir.join("yunohost.conf"),
+            &fail2ban_dir.join("filter.d/yunohost.conf"),
+        )
+        .and_then(|_| {
+            install_conf(
+                &conf_dir.join("postfix-sasl.conf"),
+                &fail2ban_dir.join("filter.d/postfix-sasl.conf"),
+            )
+        })
+        .and_then(|_| install_conf(&conf_dir.join("jail.conf"), &fail2ban_dir.join("jail.conf")))
+        .context(FileSnafu)?;
+
+        let ssh_port = SettingsConfigPanel::new()
+            .and_then(|mut settings| settings.get_string("security.ssh.ssh_port"))
+            .context(ConfigPanelSnafu)
+            .context(ConfRegenFail2banPreSettingsSnafu)?;
+
+        let jails = YunohostJails {
+            ssh_port,
+            app_jails: _app_jails(Utf8Path::new(FAIL2BAN_DIR), Utf8Path::new(APPS_DIR))?,
+        };
+        Templates::new("fail2ban").render_to(
+            "yunohost-jails.conf.j2",
+            &jails,
+            &fail2ban_dir.join("jail.d/yunohost-jails.conf"),
+        )
+    }
+
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error> {
+        if regen_conf_files.is_none() {
+            return Ok(());
+        }
+
+        // Reload fail2ban only if the jails, including the jails of the apps, look good
+        let res = cmd("fail2ban-client", ["--test"]).context(ConfRegenFail2banPostTestRunSnafu)?;
+        ensure!(
+            res.status.success(),
+            ConfRegenFail2banPostTestSnafu {
+                stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+            }
+        );
+
+        info!("Reloading fail2ban");
+        ensure!(
+            SystemCtl::reload("fail2ban", &[]),
+            ConfRegenFail2banPostReloadSnafu
+        );
+
+        Ok(())
+    }
+}
+
+/// Lists the jails installed by the apps in `fail2ban_dir/jail.d`, named after the app, whose app is
+/// installed in `apps_dir`.
+///
+/// The jails of the apps which are not installed anymore are not managed by regen-conf, so like
+/// Python they are left alone, with a warning because their log files usually don't exist anymore,
+/// which prevents fail2ban from starting.
+///
+/// Errors when:
+/// - listing the jails failed
+fn _app_jails(fail2ban_dir: &Utf8Path, apps_dir: &Utf8Path) -> Result<Vec<String>, Error> {
+    let mut app_jails = vec![];
+
+    for jail in glob(&format!("{fail2ban_dir}/jail.d/*.conf")).context(FileSnafu)? {
+        // UNWRAP NOTE: Globbed files always have a file name
+        let file_name = jail.file_name().unwrap();
+        if SYSTEM_JAILS.contains(&file_name) {
+            continue;
+        }
+
+        let app = file_name.trim_end_matches(".conf");
+        if apps_dir.join(app).is_dir() {
+            app_jails.push(app.to_string());
+        } else {
+            warn!(
+                "The fail2ban jail {jail} belongs to the app {app}, which is not installed anymore"
+            );
+        }
+    }
+
+    app_jails.sort();
+    Ok(app_jails)
+}
+
+fn main() -> Result<(), Error> {
+    run_hook::<Fail2banHook>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_jails() {
+        let root = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(root.path()).unwrap();
+        let fail2ban_dir = root.join("fail2ban");
+        let apps_dir = root.join("apps");
+
+        path(fail2ban_dir.join("jail.d")).mkdir_p().unwrap();
+        path(apps_dir.join("nextcloud")).mkdir_p().unwrap();
+        for jail in ["defaults-debian.conf", "nextcloud.conf", "removed.conf"] {
+            path(fail2ban_dir.join("jail.d").join(jail))
+                .write("")
+                .unwrap();
+        }
+
+        assert_eq!(
+            vec!["nextcloud".to_string()],
+            _app_jails(&fail2ban_dir, &apps_dir).unwrap()
+        );
+    }
+
+    #[test]
+    fn templates() {
+        let templates = Templates::from_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/conf/fail2ban"));
+        let mut jails = YunohostJails {
+            ssh_port: "2222".to_string(),
+            app_jails: vec![],
+        };
+        insta::assert_snapshot!(
+            "yunohost-jails.conf",
+            templates.render("yunohost-jails.conf.j2", &jails).unwrap()
+        );
+
+        jails.app_jails = vec!["nextcloud".to_string(), "wordpress".to_string()];
+        insta::assert_snapshot!(
+            "yunohost-jails.conf.apps",
+            templates.render("yunohost-jails.conf.j2", &jails).unwrap()
+        );
+    }
+}
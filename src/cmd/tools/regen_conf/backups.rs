@@ -0,0 +1,80 @@
+use camino::Utf8PathBuf;
+use clap::{Parser, Subcommand};
+use snafu::prelude::*;
+
+use crate::{error::*, helpers::output, helpers::regenconf::*};
+
+#[derive(Clone, Debug, Parser)]
+pub struct RegenConfBackupsCommand {
+    #[command(subcommand)]
+    cmd: RegenConfBackupsSubCommand,
+}
+
+impl RegenConfBackupsCommand {
+    pub fn run(&self) -> Result<(), Error> {
+        match &self.cmd {
+            RegenConfBackupsSubCommand::List(cmd) => cmd.run(),
+            RegenConfBackupsSubCommand::Restore(cmd) => cmd.run(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum RegenConfBackupsSubCommand {
+    /// List the saved versions of the configuration files
+    #[command(name = "list")]
+    List(RegenConfBackupsListCommand),
+    /// Put back a saved version of a configuration file
+    #[command(name = "restore")]
+    Restore(RegenConfBackupsRestoreCommand),
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct RegenConfBackupsListCommand {
+    #[arg(long)]
+    json: bool,
+
+    /// Only list the files managed by this category
+    #[arg()]
+    category: Option<String>,
+}
+
+impl RegenConfBackupsListCommand {
+    pub fn run(&self) -> Result<(), Error> {
+        if self.json {
+            output::enable_json();
+        }
+
+        let res = list_backups(self.category.as_deref()).context(RegenConfSnafu)?;
+        output::exit_success(res);
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Parser)]
+pub struct RegenConfBackupsRestoreCommand {
+    #[arg(long)]
+    json: bool,
+
+    /// Timestamp of the backup to restore (eg. 20240101.120000), defaults to the latest
+    #[arg(long = "at")]
+    at: Option<String>,
+
+    /// Absolute path of the configuration file to restore
+    #[arg()]
+    file: Utf8PathBuf,
+}
+
+impl RegenConfBackupsRestoreCommand {
+    pub fn run(&self) -> Result<(), Error> {
+        if self.json {
+            output::enable_json();
+        }
+
+        let res = restore_backup(&self.file, self.at.as_deref()).context(RegenConfSnafu)?;
+        output::exit_success(res);
+
+        Ok(())
+    }
+}
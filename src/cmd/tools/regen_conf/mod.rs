@@ -0,0 +1,184 @@
+use clap::{Parser, Subcommand};
+use snafu::prelude::*;
+
+use std::collections::BTreeMap;
+
+use crate::{
+    error::*, helpers::file::*, helpers::output, helpers::regenconf::error::RegenConfError,
+    helpers::regenconf::*,
+};
+
+pub mod backups;
+pub mod status;
+
+#[derive(Clone, Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct RegenConfCommand {
+    #[command(subcommand)]
+    cmd: Option<RegenConfSubCommand>,
+
+    #[arg(short = 'p', long = "list-pending")]
+    list_pending: bool,
+
+    #[arg(short = 'd', long = "with-diff")]
+    with_diff: bool,
+
+    /// Number of context lines in diffs
+    #[arg(long = "diff-context", default_value_t = DIFF_CONTEXT)]
+    diff_context: usize,
+
+    /// Print the diffs as unified diffs, coloured in a terminal, instead of YAML/JSON (implies --with-diff)
+    #[arg(long = "color-diff", conflicts_with = "json")]
+    color_diff: bool,
+
+    /// Override all manual modifications in configuration files
+    #[arg(short = 'f', long = "force")]
+    force: bool,
+
+    /// Show what would be done without applying anything
+    #[arg(short = 'n', long = "dry-run")]
+    dry_run: bool,
+
+    /// Number of categories to generate in parallel, defaults to the number of CPUs
+    #[arg(short = 'j', long = "jobs", value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+
+    #[arg(long)]
+    json: bool,
+
+    #[arg()]
+    names: Vec<String>,
+}
+
+impl RegenConfCommand {
+    pub fn run(&self) -> Result<(), Error> {
+        match &self.cmd {
+            Some(RegenConfSubCommand::Backups(cmd)) => return cmd.run(),
+            Some(RegenConfSubCommand::Status(cmd)) => return cmd.run(),
+            None => {}
+        }
+
+        if self.json {
+            output::enable_json();
+        }
+
+        if path("/etc/yunohost/settings.json").exists()
+            && !path("/etc/yunohost/settings.yml").exists()
+        {
+            return Err(RegenConfError::SettingsNotMigrated).context(RegenConfSnafu);
+        }
+
+        if self.list_pending {
+            self.run_list_pending()?;
+        } else if self.dry_run {
+            let res = regen_conf_dry_run(
+                &self.names,
+                self.diff_context(),
+                self.force,
+                self.jobs.map(usize::from),
+            )
+            .context(RegenConfSnafu)?;
+            if self.color_diff {
+                for conf_files in res.values() {
+                    for (system_path, plan) in conf_files {
+                        print_diff(
+                            &system_path.to_absolute(),
+                            plan.diff_path.as_deref(),
+                            plan.diff.as_deref().unwrap_or_default(),
+                        );
+                    }
+                }
+                return Ok(());
+            }
+            output::exit_success(res);
+        } else {
+            let res = regen_conf(
+                &self.names,
+                self.diff_context(),
+                self.force,
+                self.jobs.map(usize::from),
+            )
+            .context(RegenConfSnafu)?;
+            if self.color_diff {
+                for category_result in res.values() {
+                    for (system_path, file_result) in category_result
+                        .applied
+                        .iter()
+                        .chain(&category_result.pending)
+                    {
+                        print_diff(
+                            &system_path.to_absolute(),
+                            file_result.diff_path.as_deref(),
+                            file_result.diff.as_deref().unwrap_or_default(),
+                        );
+                    }
+                }
+                return Ok(());
+            }
+            output::exit_success(res);
+        }
+
+        Ok(())
+    }
+
+    /// The number of context lines, if diffs were requested.
+    fn diff_context(&self) -> Option<usize> {
+        (self.with_diff || self.color_diff).then_some(self.diff_context)
+    }
+
+    pub fn run_list_pending(&self) -> Result<(), Error> {
+        let pending = _get_pending_conf(&self.names).context(RegenConfSnafu)?;
+
+        if let Some(context) = self.diff_context().filter(|_| self.color_diff) {
+            for conf_files in pending.into_values() {
+                for (system_path, pending_path) in conf_files {
+                    let pending_diff = pending_path
+                        .into_pending_diff(context)
+                        .context(RegenConfSnafu)?;
+                    let system_path = system_path.to_absolute();
+                    print_diff(
+                        &system_path,
+                        pending_diff
+                            .pending_conf
+                            .content_path(&system_path)
+                            .as_deref(),
+                        &pending_diff.diff,
+                    );
+                }
+            }
+        } else if !self.with_diff {
+            output::exit_success(pending);
+        } else {
+            let mut pending_diff: BTreeMap<String, BTreeMap<RelativeConfFile, PendingConfDiff>> =
+                BTreeMap::new();
+
+            for (category, conf_files) in pending {
+                let mut category_files: BTreeMap<RelativeConfFile, PendingConfDiff> =
+                    BTreeMap::new();
+                for (system_path, pending_path) in conf_files {
+                    category_files.insert(
+                        system_path,
+                        pending_path
+                            .into_pending_diff(self.diff_context)
+                            .context(RegenConfSnafu)?,
+                    );
+                }
+
+                pending_diff.insert(category, category_files);
+            }
+            output::exit_success(pending_diff);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum RegenConfSubCommand {
+    /// Browse and restore the configuration files overwritten by regen-conf
+    #[command(name = "backups")]
+    Backups(backups::RegenConfBackupsCommand),
+    /// Compare the configuration files on the system to their hashes saved by the last regen-conf
+    #[command(name = "status")]
+    Status(status::RegenConfStatusCommand),
+}
@@ -0,0 +1,27 @@
+use clap::Parser;
+use snafu::prelude::*;
+
+use crate::{error::*, helpers::output, helpers::regenconf::*};
+
+#[derive(Clone, Debug, Parser)]
+pub struct RegenConfStatusCommand {
+    #[arg(long)]
+    json: bool,
+
+    /// Only report the files managed by this category
+    #[arg()]
+    category: Option<String>,
+}
+
+impl RegenConfStatusCommand {
+    pub fn run(&self) -> Result<(), Error> {
+        if self.json {
+            output::enable_json();
+        }
+
+        let res = regen_conf_status(self.category.as_deref()).context(RegenConfSnafu)?;
+        output::exit_success(res);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use clap::Parser;
+use serde::Serialize;
+use snafu::prelude::*;
+
+use crate::{
+    error::*,
+    helpers::credentials::{Password, Username},
+    helpers::output,
+    helpers::user::{UserAttr, YunohostUser},
+};
+
+#[derive(Clone, Debug, Parser)]
+pub struct UserCreateCommand {
+    #[arg(long)]
+    json: bool,
+
+    /// The full name of the user, like "Camille Dupont"
+    #[arg(short = 'F', long)]
+    fullname: String,
+
+    /// The domain of the email address of the user, defaults to the main domain
+    #[arg(short, long)]
+    domain: Option<String>,
+
+    #[arg(short, long)]
+    password: Password,
+
+    /// The size of the mailbox, like 500M, or 0 to not have a quota
+    #[arg(short = 'q', long, default_value = "0")]
+    mailbox_quota: String,
+
+    #[arg()]
+    username: Username,
+}
+
+impl UserCreateCommand {
+    pub fn run(&self) -> Result<(), Error> {
+        if self.json {
+            output::enable_json();
+        }
+
+        let user = YunohostUser::create(
+            &self.username,
+            &self.fullname,
+            self.domain.as_deref(),
+            &self.password,
+            &self.mailbox_quota,
+        )?;
+
+        let output = output::format(&UserCreated::try_from(user)?)?;
+        println!("{}", output);
+
+        Ok(())
+    }
+}
+
+/// The output of `yunohost user create`, like the Python version.
+#[derive(Clone, Debug, Serialize)]
+pub struct UserCreated {
+    pub fullname: String,
+    pub username: String,
+    pub mail: String,
+}
+
+impl TryFrom<YunohostUser> for UserCreated {
+    type Error = Error;
+
+    fn try_from(user: YunohostUser) -> Result<Self, Error> {
+        let missing = |attr: UserAttr| UserAttributeMissingSnafu {
+            user: &user.username,
+            attribute: attr.to_ldap_attr(),
+        };
+        Ok(Self {
+            fullname: user.fullname.context(missing(UserAttr::Fullname))?,
+            mail: user.mail.context(missing(UserAttr::Mail))?,
+            username: user.username,
+        })
+    }
+}
@@ -0,0 +1,19 @@
+use clap::Parser;
+
+use crate::{error::*, helpers::credentials::Username, helpers::user::YunohostUser};
+
+#[derive(Clone, Debug, Parser)]
+pub struct UserDeleteCommand {
+    /// Also remove the home folder and the emails of the user
+    #[arg(long)]
+    purge: bool,
+
+    #[arg()]
+    username: Username,
+}
+
+impl UserDeleteCommand {
+    pub fn run(&self) -> Result<(), Error> {
+        YunohostUser::delete(&self.username, self.purge)
+    }
+}
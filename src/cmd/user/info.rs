@@ -1,11 +1,12 @@
 use clap::Parser;
 use serde::Serialize;
+use snafu::prelude::*;
 
 use crate::{
     error::*,
     helpers::mail::*,
     helpers::output,
-    helpers::user::{UserQuery, YunohostUser},
+    helpers::user::{UserAttr, UserQuery, YunohostUser},
 };
 
 #[derive(Clone, Debug, Parser)]
@@ -57,8 +58,8 @@ pub struct DefaultSingle {
     pub mail_aliases: Vec<String>,
     #[serde(rename = "mail-forward")]
     pub mail_forward: Vec<String>,
-    #[serde(rename = "mailbox-quota")]
-    pub mailbox_quota: MailStorageUse,
+    #[serde(rename = "mailbox-quota", skip_serializing_if = "Option::is_none")]
+    pub mailbox_quota: Option<MailStorageUse>,
 }
 
 impl TryFrom<YunohostUser> for DefaultSingle {
@@ -66,12 +67,22 @@ impl TryFrom<YunohostUser> for DefaultSingle {
 
     fn try_from(user: YunohostUser) -> Result<Self, Error> {
         // let mailbox_quota = MailStorageUse::from_doveadm(&user.username, &user.mailbox_quota)?;
-        let mailbox_quota = MailStorageUse::from_disk(&user.username, &user.mailbox_quota)?;
+        // Like Python, which doesn't show the mailbox of users without a quota attribute
+        let mailbox_quota = user
+            .mailbox_quota
+            .as_deref()
+            .map(|quota| MailStorageUse::from_disk(&user.username, quota))
+            .transpose()?;
+        let missing = |attr: UserAttr| UserAttributeMissingSnafu {
+            user: &user.username,
+            attribute: attr.to_ldap_attr(),
+        };
         Ok(Self {
+            fullname: user.fullname.context(missing(UserAttr::Fullname))?,
+            mail: user.mail.context(missing(UserAttr::Mail))?,
+            // Like Python, users without a login shell can't log in
+            login_shell: user.login_shell.unwrap_or("/bin/false".to_string()),
             username: user.username,
-            fullname: user.fullname,
-            mail: user.mail,
-            login_shell: user.login_shell,
             mail_aliases: user.mail_aliases,
             mail_forward: user.mail_forward,
             mailbox_quota,
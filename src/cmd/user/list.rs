@@ -1,5 +1,7 @@
 use clap::Parser;
+use ldap3::SearchEntry;
 use serde::Serialize;
+use serde_json::{Map, Value};
 
 use std::collections::BTreeMap;
 
@@ -14,7 +16,8 @@ pub struct UserListCommand {
     #[arg(long)]
     json: bool,
 
-    #[arg(long)]
+    /// The fields to show for each user, defaults to username, fullname, mail and mailbox-quota
+    #[arg(long, num_args = 1..)]
     fields: Vec<UserAttr>,
 }
 
@@ -24,11 +27,22 @@ impl UserListCommand {
             output::enable_json();
         }
 
-        // Get the userlist from the LDAP DB
-        let users = YunohostUser::list(None)?;
+        let fields = if self.fields.is_empty() {
+            vec![
+                UserAttr::Username,
+                UserAttr::Fullname,
+                UserAttr::Mail,
+                UserAttr::MailboxQuota,
+            ]
+        } else {
+            self.fields.clone()
+        };
+
+        // Get the userlist from the LDAP DB, with only the requested attributes
+        let entries = YunohostUser::list_entries(&fields)?;
 
         // Extract the fields that interest us
-        let users = DefaultList::from(users);
+        let users = UserList::new(entries, &fields)?;
 
         // Format the output
         let output = output::format(&users)?;
@@ -38,40 +52,118 @@ impl UserListCommand {
     }
 }
 
+/// The output of `yunohost user list`, with the requested fields of each user in the requested
+/// order, like the Python version.
 #[derive(Clone, Debug, Serialize)]
-pub struct DefaultListInfo {
-    pub username: String,
-    pub fullname: String,
-    pub mail: String,
-    #[serde(rename = "mailbox-quota")]
-    pub mailbox_quota: String,
+pub struct UserList {
+    users: BTreeMap<String, Map<String, Value>>,
 }
 
-impl From<YunohostUser> for DefaultListInfo {
-    fn from(user: YunohostUser) -> Self {
-        Self {
-            username: user.username,
-            fullname: user.fullname,
-            mail: user.mail,
-            mailbox_quota: user.mailbox_quota,
+impl UserList {
+    /// Errors when:
+    /// - an entry has no `uid` attribute
+    pub fn new(entries: Vec<SearchEntry>, fields: &[UserAttr]) -> Result<Self, Error> {
+        let mut users = BTreeMap::new();
+        for entry in entries {
+            let username = YunohostUser::uid(&entry)?;
+            let mut user = Map::new();
+            for field in fields {
+                let values = entry
+                    .attrs
+                    .get(field.to_ldap_attr())
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                user.insert(field.to_string(), _display_field(field, values, &username));
+            }
+            users.insert(username, user);
         }
+
+        Ok(Self { users })
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
-pub struct DefaultList {
-    users: BTreeMap<String, DefaultListInfo>,
+/// Formats the LDAP values of a field like Python: a single value is shown as a string, and
+/// several or no values as a list, except for the fields which have a dedicated format.
+fn _display_field(field: &UserAttr, values: &[String], username: &str) -> Value {
+    match field {
+        UserAttr::Mail => _display_default(values.get(..1).unwrap_or_default()),
+        UserAttr::MailAlias => values.iter().skip(1).cloned().collect(),
+        // The first forward is the mailbox of the user itself
+        UserAttr::MailForward => values
+            .iter()
+            .filter(|forward| *forward != username)
+            .cloned()
+            .collect(),
+        // The groups every user is in are not shown, and the DNs are shortened to the group name
+        UserAttr::Groups => values
+            .iter()
+            .filter(|group| {
+                !group.starts_with("cn=all_users,")
+                    && !group.starts_with(&format!("cn={username},"))
+            })
+            .map(|group| {
+                let group = group.get(3..).unwrap_or_default();
+                group.split(',').next().unwrap_or_default().to_string()
+            })
+            .collect(),
+        // Like Python, which only tells whether the login shell is disabled
+        UserAttr::Shell => Value::Bool(
+            values
+                .first()
+                .is_some_and(|shell| shell.trim() == "/bin/false"),
+        ),
+        _ => _display_default(values),
+    }
 }
 
-impl From<Vec<YunohostUser>> for DefaultList {
-    fn from(users: Vec<YunohostUser>) -> Self {
-        let mut default_list: BTreeMap<String, DefaultListInfo> = BTreeMap::new();
-        for user in users {
-            default_list.insert(user.username.clone(), user.into());
-        }
+fn _display_default(values: &[String]) -> Value {
+    match values {
+        [value] => Value::String(value.clone()),
+        _ => values.iter().cloned().collect(),
+    }
+}
 
-        Self {
-            users: default_list,
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_list() {
+        let entry = SearchEntry {
+            dn: "uid=alice,ou=users,dc=yunohost,dc=org".to_string(),
+            attrs: hashmap! {
+                "uid".to_string() => vec!["alice".to_string()],
+                "cn".to_string() => vec!["Alice Liddell".to_string()],
+                "mail".to_string() => vec![
+                    "alice@example.com".to_string(),
+                    "a@example.com".to_string(),
+                ],
+                "maildrop".to_string() => vec![
+                    "alice".to_string(),
+                    "alice@example.net".to_string(),
+                ],
+                "memberOf".to_string() => vec![
+                    "cn=alice,ou=groups,dc=yunohost,dc=org".to_string(),
+                    "cn=all_users,ou=groups,dc=yunohost,dc=org".to_string(),
+                    "cn=admins,ou=groups,dc=yunohost,dc=org".to_string(),
+                ],
+                "loginShell".to_string() => vec!["/bin/bash".to_string()],
+            },
+            bin_attrs: hashmap! {},
+        };
+        let fields = [
+            UserAttr::Mail,
+            UserAttr::Username,
+            UserAttr::MailAlias,
+            UserAttr::MailForward,
+            UserAttr::Groups,
+            UserAttr::Shell,
+            UserAttr::HomePath,
+        ];
+
+        assert_eq!(
+            r#"{"users":{"alice":{"mail":"alice@example.com","username":"alice","mail-alias":["a@example.com"],"mail-forward":["alice@example.net"],"groups":["admins"],"shell":false,"home-path":[]}}}"#,
+            serde_json::to_string(&UserList::new(vec![entry], &fields).unwrap()).unwrap()
+        );
     }
 }
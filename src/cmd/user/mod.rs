@@ -2,8 +2,11 @@ use clap::{Parser, Subcommand};
 
 use crate::error::Error;
 
+pub mod create;
+pub mod delete;
 pub mod info;
 pub mod list;
+pub mod update;
 
 #[derive(Clone, Debug, Parser)]
 pub struct UserCommand {
@@ -14,16 +17,25 @@ pub struct UserCommand {
 impl UserCommand {
     pub fn run(&self) -> Result<(), Error> {
         match &self.cmd {
+            UserSubCommand::UserCreate(cmd) => cmd.run(),
+            UserSubCommand::UserDelete(cmd) => cmd.run(),
             UserSubCommand::UserInfo(cmd) => cmd.run(),
             UserSubCommand::UserList(cmd) => cmd.run(),
+            UserSubCommand::UserUpdate(cmd) => cmd.run(),
         }
     }
 }
 
 #[derive(Clone, Debug, Subcommand)]
 pub enum UserSubCommand {
+    #[command(name = "create")]
+    UserCreate(create::UserCreateCommand),
+    #[command(name = "delete")]
+    UserDelete(delete::UserDeleteCommand),
     #[command(name = "info")]
     UserInfo(info::UserInfoCommand),
     #[command(name = "list")]
     UserList(list::UserListCommand),
+    #[command(name = "update")]
+    UserUpdate(update::UserUpdateCommand),
 }
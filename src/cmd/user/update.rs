@@ -0,0 +1,77 @@
+use clap::Parser;
+
+use crate::{
+    cmd::user::info::DefaultSingle,
+    error::*,
+    helpers::credentials::{Password, Username},
+    helpers::output,
+    helpers::user::{UserUpdate, YunohostUser},
+};
+
+#[derive(Clone, Debug, Parser)]
+pub struct UserUpdateCommand {
+    #[arg(long)]
+    json: bool,
+
+    /// The new full name of the user, like "Camille Dupont"
+    #[arg(short = 'F', long)]
+    fullname: Option<String>,
+
+    /// The new main email address, which may be one of the aliases of the user
+    #[arg(short, long)]
+    mail: Option<String>,
+
+    #[arg(long, num_args = 1..)]
+    add_mailalias: Vec<String>,
+
+    #[arg(long, num_args = 1..)]
+    remove_mailalias: Vec<String>,
+
+    #[arg(long, num_args = 1..)]
+    add_mailforward: Vec<String>,
+
+    #[arg(long, num_args = 1..)]
+    remove_mailforward: Vec<String>,
+
+    /// The size of the mailbox, like 500M, or 0 to not have a quota
+    #[arg(short = 'q', long)]
+    mailbox_quota: Option<String>,
+
+    #[arg(short = 'p', long)]
+    change_password: Option<Password>,
+
+    /// The login shell of the user, one of /etc/shells
+    #[arg(short = 's', long = "loginShell")]
+    login_shell: Option<String>,
+
+    #[arg()]
+    username: Username,
+}
+
+impl UserUpdateCommand {
+    pub fn run(&self) -> Result<(), Error> {
+        if self.json {
+            output::enable_json();
+        }
+
+        let update = UserUpdate {
+            fullname: self.fullname.clone(),
+            mail: self.mail.clone(),
+            add_mailalias: self.add_mailalias.clone(),
+            remove_mailalias: self.remove_mailalias.clone(),
+            add_mailforward: self.add_mailforward.clone(),
+            remove_mailforward: self.remove_mailforward.clone(),
+            mailbox_quota: self.mailbox_quota.clone(),
+            change_password: self.change_password.clone(),
+            login_shell: self.login_shell.clone(),
+        };
+        YunohostUser::update(&self.username, &update)?;
+
+        // Like Python, which shows the user info after the update
+        let user = DefaultSingle::try_from(YunohostUser::get(self.username.as_str())?)?;
+        let output = output::format(&user)?;
+        println!("{}", output);
+
+        Ok(())
+    }
+}
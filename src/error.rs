@@ -10,9 +10,15 @@ pub enum Error {
         source: helpers::configpanel::error::ConfigPanelError,
     },
 
+    #[snafu(display("An error happened during regen-conf"))]
+    RegenConf {
+        source: helpers::regenconf::error::RegenConfError,
+    },
+
     #[snafu(display("An error happened when translating"))]
     I18N {
-        source: helpers::i18n::error::I18NError,
+        #[snafu(source(from(helpers::i18n::error::I18NError, Box::new)))]
+        source: Box<helpers::i18n::error::I18NError>,
     },
 
     #[snafu(display("A file error occurred"))]
@@ -50,6 +56,37 @@ pub enum Error {
     ))]
     UnsupportedDebianRelease { version: String },
 
+    // ===================
+    // src/helpers/domain.rs
+    // ===================
+
+    //     fn main (YunohostDomain::main)
+    #[snafu(display(
+        "YunohostDomain::main failed to read the main domain from /etc/yunohost/current_host"
+    ))]
+    DomainMainRead {
+        #[snafu(source(from(helpers::file::error::FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    //     fn feature_enabled (YunohostDomain::feature_enabled)
+    #[snafu(display(
+        "YunohostDomain::feature_enabled failed to read the settings of domain {domain}"
+    ))]
+    DomainSettingsRead {
+        domain: String,
+        #[snafu(source(from(helpers::file::error::FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // ===================
+    // src/helpers/hook.rs
+    // ===================
+
+    //     fn exec (Hook::exec)
+    #[snafu(display("Hook::exec cannot run Python hook {path}"))]
+    HookExecPython { path: String },
+
     // ===================
     // src/helpers/ldap.rs
     // ===================
@@ -79,16 +116,23 @@ pub enum Error {
     #[snafu(display("new_ldap failed to init connection to LDAP database {}", uri))]
     LdapInit {
         uri: String,
-        source: ldap3::result::LdapError,
+        #[snafu(source(from(ldap3::result::LdapError, Box::new)))]
+        source: Box<ldap3::result::LdapError>,
     },
 
     // TODO
     #[snafu(display("Failed to bind on the LDAP database"))]
-    LdapBind { source: ldap3::result::LdapError },
+    LdapBind {
+        #[snafu(source(from(ldap3::result::LdapError, Box::new)))]
+        source: Box<ldap3::result::LdapError>,
+    },
 
     // TODO
     #[snafu(display("Failed to search the LDAP database"))]
-    LdapSearch { source: ldap3::result::LdapError },
+    LdapSearch {
+        #[snafu(source(from(ldap3::result::LdapError, Box::new)))]
+        source: Box<ldap3::result::LdapError>,
+    },
 
     // TODO
     // #[snafu(display("No such user: {}", username.as_str()))]
@@ -101,6 +145,30 @@ pub enum Error {
     #[snafu(display("Failed to lookup permission {name}"))]
     LdapPermissionNotFound { name: String },
 
+    //     fn add (YunohostLDAP::add)
+    #[snafu(display("YunohostLDAP::add failed to add LDAP entry {dn}"))]
+    LdapAdd {
+        dn: String,
+        #[snafu(source(from(ldap3::result::LdapError, Box::new)))]
+        source: Box<ldap3::result::LdapError>,
+    },
+
+    //     fn modify (YunohostLDAP::modify)
+    #[snafu(display("YunohostLDAP::modify failed to modify LDAP entry {dn}"))]
+    LdapModify {
+        dn: String,
+        #[snafu(source(from(ldap3::result::LdapError, Box::new)))]
+        source: Box<ldap3::result::LdapError>,
+    },
+
+    //     fn delete (YunohostLDAP::delete)
+    #[snafu(display("YunohostLDAP::delete failed to delete LDAP entry {dn}"))]
+    LdapDelete {
+        dn: String,
+        #[snafu(source(from(ldap3::result::LdapError, Box::new)))]
+        source: Box<ldap3::result::LdapError>,
+    },
+
     // TODO
     #[snafu(display("Empty username provided for login"))]
     LdapEmptyUsername,
@@ -109,6 +177,44 @@ pub enum Error {
     #[snafu(display("Empty password provided for login"))]
     LdapEmptyPassword,
 
+    // ===================
+    // src/helpers/credentials.rs
+    // ===================
+
+    //     fn Password::random
+    #[snafu(display("Failed to generate a random password"))]
+    PasswordRandom { source: getrandom::Error },
+
+    //     fn Password::validate
+    #[snafu(display("The password is too long, it should be less than 127 characters"))]
+    PasswordTooLong,
+
+    #[snafu(display("The password should not contain control characters, like line breaks"))]
+    PasswordControlChars,
+
+    #[snafu(display("Failed to read the list of the most used passwords"))]
+    PasswordListRead {
+        #[snafu(source(from(helpers::file::error::FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("This password is among the most used passwords in the world. Please choose something more unique."))]
+    PasswordListed,
+
+    // Python: password_too_simple_{strength}
+    #[snafu(display("The password is too simple for the password strength level {strength}. It needs at least 8 characters, and digits, upper and lower case characters from level 2, special characters from level 3, and 12 characters at level 4."))]
+    PasswordTooSimple { strength: i64 },
+
+    //     fn Password::ldap_hash
+    #[snafu(display("Failed to run openssl to hash the password"))]
+    PasswordHashRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("openssl failed to hash the password:\n{stderr}"))]
+    PasswordHash { stderr: String },
+
     // ===================
     // src/helpers/process.rs
     // ===================
@@ -139,20 +245,30 @@ pub enum Error {
     },
 
     // ===================
-    // src/helpers/regenconf.rs
+    // src/helpers/lock.rs
     // ===================
 
-    //     fn get_pending_conf
-    #[snafu(display(
-        "_get_pending_conf failed to read pending changes for category {} from directory {}",
-        category,
-        path
-    ))]
-    GetPendingConfGlob {
-        category: String,
+    //     fn acquire (MoulinetteLock::acquire)
+    // Python: MoulinetteError("instance_already_running")
+    #[snafu(display("There is already a YunoHost operation running (PID {pids:?}). Please wait for it to finish before running another one."))]
+    LockAlreadyRunning { pids: Vec<u32> },
+
+    #[snafu(display("Failed to read lock file {path}"))]
+    LockRead {
         path: Utf8PathBuf,
-        #[snafu(source(from(Error, Box::new)))]
-        source: Box<dyn std::error::Error + Send + Sync>,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to write lock file {path}"))]
+    LockWrite {
+        path: Utf8PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to remove stale lock file {path}"))]
+    LockRemove {
+        path: Utf8PathBuf,
+        source: std::io::Error,
     },
 
     // ===================
@@ -191,6 +307,121 @@ pub enum Error {
     // src/helpers/ldap.rs
     // ===================
 
+    //     fn create, fn delete, fn update (YunohostUser)
+    // Python: user_unknown
+    #[snafu(display("Unknown user: {username}"))]
+    UserUnknown { username: String },
+
+    // Python: pattern_username
+    #[snafu(display("Invalid username {username}: it must be lowercase alphanumeric and underscore characters only"))]
+    UserUsernameInvalid { username: String },
+
+    // Python: pattern_fullname
+    #[snafu(display("Invalid full name {fullname:?}: it must contain letters or digits, and may only be separated by spaces or ,.'- characters"))]
+    UserFullnameInvalid { fullname: String },
+
+    // Python: pattern_mailbox_quota
+    #[snafu(display("Invalid mailbox quota {mailbox_quota}: it must be a size with b/k/M/G/T suffix, or 0 to not have a quota"))]
+    UserMailboxQuotaInvalid { mailbox_quota: String },
+
+    // Python: pattern_email
+    #[snafu(display("Invalid email address {mail}: it must be like someone@example.com"))]
+    UserMailInvalid { mail: String },
+
+    #[snafu(display("Failed to read the password strength setting"))]
+    UserPasswordSettings {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // Python: mail_domain_unknown
+    #[snafu(display(
+        "Invalid email address domain {domain}: please use a domain administered by this server"
+    ))]
+    UserMailDomainUnknown { domain: String },
+
+    // Python: mail_unavailable
+    #[snafu(display("The email address {mail} is reserved for the administrators group"))]
+    UserMailUnavailable { mail: String },
+
+    #[snafu(display("User {user} has no LDAP attribute {attribute}"))]
+    UserAttributeMissing { user: String, attribute: String },
+
+    // Python: ldap_attribute_already_exists
+    #[snafu(display("LDAP attribute '{attribute}' already exists with value '{value}'"))]
+    UserAttributeExists { attribute: String, value: String },
+
+    //     fn create (YunohostUser::create)
+    #[snafu(display("YunohostUser::create failed to take the yunohost lock"))]
+    UserCreateLock {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // Python: system_username_exists
+    #[snafu(display("Username {username} already exists in the list of system users"))]
+    UserCreateSystemUserExists { username: String },
+
+    #[snafu(display("YunohostUser::create found no free uidNumber/gidNumber for the new user"))]
+    UserCreateNoFreeId,
+
+    #[snafu(display("YunohostUser::create failed to run getent {database}"))]
+    UserCreateGetentRun {
+        database: String,
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("getent {database} failed:\n{stderr}"))]
+    UserCreateGetent { database: String, stderr: String },
+
+    //     fn delete (YunohostUser::delete)
+    // Python: group_cannot_remove_last_admin
+    #[snafu(display("Cannot delete user {username}, who is the last member of the admins group"))]
+    UserDeleteLastAdmin { username: String },
+
+    #[snafu(display("YunohostUser::delete failed to take the yunohost lock"))]
+    UserDeleteLock {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display(
+        "Failed to delete user {username} while {step}. The previous steps were already applied."
+    ))]
+    UserDeleteStep {
+        username: String,
+        step: String,
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("Failed to delete user {username} while purging {path}. The previous steps were already applied."))]
+    UserDeletePurge {
+        username: String,
+        path: String,
+        source: std::io::Error,
+    },
+
+    //     fn update (YunohostUser::update)
+    #[snafu(display("YunohostUser::update failed to take the yunohost lock"))]
+    UserUpdateLock {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // Python: mail_alias_remove_failed
+    #[snafu(display("Email alias '{mail}' could not be removed"))]
+    UserUpdateMailAliasRemove { mail: String },
+
+    // Python: mail_forward_remove_failed
+    #[snafu(display("Email forward '{mail}' could not be removed"))]
+    UserUpdateMailForwardRemove { mail: String },
+
+    // Python: invalid_shell
+    #[snafu(display("Invalid shell: {shell}"))]
+    UserUpdateShellInvalid { shell: String },
+
     //     fn from_str (UserAttr::from_str)
     #[snafu(display("UserAttr: unknown user field: {}", field))]
     LdapUserAttrUnknown { field: String },
@@ -198,6 +429,39 @@ pub enum Error {
     #[snafu(display("UserAttr: cannot request user password from LDAP"))]
     LdapUserAttrNotPassword,
 
+    // ===================
+    // src/helpers/ssl.rs
+    // ===================
+    //     fn _openssl
+    #[snafu(display("Failed to run openssl to {step}"))]
+    SslOpensslRun {
+        step: String,
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("Failed to {step}, openssl said:\n{stderr}"))]
+    SslOpenssl { step: String, stderr: String },
+
+    //     (SslPaths)
+    #[snafu(display("Failed to setup certificate files in {path}"))]
+    SslFile {
+        path: Utf8PathBuf,
+        #[snafu(source(from(helpers::file::error::FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // ===================
+    // src/helpers/template.rs
+    // ===================
+
+    //     fn Templates::render
+    #[snafu(display("Failed to render the template {path}: {source}"))]
+    TemplateRender {
+        path: Utf8PathBuf,
+        source: minijinja::Error,
+    },
+
     // ===================
     // hooks/conf_regen/01-yunohost.rs
     // ===================
@@ -254,6 +518,394 @@ pub enum Error {
         source: Box<dyn std::error::Error + Send + Sync>,
     },
 
+    // ===================
+    // hooks/conf_regen/02-ssl.rs
+    // ===================
+
+    //     fn init, fn post
+    #[snafu(display("conf_regen/02-ssl failed to run update-ca-certificates"))]
+    ConfRegenSslUpdateCaCertificates {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    //     fn post
+    #[snafu(display("conf_regen/02-ssl::post failed to move the legacy local CA"))]
+    ConfRegenSslPostLegacy {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/02-ssl::post failed to copy the legacy local CA"))]
+    ConfRegenSslPostLegacyCopy,
+
+    #[snafu(display("conf_regen/02-ssl::post failed to remove the legacy local CA"))]
+    ConfRegenSslPostLegacyRemove { source: std::io::Error },
+
+    #[snafu(display("conf_regen/02-ssl::post failed to read the main domain"))]
+    ConfRegenSslPostMainDomain {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // ===================
+    // hooks/conf_regen/03-ssh.rs
+    // ===================
+
+    //     fn pre
+    #[snafu(display("conf_regen/03-ssh::pre failed to read the ssh settings"))]
+    ConfRegenSshPreSettings {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    //     fn post
+    #[snafu(display("conf_regen/03-ssh::post failed to reload ssh"))]
+    ConfRegenSshPostReload,
+
+    // ===================
+    // hooks/conf_regen/06-slapd.rs
+    // ===================
+
+    //     fn _run
+    #[snafu(display("conf_regen/06-slapd failed to run {cmd}"))]
+    ConfRegenSlapdCmdRun {
+        cmd: String,
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/06-slapd: {cmd} failed:\n{stderr}"))]
+    ConfRegenSlapdCmd { cmd: String, stderr: String },
+
+    //     fn _service
+    #[snafu(display("conf_regen/06-slapd failed to {action} slapd"))]
+    ConfRegenSlapdService { action: String },
+
+    //     fn _remove_all
+    #[snafu(display("conf_regen/06-slapd failed to remove {path}"))]
+    ConfRegenSlapdRemove {
+        path: Utf8PathBuf,
+        source: std::io::Error,
+    },
+
+    //     fn post (_regenerate_slapd_conf)
+    #[snafu(display(
+        "conf_regen/06-slapd::post refused to install the new slapd configuration, it is invalid"
+    ))]
+    ConfRegenSlapdPostConfigTest {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/06-slapd::post failed to replace the slapd configuration"))]
+    ConfRegenSlapdPostReplace { source: std::io::Error },
+
+    // ===================
+    // hooks/conf_regen/09-nslcd.rs
+    // ===================
+
+    //     fn post
+    #[snafu(display("conf_regen/09-nslcd::post failed to restart nslcd"))]
+    ConfRegenNslcdPostRestart,
+
+    // ===================
+    // hooks/conf_regen/10-apt.rs
+    // ===================
+
+    //     fn post
+    #[snafu(display("conf_regen/10-apt::post failed to run apt-get update"))]
+    ConfRegenAptPostUpdateRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/10-apt::post failed to update the list of packages:\n{stderr}"))]
+    ConfRegenAptPostUpdate { stderr: String },
+
+    //     fn _download_key
+    #[snafu(display("conf_regen/10-apt::post failed to run the download of a repository key"))]
+    ConfRegenAptPostKeyRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display(
+        "conf_regen/10-apt::post failed to convert the repository key from {url}:\n{stderr}"
+    ))]
+    ConfRegenAptPostKey { url: String, stderr: String },
+
+    // ===================
+    // hooks/conf_regen/12-metronome.rs
+    // ===================
+
+    //     fn pre
+    #[snafu(display("conf_regen/12-metronome::pre failed to read the XMPP domains"))]
+    ConfRegenMetronomePreDomains {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    //     fn post
+    #[snafu(display("conf_regen/12-metronome::post failed to read the XMPP domains"))]
+    ConfRegenMetronomePostDomains {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/12-metronome::post failed to run usermod"))]
+    ConfRegenMetronomePostUsermodRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display(
+        "conf_regen/12-metronome::post failed to add metronome to the ssl-cert group:\n{stderr}"
+    ))]
+    ConfRegenMetronomePostUsermod { stderr: String },
+
+    #[snafu(display("conf_regen/12-metronome::post failed to restart metronome"))]
+    ConfRegenMetronomePostRestart,
+
+    // ===================
+    // hooks/conf_regen/15-nginx.rs
+    // ===================
+
+    //     fn pre
+    #[snafu(display("conf_regen/15-nginx::pre failed to read the nginx settings"))]
+    ConfRegenNginxPreSettings {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/15-nginx::pre failed to list the domains"))]
+    ConfRegenNginxPreDomains {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/15-nginx::pre failed to check the certificates of the domains"))]
+    ConfRegenNginxPreCerts {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    //     fn post
+    #[snafu(display("conf_regen/15-nginx::post failed to list the domains"))]
+    ConfRegenNginxPostDomains {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/15-nginx::post failed to run nginx -t"))]
+    ConfRegenNginxPostTestRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/15-nginx::post refused to reload nginx, the configuration is invalid:\n{stderr}"))]
+    ConfRegenNginxPostTest { stderr: String },
+
+    #[snafu(display("conf_regen/15-nginx::post failed to reload nginx"))]
+    ConfRegenNginxPostReload,
+
+    // ===================
+    // hooks/conf_regen/19-postfix.rs
+    // ===================
+
+    //     fn pre
+    #[snafu(display("conf_regen/19-postfix::pre failed to read the email settings"))]
+    ConfRegenPostfixPreSettings {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/19-postfix::pre failed to read the domains"))]
+    ConfRegenPostfixPreDomains {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    //     fn post (_postmap)
+    #[snafu(display("conf_regen/19-postfix::post failed to run postmap"))]
+    ConfRegenPostfixPostmapRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/19-postfix::post failed to build a postfix map:\n{stderr}"))]
+    ConfRegenPostfixPostmap { stderr: String },
+
+    //     fn post
+    #[snafu(display("conf_regen/19-postfix::post failed to restart {service}"))]
+    ConfRegenPostfixPostRestart { service: String },
+
+    // ===================
+    // hooks/conf_regen/25-dovecot.rs
+    // ===================
+
+    //     fn pre
+    #[snafu(display("conf_regen/25-dovecot::pre failed to read the email settings"))]
+    ConfRegenDovecotPreSettings {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/25-dovecot::pre failed to read the domains"))]
+    ConfRegenDovecotPreDomains {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    //     fn post (_ensure_vmail_user)
+    #[snafu(display("conf_regen/25-dovecot::post failed to run adduser for the vmail user"))]
+    ConfRegenDovecotPostVmailRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/25-dovecot::post failed to create the vmail user:\n{stderr}"))]
+    ConfRegenDovecotPostVmail { stderr: String },
+
+    //     fn post
+    #[snafu(display("conf_regen/25-dovecot::post failed to run sievec"))]
+    ConfRegenDovecotPostSievecRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/25-dovecot::post failed to compile the sieve script:\n{stderr}"))]
+    ConfRegenDovecotPostSievec { stderr: String },
+
+    #[snafu(display("conf_regen/25-dovecot::post failed to restart dovecot"))]
+    ConfRegenDovecotPostRestart,
+
+    // ===================
+    // hooks/conf_regen/34-mysql.rs
+    // ===================
+
+    //     fn _ensure_active
+    #[snafu(display("conf_regen/34-mysql failed to start mariadb"))]
+    ConfRegenMysqlStart,
+
+    //     fn _ensure_root_password
+    #[snafu(display("conf_regen/34-mysql failed to run mariadb to set the root password"))]
+    ConfRegenMysqlPasswordRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/34-mysql failed to set the MySQL root password:\n{stderr}"))]
+    ConfRegenMysqlPassword { stderr: String },
+
+    //     fn post
+    #[snafu(display("conf_regen/34-mysql::post failed to restart mariadb"))]
+    ConfRegenMysqlPostRestart,
+
+    // ===================
+    // hooks/conf_regen/35-postgresql.rs
+    // ===================
+
+    //     fn _ensure_root_password
+    #[snafu(display("conf_regen/35-postgresql failed to start postgresql"))]
+    ConfRegenPostgresqlStart,
+
+    #[snafu(display("conf_regen/35-postgresql failed to run psql to set the root password"))]
+    ConfRegenPostgresqlPasswordRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display(
+        "conf_regen/35-postgresql failed to set the PostgreSQL root password:\n{stderr}"
+    ))]
+    ConfRegenPostgresqlPassword { stderr: String },
+
+    //     fn post
+    #[snafu(display("conf_regen/35-postgresql::post failed to reload postgresql"))]
+    ConfRegenPostgresqlPostReload,
+
+    // ===================
+    // hooks/conf_regen/36-redis.rs
+    // ===================
+
+    //     fn post
+    #[snafu(display("conf_regen/36-redis::post failed to restart redis-server"))]
+    ConfRegenRedisPostRestart,
+
+    // ===================
+    // hooks/conf_regen/37-mdns.rs
+    // ===================
+
+    //     fn pre
+    #[snafu(display("conf_regen/37-mdns::pre failed to read the domains"))]
+    ConfRegenMdnsPreDomains {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    //     fn post
+    #[snafu(display("conf_regen/37-mdns::post failed to run useradd"))]
+    ConfRegenMdnsPostUserRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("conf_regen/37-mdns::post failed to create the mdns user:\n{stderr}"))]
+    ConfRegenMdnsPostUser { stderr: String },
+
+    #[snafu(display("conf_regen/37-mdns::post failed to restart yunomdns"))]
+    ConfRegenMdnsPostRestart,
+
+    // ===================
+    // hooks/conf_regen/43-dnsmasq.rs
+    // ===================
+
+    //     fn pre
+    #[snafu(display("conf_regen/43-dnsmasq::pre failed to read the domains"))]
+    ConfRegenDnsmasqPreDomains {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    //     fn post
+    #[snafu(display("conf_regen/43-dnsmasq::post failed to restart dnsmasq"))]
+    ConfRegenDnsmasqPostRestart,
+
+    // ===================
+    // hooks/conf_regen/46-nsswitch.rs
+    // ===================
+
+    //     fn _restart_unscd
+    #[snafu(display("conf_regen/46-nsswitch failed to restart unscd"))]
+    ConfRegenNsswitchRestart,
+
+    // ===================
+    // hooks/conf_regen/52-fail2ban.rs
+    // ===================
+
+    //     fn pre
+    #[snafu(display("conf_regen/52-fail2ban::pre failed to read the ssh port"))]
+    ConfRegenFail2banPreSettings {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    //     fn post
+    #[snafu(display("conf_regen/52-fail2ban::post failed to run fail2ban-client --test"))]
+    ConfRegenFail2banPostTestRun {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display(
+        "conf_regen/52-fail2ban::post refused to reload fail2ban, the jails are invalid:\n{stderr}"
+    ))]
+    ConfRegenFail2banPostTest { stderr: String },
+
+    #[snafu(display("conf_regen/52-fail2ban::post failed to reload fail2ban"))]
+    ConfRegenFail2banPostReload,
+
     #[snafu(display("TODO"))]
     TODO,
 }
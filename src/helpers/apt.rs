@@ -1,6 +1,9 @@
 use snafu::prelude::*;
 
-use crate::{error::*, helpers::file::*};
+use crate::{
+    error::*,
+    helpers::{file::*, process::cmd},
+};
 
 /// Change dpkg vendor, as per the [Debian documentation](https://wiki.debian.org/Derivatives/Guidelines#Vendor).
 pub fn change_dpkg_vendor(vendor: &StrPath) -> Result<(), Error> {
@@ -17,3 +20,9 @@ pub fn change_dpkg_vendor(vendor: &StrPath) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Checks whether the Debian `package` is installed, like `dpkg --list | grep "ii *package "`.
+pub fn is_installed(package: &str) -> bool {
+    cmd("dpkg-query", ["--show", "--showformat=${Status}", package])
+        .is_ok_and(|res| res.status.success() && res.stdout == b"install ok installed")
+}
@@ -57,6 +57,13 @@ pub enum ConfigPanelError {
         option_type: String,
         source: strum::ParseError,
     },
+
+    // settings.rs (SettingsConfigPanel::get_bool, SettingsConfigPanel::get_string)
+    #[snafu(display("Setting {key} has an unexpected value: {value}"))]
+    SettingsValueType {
+        key: String,
+        value: serde_json::Value,
+    },
 }
 
 impl std::cmp::PartialEq for ConfigPanelError {
@@ -117,6 +124,10 @@ impl std::cmp::PartialEq for ConfigPanelError {
                     ..
                 },
             ) => id1 == id2 && t1 == t2,
+            (
+                Self::SettingsValueType { key: k1, value: v1 },
+                Self::SettingsValueType { key: k2, value: v2 },
+            ) => k1 == k2 && v1 == v2,
             _ => false,
         }
     }
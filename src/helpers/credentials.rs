@@ -4,7 +4,10 @@ use snafu::prelude::*;
 
 use std::str::FromStr;
 
-use crate::error::*;
+use crate::{
+    error::*,
+    helpers::{file::*, process::cmd_stdin},
+};
 
 fn non_empty_string(s: &str) -> Option<String> {
     if s.trim() == "" {
@@ -81,7 +84,34 @@ impl TryFromChunks for Username {
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
+/// Passwords from this list are always refused, unless password validation is disabled.
+const SMALL_PASSWORD_LIST: [&str; 8] = [
+    "yunohost",
+    "olinuxino",
+    "olinux",
+    "raspberry",
+    "admin",
+    "root",
+    "test",
+    "rpi",
+];
+/// The most common passwords, which are refused like [`SMALL_PASSWORD_LIST`], when the file exists.
+const MOST_USED_PASSWORDS: &str = "/usr/share/yunohost/100000-most-used-passwords.txt";
+/// The minimum length, digits, lowercase, uppercase and other characters of each strength level.
+const STRENGTH_LEVELS: [[usize; 5]; 4] = [
+    [8, 0, 0, 0, 0],
+    [8, 1, 1, 1, 0],
+    [8, 1, 1, 1, 1],
+    [12, 1, 1, 1, 1],
+];
+/// Longer passwords break some UNIX tools, like su or SSH login.
+const MAX_PASSWORD_LENGTH: usize = 127;
+
+/// The characters of generated passwords. They never need escaping, in a shell or in SQL.
+const RANDOM_PASSWORD_CHARS: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+#[derive(Clone, Serialize)]
 pub struct Password(String);
 
 impl Password {
@@ -91,6 +121,28 @@ impl Password {
             .map(|s| Password(s))
     }
 
+    /// Generates a random alphanumeric password of `length` characters, from the CSPRNG of the OS.
+    ///
+    /// Errors when:
+    /// - the OS random number generator is not available
+    pub fn random(length: usize) -> Result<Password, Error> {
+        // Random bytes above the largest multiple of the number of characters are dropped,
+        // so that every character is equally likely
+        let limit = 256 - 256 % RANDOM_PASSWORD_CHARS.len();
+        let mut password = String::with_capacity(length);
+        let mut bytes = [0u8; 64];
+        while password.len() < length {
+            getrandom::fill(&mut bytes).context(PasswordRandomSnafu)?;
+            for byte in bytes.iter().map(|byte| *byte as usize) {
+                if byte < limit && password.len() < length {
+                    password
+                        .push(RANDOM_PASSWORD_CHARS[byte % RANDOM_PASSWORD_CHARS.len()] as char);
+                }
+            }
+        }
+        Ok(Password(password))
+    }
+
     pub fn as_str(&self) -> &str {
         self.0.as_str()
     }
@@ -98,6 +150,109 @@ impl Password {
     pub fn ldap_escape(&self) -> String {
         dn_escape(self.as_str()).to_string()
     }
+
+    /// The strength of the password, from 0 to 4, like in Python: each level requires a minimum length
+    /// (8, or 12 for level 4), and from level 2 at least one digit, lowercase and uppercase letter,
+    /// and from level 3 one other character.
+    pub fn strength_level(&self) -> i64 {
+        let password = self.as_str();
+        let length = password.chars().count();
+        let digits = password.chars().filter(char::is_ascii_digit).count();
+        let lowers = password.chars().filter(char::is_ascii_lowercase).count();
+        let uppers = password.chars().filter(char::is_ascii_uppercase).count();
+        let strength = [
+            length,
+            digits,
+            lowers,
+            uppers,
+            length - digits - lowers - uppers,
+        ];
+
+        let mut level = 0;
+        for criteria in STRENGTH_LEVELS {
+            if strength.iter().zip(criteria).any(|(s, c)| *s < c) {
+                break;
+            }
+            level += 1;
+        }
+        level
+    }
+
+    /// Checks that the password can be used on the system, and that it's at least as strong as
+    /// `strength`, like the `security.password.*.strength` settings. A strength of -1 disables the
+    /// strength checks.
+    ///
+    /// Errors when:
+    /// - the password is too long, or contains control characters
+    /// - the password is one of the most used passwords
+    /// - the password is not strong enough
+    /// - the list of the most used passwords could not be read
+    pub fn validate(&self, strength: i64) -> Result<(), Error> {
+        let password = self.as_str();
+        ensure!(
+            password.chars().count() < MAX_PASSWORD_LENGTH,
+            PasswordTooLongSnafu
+        );
+        ensure!(
+            !password.chars().any(char::is_control),
+            PasswordControlCharsSnafu
+        );
+
+        if strength == -1 {
+            return Ok(());
+        }
+
+        let most_used = path(MOST_USED_PASSWORDS);
+        let listed = SMALL_PASSWORD_LIST.contains(&password)
+            || (most_used.is_file()
+                && most_used
+                    .read_lines()
+                    .context(PasswordListReadSnafu)?
+                    .iter()
+                    .any(|line| line == password));
+        ensure!(!listed, PasswordListedSnafu);
+        ensure!(
+            self.strength_level() >= strength,
+            PasswordTooSimpleSnafu { strength }
+        );
+
+        Ok(())
+    }
+
+    /// Hashes the password with SHA-512 crypt and a random salt, in the `{CRYPT}` format of slapd.
+    /// The password is given to openssl on its standard input, so that it never appears in the
+    /// process list.
+    ///
+    /// Errors when:
+    /// - generating the salt failed
+    /// - openssl failed to run or to hash the password
+    pub fn ldap_hash(&self) -> Result<String, Error> {
+        let salt = Password::random(16)?;
+        let res = cmd_stdin(
+            "openssl",
+            ["passwd", "-6", "-salt", salt.as_str(), "-stdin"],
+            self.as_str().as_bytes(),
+        )
+        .context(PasswordHashRunSnafu)?;
+        ensure!(
+            res.status.success(),
+            PasswordHashSnafu {
+                stderr: String::from_utf8_lossy(&res.stderr).to_string(),
+            }
+        );
+
+        Ok(format!(
+            "{{CRYPT}}{}",
+            String::from_utf8_lossy(&res.stdout).trim()
+        ))
+    }
+}
+
+/// The password is never displayed, so that it doesn't end up in the logs.
+impl std::fmt::Debug for Password {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "Password(<redacted>)")
+    }
 }
 
 impl FromStr for Password {
@@ -117,3 +272,45 @@ impl<'de> Deserialize<'de> for Password {
         FromStr::from_str(&s).map_err(serde::de::Error::custom)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_password() {
+        let password = Password::random(32).unwrap();
+        assert_eq!(32, password.as_str().len());
+        assert!(password
+            .as_str()
+            .bytes()
+            .all(|c| RANDOM_PASSWORD_CHARS.contains(&c)));
+        assert_ne!(password.as_str(), Password::random(32).unwrap().as_str());
+        assert_eq!("Password(<redacted>)", format!("{password:?}"));
+    }
+
+    #[test]
+    fn password_strength() {
+        let levels = [
+            ("short", 0),
+            ("longenough", 1),
+            ("Long3nough", 2),
+            ("Long3nough!", 3),
+            ("Very Long3nough!", 4),
+        ];
+        for (password, level) in levels {
+            assert_eq!(level, Password::new(password).unwrap().strength_level());
+        }
+
+        let password = Password::new("Long3nough").unwrap();
+        assert!(password.validate(2).is_ok());
+        assert!(password.validate(3).is_err());
+        assert!(Password::new("yunohost").unwrap().validate(1).is_err());
+        assert!(Password::new("yunohost").unwrap().validate(-1).is_ok());
+        assert!(Password::new("new\nline").unwrap().validate(-1).is_err());
+        assert!(Password::new(&"a".repeat(127))
+            .unwrap()
+            .validate(-1)
+            .is_err());
+    }
+}
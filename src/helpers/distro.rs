@@ -28,6 +28,14 @@ impl FromStr for DebianRelease {
 }
 
 impl DebianRelease {
+    /// The codename of the release, as used in the APT sources.
+    pub fn codename(&self) -> &'static str {
+        match self {
+            Self::Bullseye => "bullseye",
+            Self::Bookworm => "bookworm",
+        }
+    }
+
     pub fn from_disk() -> Result<Self, Error> {
         let p = path("/etc/os-release");
         let s = p.read().context(DistroSnafu)?;
@@ -0,0 +1,130 @@
+use ldap3::Scope;
+use serde_yaml_ng::Value;
+use snafu::prelude::*;
+use tokio::runtime::Builder as RuntimeBuilder;
+
+use std::collections::BTreeMap;
+
+use crate::{
+    error::*,
+    helpers::{file::*, ldap::*},
+};
+
+pub const MAIN_DOMAIN_FILE: &str = "/etc/yunohost/current_host";
+/// The settings of each domain are stored in `DOMAIN.yml` in this directory.
+pub const DOMAIN_SETTINGS_DIR: &str = "/etc/yunohost/domains";
+
+/// The domains configured on the Yunohost system.
+///
+/// More specifically, the entries in the `ou=domains` in the Yunohost LDAP database.
+pub struct YunohostDomain;
+
+impl YunohostDomain {
+    /// Reads the main domain from disk.
+    ///
+    /// Errors when:
+    ///   - reading /etc/yunohost/current_host failed
+    pub fn main() -> Result<String, Error> {
+        let main = path(MAIN_DOMAIN_FILE).read().context(DomainMainReadSnafu)?;
+        Ok(main.trim().to_string())
+    }
+
+    /// Lists all domains, main domain first, then sorted like the Python version does.
+    ///
+    /// If `exclude_subdomains` is true, domains whose parent domain is also in the list are omitted.
+    ///
+    /// Errors when:
+    ///   - querying the LDAP database failed
+    ///   - reading the main domain failed
+    pub fn list(exclude_subdomains: bool) -> Result<Vec<String>, Error> {
+        let rt = RuntimeBuilder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        let domain_list = rt.block_on(async {
+            let ldap = YunohostLDAP::new(1000).await?;
+
+            let domains = ldap
+                .list(
+                    "ou=domains,dc=yunohost,dc=org",
+                    Scope::OneLevel,
+                    "(objectclass=domain)",
+                    vec!["virtualdomain"],
+                )
+                .await?;
+
+            Ok(domains)
+        })?;
+
+        let mut domains: Vec<String> = domain_list
+            .into_iter()
+            .filter_map(|entry| {
+                entry
+                    .attrs
+                    .get("virtualdomain")
+                    .and_then(|values| values.first().cloned())
+            })
+            .collect();
+
+        if exclude_subdomains {
+            // Like Python, only the direct parent of a domain is checked
+            let all_domains = domains.clone();
+            domains.retain(|domain| {
+                domain
+                    .split_once('.')
+                    .is_none_or(|(_, parent)| !all_domains.iter().any(|d| d == parent))
+            });
+        }
+
+        domains.sort_by_key(|domain| Self::sort_key(domain));
+
+        if !domains.is_empty() {
+            let main = Self::main()?;
+            if let Some(index) = domains.iter().position(|domain| domain == &main) {
+                let main = domains.remove(index);
+                domains.insert(0, main);
+            }
+        }
+
+        Ok(domains)
+    }
+
+    /// Checks whether a boolean `feature` of `domain`, like `xmpp`, is enabled in the domain settings.
+    /// Features which are not set are disabled, like `yunohost domain list --features` does.
+    ///
+    /// Errors when:
+    ///   - the domain settings exist but could not be read
+    pub fn feature_enabled(domain: &str, feature: &str) -> Result<bool, Error> {
+        let settings_file = path(format!("{DOMAIN_SETTINGS_DIR}/{domain}.yml"));
+        if !settings_file.is_file() {
+            return Ok(false);
+        }
+
+        let settings: BTreeMap<String, Value> = settings_file
+            .read_yaml()
+            .context(DomainSettingsReadSnafu { domain })?;
+        Ok(match settings.get(feature) {
+            Some(Value::Bool(enabled)) => *enabled,
+            Some(Value::Number(number)) => number.as_u64() == Some(1),
+            Some(Value::String(string)) => ["1", "true", "yes"].contains(&string.as_str()),
+            _ => false,
+        })
+    }
+
+    /// Keep the main part of the domain and the extension together, then reverse
+    /// the parts, so that subdomains are listed right after their parent domain.
+    ///
+    /// eg: `this.is.an.example.com` -> `["examplecom", "an", "is", "this"]`
+    fn sort_key(domain: &str) -> Vec<String> {
+        let mut parts: Vec<String> = domain.split('.').map(String::from).collect();
+        if parts.len() > 1 {
+            let extension = parts.pop().unwrap();
+            let last = parts.len() - 1;
+            parts[last].push_str(&extension);
+        }
+        parts.reverse();
+        parts
+    }
+}
@@ -117,6 +117,13 @@ pub enum FileError {
         source: std::io::Error,
     },
 
+    // StrPath::write
+    #[snafu(display("write failed to write {path}"))]
+    PathWrite {
+        path: StrPath,
+        source: std::io::Error,
+    },
+
     // StrPath::symlink_to_target
     #[snafu(display("symlink failed to create a symlink to {target} due to failing to remove existing file: {link}"))]
     PathSymlinkRemove {
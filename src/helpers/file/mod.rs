@@ -10,7 +10,8 @@ use serde::Deserialize;
 use snafu::prelude::*;
 
 use std::fs;
-use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::io::Write;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
 use std::path::Path;
 
 pub mod error;
@@ -265,6 +266,30 @@ impl StrPath {
         Ok(())
     }
 
+    /// Copy to the `dest` file, keeping permissions and modification time like Python's `shutil.copy2`.
+    ///
+    /// Errors when:
+    /// - the path does not exist or is not readable
+    /// - copying to `dest` failed
+    pub fn copy_file(&self, dest: &StrPath) -> Result<(), FileError> {
+        let context = PathCopyFailSnafu {
+            path: self.clone(),
+            dest: dest.clone(),
+        };
+
+        fs::copy(self, dest).context(context.clone())?;
+        let modified = fs::metadata(self)
+            .and_then(|metadata| metadata.modified())
+            .context(context.clone())?;
+        fs::File::options()
+            .write(true)
+            .open(dest)
+            .and_then(|file| file.set_modified(modified))
+            .context(context)?;
+
+        Ok(())
+    }
+
     /// Reads UTF-8 content to an owned string.
     ///
     /// Errors when:
@@ -275,6 +300,132 @@ impl StrPath {
         fs::read_to_string(self).context(PathReadSnafu { path: self.clone() })
     }
 
+    /// Reads raw bytes content, for files which may not be UTF-8.
+    ///
+    /// Errors when:
+    /// - the path does not exist or is not a file
+    /// - the path is not readable
+    pub fn read_bytes(&self) -> Result<Vec<u8>, FileError> {
+        fs::read(self).context(PathReadSnafu { path: self.clone() })
+    }
+
+    /// Writes `content` to the file, creating it if needed and truncating it otherwise.
+    ///
+    /// When the file already exists, its ownership and permissions are kept.
+    ///
+    /// Errors when:
+    /// - the parent directory does not exist
+    /// - the path is not writable
+    pub fn write<C: AsRef<[u8]>>(&self, content: C) -> Result<(), FileError> {
+        fs::write(self, content).context(PathWriteSnafu { path: self.clone() })
+    }
+
+    /// Writes `content` to a file which only its owner can read (mode 0600), such as a password.
+    /// The file is created with this mode, so the content is never readable by other users, and
+    /// the mode of an existing file is reset first.
+    ///
+    /// Errors when:
+    /// - the parent directory does not exist or is not writable
+    /// - the mode of the existing file could not be changed
+    pub fn write_private<C: AsRef<[u8]>>(&self, content: C) -> Result<(), FileError> {
+        if self.exists() {
+            self.chmod(0o600)?;
+        }
+
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(self)
+            .and_then(|mut file| file.write_all(content.as_ref()))
+            .context(PathWriteSnafu { path: self.clone() })
+    }
+
+    /// Writes `content` to the file atomically: it is written to a temporary file in the same directory,
+    /// which then replaces the file. The file is never left half-written, even if the process is killed.
+    ///
+    /// When the file already exists, its ownership and permissions are kept, otherwise it gets mode 0644.
+    /// When it's a symlink, the target of the symlink is replaced.
+    ///
+    /// Errors when:
+    /// - the parent directory does not exist or is not writable
+    /// - the ownership/permissions of the existing file could not be read or applied
+    pub fn write_atomic<C: AsRef<[u8]>>(&self, content: C) -> Result<(), FileError> {
+        self.replace_atomic(content.as_ref(), None)
+    }
+
+    /// Copies the file `source` to the file atomically, like [`write_atomic`](Self::write_atomic), but the
+    /// file gets the ownership and permissions of `source`, like Python's `shutil.copy2`. The file is never
+    /// readable by other users before it gets the permissions of `source`.
+    ///
+    /// Errors when:
+    /// - `source` could not be read
+    /// - the parent directory does not exist or is not writable
+    /// - the ownership/permissions of `source` could not be read or applied
+    pub fn install_atomic(&self, source: &StrPath) -> Result<(), FileError> {
+        let content = source.read_bytes()?;
+        self.replace_atomic(&content, Some(source))
+    }
+
+    /// Replaces the file with `content` through a temporary file, with the ownership and permissions
+    /// of `metadata_from`, or of the existing file.
+    fn replace_atomic(
+        &self,
+        content: &[u8],
+        metadata_from: Option<&StrPath>,
+    ) -> Result<(), FileError> {
+        let target = if self.is_symlink() {
+            self.canonicalize()?
+        } else {
+            self.clone()
+        };
+
+        // UNWRAP NOTE: We are writing to a file, so it has a file name
+        let tmp = StrPath::from(target.with_file_name(format!(
+            ".{}.tmp{}",
+            target.file_name().unwrap(),
+            std::process::id()
+        )));
+
+        let res = target.write_atomic_tmp(&tmp, content, metadata_from);
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        res
+    }
+
+    /// Writes to `tmp`, which is only readable by its owner until it gets the ownership and permissions
+    /// of `metadata_from` or `self`, then moves `tmp` to `self`.
+    fn write_atomic_tmp(
+        &self,
+        tmp: &StrPath,
+        content: &[u8],
+        metadata_from: Option<&StrPath>,
+    ) -> Result<(), FileError> {
+        // A leftover from a killed process may have other permissions
+        let _ = fs::remove_file(tmp);
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(tmp)
+            .context(PathWriteSnafu { path: tmp.clone() })?;
+        file.write_all(content)
+            .and_then(|_| file.sync_all())
+            .context(PathWriteSnafu { path: tmp.clone() })?;
+
+        match metadata_from.or(self.exists().then_some(self)) {
+            Some(from) => {
+                tmp.chown(&from.owner_get()?, &from.group_get()?)?;
+                tmp.mode_set(from.mode_get()?)?;
+            }
+            None => tmp.mode_set(0o644)?,
+        }
+
+        fs::rename(tmp, self).context(PathWriteSnafu { path: self.clone() })
+    }
+
     /// Reads UTF-8 lines to an owned list of strings.
     ///
     /// Example:
@@ -295,13 +446,13 @@ impl StrPath {
 
     /// Creates a symlink on `self` pointing to `target`.
     ///
-    /// If `force` is true, an existing file `self` will be deleted first.
+    /// If `force` is true, an existing file or symlink `self` will be deleted first.
     ///
     /// Errors when:
     ///   - `self` exists and `force` is not true
     ///   - creating the symlink failed
     pub fn symlink_to_target(&self, target: &Utf8Path, force: bool) -> Result<(), FileError> {
-        if force && self.exists() {
+        if force && (self.exists() || self.is_symlink()) {
             self.file_remove().context(PathSymlinkRemoveSnafu {
                 target: StrPath::from(target),
                 link: self.clone(),
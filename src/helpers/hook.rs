@@ -1,5 +1,10 @@
-use std::fs::read_dir;
+use std::collections::BTreeMap;
+use std::fs::{read_dir, File};
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use crate::{error::*, helpers::process::cmd_env};
 
 pub const HOOK_FOLDER: &'static str = "/usr/share/yunohost/hooks";
 pub const CUSTOM_HOOK_FOLDER: &'static str = "/etc/yunohost/hooks.d";
@@ -50,6 +55,45 @@ impl Hook {
         }
         result
     }
+
+    /// Checks whether the hook is a native (ELF) executable, as opposed to a script.
+    pub fn is_native(&self) -> bool {
+        let mut magic = [0u8; 4];
+        File::open(&self.path)
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .map(|_| &magic == b"\x7fELF")
+            .unwrap_or(false)
+    }
+
+    /// Runs the hook with `args` and extra `env` variables, returning its [`Output`].
+    ///
+    /// Native hooks (such as `01-yunohost`) are executed directly, while other hooks are
+    /// shell scripts and are run through bash, like the Python version does.
+    /// Does not error when the hook returns a non-zero exit code.
+    ///
+    /// Errors when:
+    ///   - the hook is a Python hook, which cannot be run from here
+    ///   - the hook (or bash) failed to run
+    pub fn exec(&self, args: &[&str], env: &BTreeMap<String, String>) -> Result<Output, Error> {
+        // UNWRAP NOTE: Hooks are built from read_dir entries, so they have a file name
+        let path = self.path.to_str().unwrap();
+
+        if path.ends_with(".py") {
+            return Err(Error::HookExecPython {
+                path: path.to_string(),
+            });
+        }
+
+        debug!("Running hook {path} with args {args:?}");
+
+        if self.is_native() {
+            cmd_env(path, args, env)
+        } else {
+            let mut bash_args = vec![path];
+            bash_args.extend(args);
+            cmd_env("/bin/bash", bash_args, env)
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -76,6 +120,21 @@ impl HookList {
             hooks: self.hooks.iter().map(|x| x.name.to_string()).collect(),
         }
     }
+
+    /// Returns the hooks in execution order (by priority, then name).
+    ///
+    /// A custom hook with the same priority and name as a Yunohost hook replaces it,
+    /// like in the Python version. If `names` is not empty, only hooks with those names are kept.
+    pub fn by_priority(&self, names: &[String]) -> Vec<Hook> {
+        let mut hooks: BTreeMap<(String, String), Hook> = BTreeMap::new();
+        for hook in &self.hooks {
+            if !names.is_empty() && !names.contains(&hook.name) {
+                continue;
+            }
+            hooks.insert((hook.priority.clone(), hook.name.clone()), hook.clone());
+        }
+        hooks.into_values().collect()
+    }
 }
 
 // We use stringy priority because that's what Python did
@@ -98,6 +157,33 @@ pub fn _extract_filename_parts(name: &str) -> (String, String) {
     }
 }
 
+/// Runs all the hooks of `action` in order with `args`, and `env` added to their environment,
+/// like Python's `hook_callback`. A failing hook is logged, and doesn't prevent the other hooks from running.
+///
+/// The output of the hooks is logged, but not their environment which may contain secrets.
+pub fn hook_callback(action: &str, args: &[&str], env: &BTreeMap<String, String>) {
+    for hook in HookList::for_action(action).by_priority(&[]) {
+        match hook.exec(args, env) {
+            Ok(output) => {
+                for line in String::from_utf8_lossy(&output.stdout).lines() {
+                    debug!("{}: {line}", hook.name);
+                }
+                for line in String::from_utf8_lossy(&output.stderr).lines() {
+                    warn!("{}: {line}", hook.name);
+                }
+                if !output.status.success() {
+                    error!(
+                        "Hook {} failed for action {action} ({})",
+                        hook.path.display(),
+                        output.status
+                    );
+                }
+            }
+            Err(e) => error!("Failed to run hook {}: {e}", hook.path.display()),
+        }
+    }
+}
+
 // Not reimplemented:
 // - list_by
 // - show_info
@@ -136,4 +222,32 @@ mod tests {
             _extract_filename_parts("yunohost")
         );
     }
+
+    #[test]
+    fn by_priority_custom_overrides() {
+        let list = HookList {
+            hooks: vec![
+                Hook::from_path("/usr/share/yunohost/hooks/conf_regen/15-nginx").unwrap(),
+                Hook::from_path("/usr/share/yunohost/hooks/conf_regen/01-yunohost").unwrap(),
+                Hook::from_path("/usr/share/yunohost/hooks/conf_regen/03-ssh").unwrap(),
+                Hook::from_path("/etc/yunohost/hooks.d/conf_regen/15-nginx").unwrap(),
+            ],
+        };
+
+        let hooks = list.by_priority(&[]);
+        assert_eq!(
+            vec!["yunohost", "ssh", "nginx"],
+            hooks.iter().map(|x| x.name.as_str()).collect::<Vec<&str>>(),
+        );
+        assert_eq!(
+            PathBuf::from("/etc/yunohost/hooks.d/conf_regen/15-nginx"),
+            hooks[2].path,
+        );
+
+        let hooks = list.by_priority(&["nginx".to_string(), "ssh".to_string()]);
+        assert_eq!(
+            vec!["ssh", "nginx"],
+            hooks.iter().map(|x| x.name.as_str()).collect::<Vec<&str>>(),
+        );
+    }
 }
@@ -1,7 +1,9 @@
-use ldap3::{exop::WhoAmI, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use ldap3::{exop::WhoAmI, Ldap, LdapConnAsync, LdapConnSettings, Mod, Scope, SearchEntry};
 use snafu::prelude::*;
 use tokio::sync::RwLock;
 
+use std::collections::HashSet;
+use std::hash::Hash;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -125,6 +127,39 @@ impl YunohostLDAP {
         }
     }
 
+    /// Lists the entries matching `filter` under `query`, like [`YunohostLDAP::list`], but a failed
+    /// search is an error instead of an empty list. Use it when an empty result grants something,
+    /// such as deleting a user which is not the last admin.
+    ///
+    /// Errors when:
+    ///   - the search failed, or returned a non-success result code
+    pub async fn search_checked<
+        'a,
+        S: AsRef<str> + Send + Sync + 'a,
+        A: AsRef<[S]> + Send + Sync + std::fmt::Debug + 'a,
+    >(
+        &self,
+        query: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: A,
+    ) -> Result<Vec<SearchEntry>, Error> {
+        self.keepalive().await?;
+        let mut ldap = self.inner.write().await;
+        ldap.with_timeout(self.timeout);
+
+        debug!("LDAP: Query checked list {query}");
+        debug!("LDAP: Query checked list filter: {filter}");
+        debug!("LDAP: Query checked list attrs: {attrs:?}");
+
+        let (res, _) = ldap
+            .search(query, scope, filter, attrs)
+            .await
+            .and_then(|res| res.success())
+            .context(LdapSearchSnafu)?;
+        Ok(res.into_iter().map(SearchEntry::construct).collect())
+    }
+
     pub async fn search<'a, S: AsRef<str> + Send + Sync + 'a, A: AsRef<[S]> + Send + Sync + 'a>(
         &self,
         query: &str,
@@ -167,4 +202,80 @@ impl YunohostLDAP {
             Ok(None)
         }
     }
+
+    /// Binds as root with SASL EXTERNAL over the ldapi socket, which is required to change the
+    /// database. Only works when the current process runs as root.
+    async fn bind_root(&self, ldap: &mut Ldap) -> Result<(), Error> {
+        ldap.with_timeout(self.timeout);
+        ldap.sasl_external_bind()
+            .await
+            .and_then(|res| res.success())
+            .context(LdapBindSnafu)?;
+        Ok(())
+    }
+
+    /// Adds a new entry `dn` with the attributes `attrs`.
+    ///
+    /// Errors when:
+    ///   - binding as root failed
+    ///   - adding the entry failed, for example because it already exists or violates the schema
+    pub async fn add<S: AsRef<[u8]> + Eq + Hash + Send + Sync>(
+        &self,
+        dn: &str,
+        attrs: Vec<(S, HashSet<S>)>,
+    ) -> Result<(), Error> {
+        self.keepalive().await?;
+        let mut ldap = self.inner.write().await;
+        self.bind_root(&mut ldap).await?;
+
+        debug!("LDAP: Add {dn}");
+        ldap.with_timeout(self.timeout);
+        ldap.add(dn, attrs)
+            .await
+            .and_then(|res| res.success())
+            .context(LdapAddSnafu { dn })?;
+        Ok(())
+    }
+
+    /// Applies the modifications `mods` to the attributes of the entry `dn`.
+    ///
+    /// Errors when:
+    ///   - binding as root failed
+    ///   - the entry does not exist, or the modifications are invalid
+    pub async fn modify<S: AsRef<[u8]> + Eq + Hash + Send + Sync>(
+        &self,
+        dn: &str,
+        mods: Vec<Mod<S>>,
+    ) -> Result<(), Error> {
+        self.keepalive().await?;
+        let mut ldap = self.inner.write().await;
+        self.bind_root(&mut ldap).await?;
+
+        debug!("LDAP: Modify {dn}");
+        ldap.with_timeout(self.timeout);
+        ldap.modify(dn, mods)
+            .await
+            .and_then(|res| res.success())
+            .context(LdapModifySnafu { dn })?;
+        Ok(())
+    }
+
+    /// Deletes the entry `dn`, which must not have children.
+    ///
+    /// Errors when:
+    ///   - binding as root failed
+    ///   - the entry does not exist, or has children
+    pub async fn delete(&self, dn: &str) -> Result<(), Error> {
+        self.keepalive().await?;
+        let mut ldap = self.inner.write().await;
+        self.bind_root(&mut ldap).await?;
+
+        debug!("LDAP: Delete {dn}");
+        ldap.with_timeout(self.timeout);
+        ldap.delete(dn)
+            .await
+            .and_then(|res| res.success())
+            .context(LdapDeleteSnafu { dn })?;
+        Ok(())
+    }
 }
@@ -0,0 +1,262 @@
+//! The lock shared by all yunohost commands, compatible with the Python moulinette.
+//!
+//! The lock is a file containing the PID of the process holding it. A process can always take the
+//! lock when one of its ancestors holds it, so that yunohost commands called from hooks don't
+//! wait forever for their parent. A lock left behind by a dead process is considered stale and removed,
+//! under an exclusive `flock` so that concurrent processes never remove a lock taken again in the meantime.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use snafu::prelude::*;
+
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::error::*;
+
+pub const MOULINETTE_LOCK_FILE: &str = "/var/run/moulinette_yunohost.lock";
+
+/// How long to wait between two attempts at taking the lock.
+const LOCK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The lock on [`MOULINETTE_LOCK_FILE`], released when dropped.
+#[derive(Debug)]
+pub struct MoulinetteLock {
+    path: Utf8PathBuf,
+    /// Whether the lock was taken by this process, or it was already held by a parent process
+    owned: bool,
+}
+
+impl MoulinetteLock {
+    /// Takes the lock, waiting at most `timeout` for another yunohost command to release it.
+    ///
+    /// Errors when:
+    /// - the lock is still held by another process after `timeout`
+    /// - the lock file could not be read, created or removed
+    pub fn acquire(timeout: Duration) -> Result<Self, Error> {
+        Self::acquire_at(Utf8Path::new(MOULINETTE_LOCK_FILE), timeout)
+    }
+
+    fn acquire_at(path: &Utf8Path, timeout: Duration) -> Result<Self, Error> {
+        let start = Instant::now();
+        let mut waiting = false;
+
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+            {
+                Ok(mut file) => {
+                    file.write_all(std::process::id().to_string().as_bytes())
+                        .context(LockWriteSnafu {
+                            path: path.to_path_buf(),
+                        })?;
+                    debug!("Lock {path} acquired");
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                        owned: true,
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(e).context(LockWriteSnafu {
+                        path: path.to_path_buf(),
+                    });
+                }
+            }
+
+            let lock_pids = _lock_pids(path)?;
+            if _is_son_of(&lock_pids) {
+                debug!("Lock {path} is already held by a parent process");
+                return Ok(Self {
+                    path: path.to_path_buf(),
+                    owned: false,
+                });
+            }
+
+            // The lock file may be empty for a short time while another process takes it
+            if !lock_pids.is_empty() && !lock_pids.iter().any(|pid| _pid_exists(*pid)) {
+                warn!("Removing stale lock {path} left by dead process(es) {lock_pids:?}");
+                _remove_stale_lock(path, &lock_pids)?;
+                continue;
+            }
+
+            if start.elapsed() >= timeout {
+                return Err(Error::LockAlreadyRunning { pids: lock_pids });
+            }
+
+            if !waiting {
+                info!("Waiting for another yunohost command to finish (PID {lock_pids:?})...");
+                waiting = true;
+            }
+            sleep(LOCK_INTERVAL);
+        }
+    }
+}
+
+impl Drop for MoulinetteLock {
+    fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
+
+        match fs::remove_file(&self.path) {
+            Ok(()) => debug!("Lock {} released", self.path),
+            Err(e) => warn!("Failed to release lock {}: {e}", self.path),
+        }
+    }
+}
+
+/// Reads the PIDs in the lock file. A missing lock file contains no PID.
+fn _lock_pids(path: &Utf8Path) -> Result<Vec<u32>, Error> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => {
+            return Err(e).context(LockReadSnafu {
+                path: path.to_path_buf(),
+            })
+        }
+    };
+
+    Ok(_parse_pids(&content))
+}
+
+fn _parse_pids(content: &str) -> Vec<u32> {
+    content
+        .split_whitespace()
+        .filter_map(|pid| pid.parse().ok())
+        .collect()
+}
+
+/// Removes the lock file, only if it still contains the `stale_pids`.
+///
+/// Several processes may find the same stale lock, and one of them may remove it and take the lock
+/// before another one removes it too. So the lock file is checked again and removed while holding an
+/// exclusive `flock` on it, and only if `path` still points to that very file.
+///
+/// Errors when:
+/// - the lock file could not be opened, locked, read or removed
+fn _remove_stale_lock(path: &Utf8Path, stale_pids: &[u32]) -> Result<(), Error> {
+    let context = || LockRemoveSnafu {
+        path: path.to_path_buf(),
+    };
+
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        // Someone else removed it first
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context(context()),
+    };
+    // Released when the file is closed
+    file.lock().context(context())?;
+
+    let mut content = String::new();
+    file.read_to_string(&mut content).context(context())?;
+    let locked = file.metadata().context(context())?;
+    let same_file = match fs::metadata(path) {
+        Ok(current) => current.dev() == locked.dev() && current.ino() == locked.ino(),
+        Err(e) if e.kind() == ErrorKind::NotFound => false,
+        Err(e) => return Err(e).context(context()),
+    };
+
+    if !same_file || _parse_pids(&content) != stale_pids {
+        debug!("Lock {path} was taken again in the meantime");
+        return Ok(());
+    }
+
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e).context(context()),
+        _ => Ok(()),
+    }
+}
+
+/// Whether one of the `lock_pids` is the current process, or one of its ancestors.
+fn _is_son_of(lock_pids: &[u32]) -> bool {
+    let mut pid = Some(std::process::id());
+    while let Some(current) = pid {
+        if lock_pids.contains(&current) {
+            return true;
+        }
+        pid = _parent_pid(current);
+    }
+    false
+}
+
+/// Reads the parent PID of a process from `/proc/<pid>/stat`, or `None` for init and unknown processes.
+fn _parent_pid(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The process name is between parentheses and may contain spaces, the parent PID is the second field after it
+    let (_, fields) = stat.rsplit_once(')')?;
+    let ppid = fields.split_whitespace().nth(1)?.parse().ok()?;
+    (ppid != 0).then_some(ppid)
+}
+
+fn _pid_exists(pid: u32) -> bool {
+    Utf8Path::new(&format!("/proc/{pid}")).exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_reentrant_and_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::from_path_buf(dir.path().join("test.lock")).unwrap();
+
+        {
+            let lock = MoulinetteLock::acquire_at(&path, Duration::ZERO).unwrap();
+            assert!(lock.owned);
+            assert_eq!(vec![std::process::id()], _lock_pids(&path).unwrap());
+
+            // Held by ourselves
+            let nested = MoulinetteLock::acquire_at(&path, Duration::ZERO).unwrap();
+            assert!(!nested.owned);
+        }
+        assert!(!path.exists());
+
+        // PID 0 is never a running process
+        fs::write(&path, "0").unwrap();
+        let lock = MoulinetteLock::acquire_at(&path, Duration::ZERO).unwrap();
+        assert!(lock.owned);
+    }
+
+    #[test]
+    fn lock_stale_taken_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::from_path_buf(dir.path().join("test.lock")).unwrap();
+
+        // Another process removed the stale lock and took it before us
+        fs::write(&path, std::process::id().to_string()).unwrap();
+        _remove_stale_lock(&path, &[0]).unwrap();
+        assert!(path.exists());
+
+        _remove_stale_lock(&path, &[std::process::id()]).unwrap();
+        assert!(!path.exists());
+
+        // Already removed
+        _remove_stale_lock(&path, &[0]).unwrap();
+    }
+
+    #[test]
+    fn lock_held_by_other_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::from_path_buf(dir.path().join("test.lock")).unwrap();
+
+        // A child process is not a parent process
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        fs::write(&path, child.id().to_string()).unwrap();
+        let res = MoulinetteLock::acquire_at(&path, Duration::ZERO);
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(matches!(res, Err(Error::LockAlreadyRunning { .. })));
+    }
+}
@@ -2,6 +2,7 @@ pub mod apt;
 pub mod configpanel;
 pub mod credentials;
 pub mod distro;
+pub mod domain;
 pub mod file;
 pub mod form;
 pub mod group;
@@ -9,6 +10,7 @@ pub mod hook;
 pub mod i18n;
 pub mod ldap;
 pub mod legacy;
+pub mod lock;
 pub mod mail;
 pub mod output;
 pub mod permission;
@@ -16,5 +18,7 @@ pub mod process;
 pub mod regenconf;
 pub mod service;
 pub mod settings;
+pub mod ssl;
 pub mod string;
+pub mod template;
 pub mod user;
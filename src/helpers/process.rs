@@ -1,9 +1,11 @@
 use snafu::prelude::*;
 
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs::metadata;
+use std::io::Write;
 use std::os::unix::fs::MetadataExt;
-use std::process::{Command, Output};
+use std::process::{Command, Output, Stdio};
 
 use crate::error::*;
 
@@ -52,3 +54,73 @@ where
             args,
         })
 }
+
+/// Runs a command with some arguments and extra environment variables, and returns the [`Output`].
+/// Does not error when the command returns a non-zero exit code.
+///
+/// The environment of the current process is inherited, and `env` is added on top.
+///
+/// Errors when:
+///   - `command` does not exist
+///   - the program does not have permission to execute `command`
+pub fn cmd_env<I, S>(
+    command: &str,
+    args: I,
+    env: &BTreeMap<String, String>,
+) -> Result<Output, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<String> = args
+        .into_iter()
+        .map(|x| x.as_ref().to_str().unwrap().to_string())
+        .collect();
+    Command::new(command)
+        .args(&args)
+        .envs(env)
+        .output()
+        .context(CmdSnafu {
+            cmd: command.to_string(),
+            args,
+        })
+}
+
+/// Runs a command with some arguments, writing `stdin` to its standard input, and returns the
+/// [`Output`]. Does not error when the command returns a non-zero exit code.
+///
+/// Secrets should be passed this way rather than as arguments, which are visible to all users
+/// in the process list, and appear in the error message.
+///
+/// Errors when:
+///   - `command` does not exist
+///   - the program does not have permission to execute `command`
+///   - `command` exited before reading its standard input
+pub fn cmd_stdin<I, S>(command: &str, args: I, stdin: &[u8]) -> Result<Output, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<String> = args
+        .into_iter()
+        .map(|x| x.as_ref().to_str().unwrap().to_string())
+        .collect();
+    let context = CmdSnafu {
+        cmd: command.to_string(),
+        args: args.clone(),
+    };
+
+    let mut child = Command::new(command)
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(context.clone())?;
+    // UNWRAP NOTE: The standard input is piped above. It is dropped after writing, so that the
+    // command doesn't wait for more input.
+    let res = child.stdin.take().unwrap().write_all(stdin);
+    let output = child.wait_with_output().context(context.clone())?;
+    res.context(context)?;
+    Ok(output)
+}
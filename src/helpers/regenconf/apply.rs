@@ -0,0 +1,776 @@
+//! The regen-conf engine, applying pending configuration to the system.
+//!
+//! This follows the Python `regen_conf` function: the `pre` phase of every `conf_regen` hook generates
+//! pending configuration in [`PENDING_CONF_DIR`], which is then compared to the system configuration
+//! and the hashes known in [`REGEN_CONF_FILE`], to decide which files to install (see [`plan_conf_file`]).
+//! Finally, the `post` phase of the hooks is called with the list of files that changed.
+//!
+//! Categories are independent from each other, so their `pre` phase and the hashes of their files are
+//! computed in parallel (see [`RegenConfJobs`]). The logs of a category are only printed once it's done,
+//! in the order of the hooks priority, so that categories don't interleave their output. Changes are then
+//! applied, and the `post` phases run, one category at a time in the order of the hooks priority.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use log::Level;
+use rayon::prelude::*;
+use serde::Serialize;
+use snafu::prelude::*;
+
+use std::collections::BTreeMap;
+use std::fs::remove_dir_all;
+
+use super::*;
+use crate::helpers::{domain::YunohostDomain, hook::*, lock::MoulinetteLock};
+
+/// This one is special, see [`_sshd_config_legacy`].
+const SSHD_CONFIG: &str = "/etc/ssh/sshd_config";
+
+/// How many categories are processed in parallel, `None` for one per CPU.
+pub type RegenConfJobs = Option<usize>;
+
+/// Log lines kept back until a category is done, so that categories running in parallel don't interleave their output.
+type CategoryLogs = Vec<(Level, String)>;
+
+/// The outcome of regen-conf for a single configuration file.
+#[derive(Clone, Debug, Serialize)]
+pub struct RegenConfFileResult {
+    pub status: RegenConfStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// See [`RegenConfPlan::diff_path`]
+    #[serde(skip)]
+    pub diff_path: Option<Utf8PathBuf>,
+}
+
+/// The outcome of regen-conf for a category.
+///
+/// `applied` files were successfully handled, while `pending` files were kept back.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RegenCategoryResult {
+    pub applied: BTreeMap<RelativeConfFile, RegenConfFileResult>,
+    pub pending: BTreeMap<RelativeConfFile, RegenConfFileResult>,
+}
+
+/// Regenerates the configuration for the `names` categories (all categories when empty).
+///
+/// Manually modified/removed files are kept as is, unless `force` is true. Before being overwritten
+/// or removed, system configuration files are saved in [`BACKUP_CONF_DIR`] (see [`backup_conf_file`]).
+///
+/// When `diff_context` is set, the diff between the system and pending conf of every file is reported,
+/// with this many lines of context (see [`DIFF_CONTEXT`]). Up to `jobs` categories are generated in parallel.
+///
+/// The whole operation holds the [`MoulinetteLock`], so it can't interleave with other yunohost commands.
+///
+/// Errors when:
+/// - the lock is held by another yunohost command
+/// - the threads for parallel categories could not be started
+/// - cleaning or creating the pending directories failed
+/// - listing the domains for the hooks environment failed
+/// - the `pre` phase failed for every requested category
+/// - reading pending configuration or saving the new hashes failed
+pub fn regen_conf(
+    names: &[String],
+    diff_context: Option<usize>,
+    force: bool,
+    jobs: RegenConfJobs,
+) -> Result<BTreeMap<String, RegenCategoryResult>, RegenConfError> {
+    let _lock = MoulinetteLock::acquire(LOCK_TIMEOUT).context(RegenConfLockSnafu)?;
+    let pool = _thread_pool(jobs)?;
+    let pending_dir = Utf8PathBuf::from(PENDING_CONF_DIR);
+
+    // Clean pending conf directory
+    if path(&pending_dir).is_dir() {
+        let to_clean: Vec<Utf8PathBuf> = if names.is_empty() {
+            vec![pending_dir.clone()]
+        } else {
+            names.iter().map(|name| pending_dir.join(name)).collect()
+        };
+
+        for dir in to_clean {
+            if path(&dir).is_dir() {
+                remove_dir_all(&dir).context(RegenConfPendingCleanSnafu { path: dir.clone() })?;
+            }
+        }
+    }
+
+    let hooks = _regen_conf_hooks(names);
+    let env = _hooks_env()?;
+    let succeeded = _run_pre_hooks(&hooks, &pending_dir, &env, &pool)?;
+
+    let mut regen_conf_file = _get_regenconf_infos()?;
+    let mut result: BTreeMap<String, RegenCategoryResult> = BTreeMap::new();
+
+    let mut to_plan: Vec<(
+        String,
+        BTreeMap<RelativeConfFile, PendingConfFile>,
+        RegenCategoryConfFiles,
+    )> = vec![];
+    for (category, pending_files) in _get_pending_conf_in(&pending_dir, &succeeded)? {
+        let mut conf_hashes = _get_regenconf_hashes(&regen_conf_file, &category);
+
+        if _sshd_config_legacy(&category, &pending_files, &mut conf_hashes)? {
+            _update_conf_hashes(&mut regen_conf_file, &category, conf_hashes.clone());
+            regen_conf_file.save().context(RegenConfSaveHashesSnafu {
+                category: category.to_string(),
+            })?;
+        }
+
+        to_plan.push((category, pending_files, conf_hashes));
+    }
+
+    // Everything is checked before touching the system, so that a broken file does not leave
+    // the category half-applied
+    let planned_categories = _plan_categories(to_plan, force, names, diff_context, &pool)?;
+
+    for (category, mut conf_hashes, planned) in planned_categories {
+        debug!("Processing pending configuration for category {category}");
+        let mut category_result = RegenCategoryResult::default();
+
+        for PlannedConfFile {
+            system_path,
+            pending,
+            new_hash,
+            mut plan,
+        } in planned
+        {
+            let absolute = system_path.to_absolute();
+            let pending_path = pending.as_ref().map(|pending| pending.path());
+            debug!("Processing pending conf {pending_path:?} to system conf {absolute}");
+            debug!("> {}", plan.reason);
+
+            // System conf is already up-to-date
+            let Some(status) = plan.status else {
+                if let Some(pending_path) = pending_path {
+                    _remove_pending(pending_path);
+                }
+                continue;
+            };
+
+            match status {
+                RegenConfStatus::Modified => {
+                    warn!("The configuration file {absolute} has been manually modified and will not be updated");
+                }
+                RegenConfStatus::Removed => {
+                    info!("The configuration file {absolute} has been manually removed and will not be created");
+                }
+                RegenConfStatus::New => {
+                    info!("The configuration file {absolute} is now managed by the {category} category");
+                }
+                RegenConfStatus::ForceCreated => {
+                    info!("The configuration file {absolute} has been manually removed and will be created again");
+                }
+                RegenConfStatus::ForceUpdated => {
+                    warn!("The configuration file {absolute} has been manually modified and will be overwritten");
+                }
+                RegenConfStatus::ForceRemoved => {
+                    info!("The configuration file {absolute} is not generated anymore and will be removed");
+                }
+                _ => {}
+            }
+
+            let regenerated = match plan.action {
+                // Nothing to backup when the file is created
+                RegenConfAction::Create => _process_regen_conf(&absolute, pending.as_ref(), false),
+                RegenConfAction::Update => _process_regen_conf(&absolute, pending.as_ref(), true),
+                RegenConfAction::Remove => _process_regen_conf(&absolute, None, true),
+                RegenConfAction::Unchanged => true,
+                RegenConfAction::SkipManuallyModified => false,
+            };
+
+            let file_result = RegenConfFileResult {
+                status,
+                diff: plan.diff.take(),
+                diff_path: plan.diff_path.take(),
+            };
+            if regenerated && plan.is_applied() {
+                category_result
+                    .applied
+                    .insert(system_path.clone(), file_result);
+                conf_hashes.conffiles.insert(system_path, new_hash);
+                if let Some(pending_path) = pending_path {
+                    _remove_pending(pending_path);
+                }
+            } else {
+                category_result.pending.insert(system_path, file_result);
+            }
+        }
+
+        if category_result.applied.is_empty() && category_result.pending.is_empty() {
+            debug!("The configuration is already up-to-date for category {category}");
+            continue;
+        } else if category_result.pending.is_empty() {
+            info!("The configuration has been updated for category {category}");
+        }
+
+        if !category_result.applied.is_empty() {
+            _update_conf_hashes(&mut regen_conf_file, &category, conf_hashes);
+            regen_conf_file.save().context(RegenConfSaveHashesSnafu {
+                category: category.to_string(),
+            })?;
+        }
+
+        result.insert(category, category_result);
+    }
+
+    // Execute hooks for post-regen
+    for hook in hooks.iter().filter(|hook| succeeded.contains(&hook.name)) {
+        // Coma-separated applied changes for the category
+        let regen_conf_files = result
+            .get(&hook.name)
+            .map(|category_result| {
+                category_result
+                    .applied
+                    .keys()
+                    .map(|system_path| system_path.to_absolute().to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .unwrap_or_default();
+
+        // element 2 and 3 with empty string is because of legacy...
+        let mut logs = CategoryLogs::new();
+        _run_hook(hook, &["post", "", "", &regen_conf_files], &env, &mut logs);
+        _print_logs(logs);
+    }
+
+    Ok(result)
+}
+
+/// Reports what [`regen_conf`] would do for the `names` categories (all categories when empty),
+/// without changing anything on the system.
+///
+/// The `pre` phase of the hooks is run in a scratch directory instead of [`PENDING_CONF_DIR`], and
+/// neither the system configuration nor [`REGEN_CONF_FILE`] are written. Unchanged files are reported as well.
+///
+/// When `diff_context` is set, diffs are reported like in [`regen_conf`]. Up to `jobs` categories are
+/// generated in parallel.
+///
+/// Errors when:
+/// - the threads for parallel categories could not be started
+/// - creating the scratch directory failed
+/// - listing the domains for the hooks environment failed
+/// - the `pre` phase failed for every requested category
+/// - reading pending configuration failed
+pub fn regen_conf_dry_run(
+    names: &[String],
+    diff_context: Option<usize>,
+    force: bool,
+    jobs: RegenConfJobs,
+) -> Result<BTreeMap<String, BTreeMap<RelativeConfFile, RegenConfPlan>>, RegenConfError> {
+    let pool = _thread_pool(jobs)?;
+
+    // The scratch directory is removed when dropped
+    let scratch_dir = tempfile::Builder::new()
+        .prefix("yunohost-regenconf-")
+        .tempdir()
+        .context(RegenConfDryRunScratchSnafu)?;
+    let pending_dir =
+        StrPath::from_path(scratch_dir.path()).context(RegenConfDryRunScratchPathSnafu)?;
+
+    let hooks = _regen_conf_hooks(names);
+    let env = _hooks_env()?;
+    let succeeded = _run_pre_hooks(&hooks, &pending_dir, &env, &pool)?;
+
+    let regen_conf_file = _get_regenconf_infos()?;
+
+    let mut to_plan: Vec<(
+        String,
+        BTreeMap<RelativeConfFile, PendingConfFile>,
+        RegenCategoryConfFiles,
+    )> = vec![];
+    for (category, pending_files) in _get_pending_conf_in(&pending_dir, &succeeded)? {
+        let mut conf_hashes = _get_regenconf_hashes(&regen_conf_file, &category);
+        _sshd_config_legacy(&category, &pending_files, &mut conf_hashes)?;
+        to_plan.push((category, pending_files, conf_hashes));
+    }
+
+    Ok(
+        _plan_categories(to_plan, force, names, diff_context, &pool)?
+            .into_iter()
+            .map(|(category, _, planned)| {
+                let category_result: BTreeMap<RelativeConfFile, RegenConfPlan> = planned
+                    .into_iter()
+                    .map(|planned| (planned.system_path, planned.plan))
+                    .collect();
+                (category, category_result)
+            })
+            .collect(),
+    )
+}
+
+/// Builds the thread pool running up to `jobs` categories in parallel.
+///
+/// Errors when:
+/// - the threads could not be started
+fn _thread_pool(jobs: RegenConfJobs) -> Result<rayon::ThreadPool, RegenConfError> {
+    rayon::ThreadPoolBuilder::new()
+        // 0 is one thread per CPU
+        .num_threads(jobs.unwrap_or(0))
+        .build()
+        .context(RegenConfThreadPoolSnafu)
+}
+
+/// Runs [`_plan_category`] for all categories in parallel, keeping their order and hashes.
+///
+/// Errors when:
+/// - planning a category failed, in which case the first error is returned
+fn _plan_categories(
+    to_plan: Vec<(
+        String,
+        BTreeMap<RelativeConfFile, PendingConfFile>,
+        RegenCategoryConfFiles,
+    )>,
+    force: bool,
+    names: &[String],
+    diff_context: Option<usize>,
+    pool: &rayon::ThreadPool,
+) -> Result<Vec<(String, RegenCategoryConfFiles, Vec<PlannedConfFile>)>, RegenConfError> {
+    pool.install(|| {
+        to_plan
+            .into_par_iter()
+            .map(|(category, pending_files, conf_hashes)| {
+                let planned = _plan_category(
+                    &category,
+                    pending_files,
+                    &conf_hashes,
+                    force,
+                    names,
+                    diff_context,
+                )?;
+                Ok((category, conf_hashes, planned))
+            })
+            .collect()
+    })
+}
+
+/// A configuration file of a category, and what to do with it.
+struct PlannedConfFile {
+    system_path: RelativeConfFile,
+    /// The pending conf, or `None` for a stale file which is not generated anymore
+    pending: Option<PendingConfFile>,
+    new_hash: Option<String>,
+    plan: RegenConfPlan,
+}
+
+/// Decides what to do with all the pending and stale files of a category, with their diff
+/// if `diff_context` is set.
+///
+/// Errors when:
+/// - reading a pending or system conf failed
+fn _plan_category(
+    category: &str,
+    pending_files: BTreeMap<RelativeConfFile, PendingConfFile>,
+    conf_hashes: &RegenCategoryConfFiles,
+    force: bool,
+    names: &[String],
+    diff_context: Option<usize>,
+) -> Result<Vec<PlannedConfFile>, RegenConfError> {
+    let mut planned: Vec<PlannedConfFile> = vec![];
+
+    for (system_path, pending) in _with_stale_files(pending_files, conf_hashes) {
+        let absolute = system_path.to_absolute();
+
+        let context = RegenConfProcessFileSnafu {
+            category: category.to_string(),
+            path: absolute.clone(),
+        };
+
+        let new_hash = match &pending {
+            Some(pending) => pending.new_hash(&absolute).context(context.clone())?,
+            None => None,
+        };
+        let removal = matches!(pending, Some(PendingConfFile::Removal(_)));
+        let mut plan = match &pending {
+            Some(PendingConfFile::Symlink { target, .. }) if new_hash.is_none() => {
+                let system_conf = path(&absolute);
+                plan_conf_symlink(
+                    system_conf.read_link().ok().as_ref().map(|t| t.as_path()),
+                    system_conf.exists() || system_conf.is_symlink(),
+                    target,
+                )
+            }
+            _ => _plan(
+                &system_path,
+                new_hash.as_deref(),
+                removal,
+                conf_hashes,
+                force,
+                names,
+            )
+            .context(context.clone())?,
+        };
+
+        if let Some(diff_context) = diff_context {
+            let content_path = pending
+                .as_ref()
+                .and_then(|pending| pending.content_path(&absolute));
+            plan.diff = Some(
+                _get_files_diff(&absolute, content_path.as_deref(), diff_context)
+                    .context(context)?,
+            );
+            plan.diff_path = content_path;
+        }
+
+        planned.push(PlannedConfFile {
+            system_path,
+            pending,
+            new_hash,
+            plan,
+        });
+    }
+
+    Ok(planned)
+}
+
+/// Lists the `conf_regen` hooks to run for the `names` categories, in order.
+fn _regen_conf_hooks(names: &[String]) -> Vec<Hook> {
+    let mut hooks = HookList::for_action("conf_regen").by_priority(names);
+    // Legacy category that may still be around on old systems
+    hooks.retain(|hook| hook.name != "glances");
+    hooks
+}
+
+/// The `pre` phase of a category.
+struct PreHooksRun {
+    category: String,
+    /// Whether at least one hook succeeded
+    success: bool,
+    /// Whether at least one hook failed
+    failure: bool,
+    logs: CategoryLogs,
+}
+
+/// Runs the `pre` phase of the hooks, generating pending conf in `pending_dir`.
+/// Returns the categories for which at least one hook succeeded.
+///
+/// Categories run in parallel in the `pool`, but the hooks of a category run one after the other.
+///
+/// Errors when:
+/// - creating the pending directory for a category failed
+/// - no hook succeeded at all
+fn _run_pre_hooks(
+    hooks: &[Hook],
+    pending_dir: &Utf8Path,
+    env: &BTreeMap<String, String>,
+    pool: &rayon::ThreadPool,
+) -> Result<Vec<String>, RegenConfError> {
+    // Categories in the order of their first hook
+    let mut categories: Vec<(&str, Vec<&Hook>)> = vec![];
+    for hook in hooks {
+        match categories.iter_mut().find(|(name, _)| *name == hook.name) {
+            Some((_, category_hooks)) => category_hooks.push(hook),
+            None => categories.push((&hook.name, vec![hook])),
+        }
+    }
+
+    let runs: Vec<Result<PreHooksRun, RegenConfError>> = pool.install(|| {
+        categories
+            .par_iter()
+            .map(|(category, category_hooks)| {
+                let category_pending_path = path(pending_dir.join(category));
+                category_pending_path
+                    .mkdir_p()
+                    .context(RegenConfPendingMkdirSnafu {
+                        category: category.to_string(),
+                    })?;
+
+                let mut logs = CategoryLogs::new();
+                let (mut success, mut failure) = (false, false);
+                for hook in category_hooks {
+                    // element 2 and 3 with empty string is because of legacy...
+                    if _run_hook(
+                        hook,
+                        &["pre", "", "", category_pending_path.as_str()],
+                        env,
+                        &mut logs,
+                    ) {
+                        success = true;
+                    } else {
+                        failure = true;
+                    }
+                }
+
+                Ok(PreHooksRun {
+                    category: category.to_string(),
+                    success,
+                    failure,
+                    logs,
+                })
+            })
+            .collect()
+    });
+
+    let mut succeeded: Vec<String> = vec![];
+    let mut failed: Vec<String> = vec![];
+
+    for run in runs {
+        let run = run?;
+        _print_logs(run.logs);
+
+        if run.success {
+            succeeded.push(run.category);
+        } else if run.failure {
+            failed.push(run.category);
+        }
+    }
+
+    if succeeded.is_empty() {
+        return Err(RegenConfError::RegenConfFailed {
+            categories: failed.join(", "),
+        });
+    }
+
+    Ok(succeeded)
+}
+
+/// Here we are doing some weird legacy shit
+/// The thing is, on some very old or specific setup, the sshd_config file
+/// was absolutely not managed by the regenconf ...
+/// But we now want to make sure that this file is managed.
+/// However, we don't want to overwrite a specific custom sshd_config
+/// which may make the admin unhappy ...
+/// So : if the hash for this file does not exists, set the hash as the
+/// hash of the pending configuration ...
+/// That way, the file will later appear as manually modified.
+///
+/// Returns whether the hashes were changed.
+///
+/// Errors when:
+/// - reading the pending sshd_config failed
+fn _sshd_config_legacy(
+    category: &str,
+    pending_files: &BTreeMap<RelativeConfFile, PendingConfFile>,
+    conf_hashes: &mut RegenCategoryConfFiles,
+) -> Result<bool, RegenConfError> {
+    let sshd_config = RelativeConfFile::from_relative(SSHD_CONFIG.trim_start_matches('/').into());
+    if category != "ssh" || conf_hashes.conffiles.contains_key(&sshd_config) {
+        return Ok(false);
+    }
+
+    if let Some(pending) = pending_files.get(&sshd_config) {
+        let hash =
+            pending
+                .new_hash(Utf8Path::new(SSHD_CONFIG))
+                .context(RegenConfProcessFileSnafu {
+                    category: category.to_string(),
+                    path: Utf8PathBuf::from(SSHD_CONFIG),
+                })?;
+        conf_hashes.conffiles.insert(sshd_config, hash);
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Adds the stale files to the pending files of a category.
+///
+/// `pending_files` contain files explicitly set by the current regen conf run, while `conf_hashes`
+/// contain all files known from the past runs. We compare these to get the list of stale hashes
+/// and flag the file as "should be removed" (without pending conf).
+fn _with_stale_files(
+    pending_files: BTreeMap<RelativeConfFile, PendingConfFile>,
+    conf_hashes: &RegenCategoryConfFiles,
+) -> BTreeMap<RelativeConfFile, Option<PendingConfFile>> {
+    let mut conf_files: BTreeMap<RelativeConfFile, Option<PendingConfFile>> = pending_files
+        .into_iter()
+        .map(|(system_path, pending)| (system_path, Some(pending)))
+        .collect();
+
+    for (system_path, hash) in &conf_hashes.conffiles {
+        if hash.is_some() && !conf_files.contains_key(system_path) {
+            conf_files.insert(system_path.clone(), None);
+        }
+    }
+
+    conf_files
+}
+
+/// Compares the system conf to its saved hash and `new_hash` to decide what to do. When the
+/// pending conf is a `removal` request, see [`plan_conf_removal`] instead.
+///
+/// A manually modified [`SSHD_CONFIG`] is only overwritten with `force` if the `ssh` category
+/// was explicitly requested, because a broken sshd config can lock the admin out of the server.
+///
+/// Errors when:
+/// - reading the system conf failed
+fn _plan(
+    system_path: &RelativeConfFile,
+    new_hash: Option<&str>,
+    removal: bool,
+    conf_hashes: &RegenCategoryConfFiles,
+    force: bool,
+    names: &[String],
+) -> Result<RegenConfPlan, RegenConfError> {
+    let system_hash = _calculate_hash(&system_path.to_absolute())?;
+    let saved_hash = conf_hashes.conffiles.get(system_path).cloned().flatten();
+
+    if removal {
+        return Ok(plan_conf_removal(
+            system_hash.as_deref(),
+            saved_hash.as_deref(),
+            force,
+        ));
+    }
+    let plan = plan_conf_file(
+        system_hash.as_deref(),
+        saved_hash.as_deref(),
+        new_hash,
+        force,
+    );
+
+    if plan.status == Some(RegenConfStatus::ForceUpdated)
+        && system_path.to_absolute() == SSHD_CONFIG
+        && !names.iter().any(|name| name == "ssh")
+    {
+        warn!("The ssh configuration has been manually modified, but you need to explicitly specify the 'ssh' category with --force to actually apply the changes");
+        return Ok(plan_conf_file(
+            system_hash.as_deref(),
+            saved_hash.as_deref(),
+            new_hash,
+            false,
+        ));
+    }
+
+    Ok(plan)
+}
+
+/// Builds the environment passed to the hooks.
+///
+/// The domains can only be listed once the postinstall is done, but the hooks that effectively
+/// need them are only called after the `installed` flag is set.
+fn _hooks_env() -> Result<BTreeMap<String, String>, RegenConfError> {
+    let mut env: BTreeMap<String, String> = BTreeMap::new();
+
+    if path("/etc/yunohost/installed").exists() {
+        env.insert(
+            "YNH_DOMAINS".to_string(),
+            YunohostDomain::list(false)
+                .context(RegenConfDomainsSnafu)?
+                .join(" "),
+        );
+        env.insert(
+            "YNH_MAIN_DOMAINS".to_string(),
+            YunohostDomain::list(true)
+                .context(RegenConfDomainsSnafu)?
+                .join(" "),
+        );
+    }
+
+    Ok(env)
+}
+
+/// Runs a hook, keeping its output in `logs`. Returns whether it succeeded.
+fn _run_hook(
+    hook: &Hook,
+    args: &[&str],
+    env: &BTreeMap<String, String>,
+    logs: &mut CategoryLogs,
+) -> bool {
+    match hook.exec(args, env) {
+        Ok(output) => {
+            for line in String::from_utf8_lossy(&output.stdout).lines() {
+                logs.push((Level::Debug, format!("{}: {line}", hook.name)));
+            }
+            for line in String::from_utf8_lossy(&output.stderr).lines() {
+                logs.push((Level::Warn, format!("{}: {line}", hook.name)));
+            }
+
+            if !output.status.success() {
+                logs.push((
+                    Level::Error,
+                    format!(
+                        "Hook {} failed during {} phase ({})",
+                        hook.path.display(),
+                        args[0],
+                        output.status
+                    ),
+                ));
+            }
+
+            output.status.success()
+        }
+        Err(e) => {
+            logs.push((
+                Level::Error,
+                format!("Failed to run hook {}: {e}", hook.path.display()),
+            ));
+            false
+        }
+    }
+}
+
+fn _print_logs(logs: CategoryLogs) {
+    for (level, line) in logs {
+        log!(level, "{line}");
+    }
+}
+
+/// Replaces a given system configuration file by a new one, or deletes it if
+/// `new_conf` is None or a removal. Returns whether the operation succeeded.
+///
+/// A pending symlink is installed as a symlink, and a pending regular file replaces the symlink
+/// that may have been installed previously, instead of overwriting its target.
+///
+/// When `save` is true, the existing system conf is backed up first.
+fn _process_regen_conf(
+    system_conf: &Utf8Path,
+    new_conf: Option<&PendingConfFile>,
+    save: bool,
+) -> bool {
+    let system_conf = path(system_conf);
+
+    if save && system_conf.is_file() {
+        match backup_conf_file(&system_conf) {
+            Ok(backup_path) => {
+                debug!("The configuration file {system_conf} has been backed up to {backup_path}");
+            }
+            Err(e) => {
+                warn!("Failed to backup conf {system_conf}, not regenerating it: {e}");
+                return false;
+            }
+        }
+    }
+
+    // UNWRAP NOTE: System conf is always an absolute path to a file, so it has a parent
+    let system_dir = path(system_conf.parent().unwrap());
+
+    let res = match new_conf {
+        None | Some(PendingConfFile::Removal(_)) => system_conf.file_remove().map(|_| {
+            debug!("The configuration file {system_conf} has been removed");
+        }),
+        Some(PendingConfFile::Regular(new_conf)) => system_dir
+            .mkdir_p()
+            .and_then(|_| {
+                if system_conf.is_symlink() {
+                    system_conf.file_remove()
+                } else {
+                    Ok(())
+                }
+            })
+            .and_then(|_| system_conf.install_atomic(&path(new_conf)))
+            .map(|_| {
+                debug!("The configuration file {system_conf} has been updated");
+            }),
+        Some(PendingConfFile::Symlink { target, .. }) => system_dir
+            .mkdir_p()
+            .and_then(|_| system_conf.symlink_to_target(target, true))
+            .map(|_| {
+                debug!("The configuration file {system_conf} has been linked to {target}");
+            }),
+    };
+
+    if let Err(e) = res {
+        warn!("Exception while trying to regenerate conf {system_conf}: {e}");
+        return false;
+    }
+
+    true
+}
+
+/// Removes a pending conf file which was handled. Failing to do so is not a problem
+/// because the pending dir is cleaned before every regen-conf.
+fn _remove_pending(pending_path: &Utf8Path) {
+    if let Err(e) = path(pending_path).file_remove() {
+        debug!("{e}");
+    }
+}
@@ -0,0 +1,230 @@
+//! Backups of the system configuration files overwritten or removed by regen-conf.
+//!
+//! Like in the Python version, a backup is a plain copy of the system conf in [`BACKUP_CONF_DIR`],
+//! at the same relative path, suffixed with the UTC time of the backup (eg.
+//! `/var/cache/yunohost/regenconf/backup/etc/nginx/nginx.conf-20240101.120000`).
+//! They can be listed with [`list_backups`], and put back on the system with [`restore_backup`].
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use snafu::prelude::*;
+
+use std::collections::BTreeMap;
+
+use super::*;
+use crate::helpers::lock::MoulinetteLock;
+
+/// The `strftime` format of the suffix of backup files.
+pub const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%d.%H%M%S";
+
+/// Saves a copy of the `system_conf` absolute path in [`BACKUP_CONF_DIR`], returning the path of the backup.
+///
+/// Errors when:
+/// - creating the backup directory failed
+/// - copying the system conf failed
+pub fn backup_conf_file(system_conf: &Utf8Path) -> Result<Utf8PathBuf, FileError> {
+    let relative = system_conf.as_str().trim_start_matches('/');
+    let timestamp = Utc::now().format(BACKUP_TIMESTAMP_FORMAT);
+    let backup_path = Utf8Path::new(BACKUP_CONF_DIR).join(format!("{relative}-{timestamp}"));
+
+    // UNWRAP NOTE: The backup path is in BACKUP_CONF_DIR, so it has a parent
+    path(backup_path.parent().unwrap()).mkdir_p()?;
+    path(system_conf).copy_file(&path(&backup_path))?;
+
+    Ok(backup_path)
+}
+
+/// A saved version of a system configuration file.
+#[derive(Clone, Debug, Serialize)]
+pub struct RegenConfBackup {
+    /// The time of the backup, in [`BACKUP_TIMESTAMP_FORMAT`]
+    pub timestamp: String,
+    /// The path of the backup file in [`BACKUP_CONF_DIR`]
+    pub path: Utf8PathBuf,
+}
+
+/// All the saved versions of a system configuration file, oldest first.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RegenConfFileBackups {
+    /// The category which manages this file, if it's still managed
+    pub category: Option<String>,
+    pub backups: Vec<RegenConfBackup>,
+}
+
+/// Lists the backups in [`BACKUP_CONF_DIR`], by system configuration file.
+///
+/// When `category` is set, only the files currently managed by this category are listed.
+///
+/// Errors when:
+/// - reading the backup directory failed
+/// - reading [`REGEN_CONF_FILE`] failed
+pub fn list_backups(
+    category: Option<&str>,
+) -> Result<BTreeMap<RelativeConfFile, RegenConfFileBackups>, RegenConfError> {
+    let mut res: BTreeMap<RelativeConfFile, RegenConfFileBackups> = BTreeMap::new();
+
+    if !path(BACKUP_CONF_DIR).is_dir() {
+        return Ok(res);
+    }
+
+    let regen_conf_file = _get_regenconf_infos()?;
+
+    let backup_files =
+        glob(&format!("{BACKUP_CONF_DIR}/**/*")).context(RegenConfBackupListSnafu)?;
+    for backup_path in backup_files {
+        if !backup_path.is_file() {
+            continue;
+        }
+
+        let Some((system_path, timestamp)) = _parse_backup_path(&backup_path) else {
+            debug!("Ignoring unknown file in backup directory: {backup_path}");
+            continue;
+        };
+
+        let file_category = _find_category(&regen_conf_file, &system_path);
+        if category.is_some() && file_category.as_deref() != category {
+            continue;
+        }
+
+        let file_backups = res.entry(system_path).or_default();
+        file_backups.category = file_category;
+        file_backups.backups.push(RegenConfBackup {
+            timestamp,
+            path: backup_path,
+        });
+    }
+
+    // Timestamps are sorted chronologically because of their format
+    for file_backups in res.values_mut() {
+        file_backups
+            .backups
+            .sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    }
+
+    Ok(res)
+}
+
+/// Puts back a saved version of the `system_conf` absolute path, and saves its hash in
+/// [`REGEN_CONF_FILE`] if the file is managed by a category. The latest backup is restored,
+/// unless a specific timestamp (in [`BACKUP_TIMESTAMP_FORMAT`]) is requested with `at`.
+///
+/// The current system conf is backed up before being replaced, so the restoration can be reverted as well.
+///
+/// Errors when:
+/// - the lock is held by another yunohost command
+/// - no such backup was found
+/// - the current system conf could not be backed up
+/// - the backup could not be copied to the system
+/// - saving the new hash failed
+pub fn restore_backup(
+    system_conf: &Utf8Path,
+    at: Option<&str>,
+) -> Result<RegenConfBackup, RegenConfError> {
+    let _lock = MoulinetteLock::acquire(LOCK_TIMEOUT).context(RegenConfLockSnafu)?;
+    let system_path = RelativeConfFile::from_absolute(system_conf.to_path_buf())?;
+    let mut all_backups = list_backups(None)?;
+
+    let backup = all_backups
+        .remove(&system_path)
+        .and_then(|file_backups| match at {
+            Some(at) => file_backups
+                .backups
+                .into_iter()
+                .find(|backup| backup.timestamp == at),
+            None => file_backups.backups.into_iter().last(),
+        })
+        .context(RegenConfBackupNotFoundSnafu {
+            file: system_conf.to_path_buf(),
+            at: at.map(String::from),
+        })?;
+
+    let system_conf = path(system_conf);
+    if system_conf.is_file() {
+        backup_conf_file(&system_conf).context(RegenConfBackupRestoreSnafu {
+            file: system_conf.to_path_buf(),
+        })?;
+    }
+
+    // UNWRAP NOTE: System conf is always an absolute path to a file, so it has a parent
+    path(system_conf.parent().unwrap())
+        .mkdir_p()
+        .and_then(|_| path(&backup.path).read_bytes())
+        .and_then(|content| system_conf.write_atomic(content))
+        .context(RegenConfBackupRestoreSnafu {
+            file: system_conf.to_path_buf(),
+        })?;
+    info!(
+        "The configuration file {system_conf} has been restored from {}",
+        backup.path
+    );
+
+    let mut regen_conf_file = _get_regenconf_infos()?;
+    if let Some(category) = _find_category(&regen_conf_file, &system_path) {
+        let mut conf_hashes = _get_regenconf_hashes(&regen_conf_file, &category);
+        conf_hashes
+            .conffiles
+            .insert(system_path, _calculate_hash(&system_conf)?);
+        _update_conf_hashes(&mut regen_conf_file, &category, conf_hashes);
+        regen_conf_file
+            .save()
+            .context(RegenConfSaveHashesSnafu { category })?;
+    } else {
+        debug!("The configuration file {system_conf} is not managed by any category");
+    }
+
+    Ok(backup)
+}
+
+/// Finds which category manages a system configuration file.
+fn _find_category(
+    regen_conf_file: &RegenConfFile,
+    system_path: &RelativeConfFile,
+) -> Option<String> {
+    regen_conf_file
+        .categories
+        .iter()
+        .find(|(_, category_files)| category_files.conffiles.contains_key(system_path))
+        .map(|(category, _)| category.to_string())
+}
+
+/// Splits a backup file path into the system conf it was taken from, and its timestamp.
+///
+/// Returns `None` when the path is not a backup.
+fn _parse_backup_path(backup_path: &Utf8Path) -> Option<(RelativeConfFile, String)> {
+    let relative = backup_path.strip_prefix(BACKUP_CONF_DIR).ok()?;
+    let (system_path, timestamp) = relative.as_str().rsplit_once('-')?;
+
+    NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT).ok()?;
+
+    Some((
+        RelativeConfFile::from_relative(Utf8PathBuf::from(system_path)),
+        timestamp.to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_backup_path() {
+        let (system_path, timestamp) = _parse_backup_path(Utf8Path::new(
+            "/var/cache/yunohost/regenconf/backup/etc/nginx/conf.d/yunohost-admin.conf-20240101.120000",
+        ))
+        .unwrap();
+        assert_eq!(
+            Utf8Path::new("/etc/nginx/conf.d/yunohost-admin.conf"),
+            system_path.to_absolute()
+        );
+        assert_eq!("20240101.120000", timestamp);
+
+        assert!(_parse_backup_path(Utf8Path::new(
+            "/var/cache/yunohost/regenconf/backup/etc/ssh/ssh-config"
+        ))
+        .is_none());
+        assert!(
+            _parse_backup_path(Utf8Path::new("/etc/ssh/sshd_config-20240101.120000")).is_none()
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! Unified diffs of configuration files, compatible with Python's `difflib.unified_diff`.
+//!
+//! The `difflib` crate cannot be used here: its `SequenceMatcher` filters out the wrong elements
+//! for long files (the "autojunk" heuristic), and its `unified_diff` always adds a date to the headers.
+//! This is a port of the parts of Python's `difflib` needed to produce the exact same output.
+
+use camino::Utf8Path;
+
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+
+/// The number of context lines in diffs, by default.
+pub const DIFF_CONTEXT: usize = 3;
+
+const COLOR_RESET: &str = "\x1b[0m";
+const COLOR_HEADER: &str = "\x1b[1m";
+const COLOR_HUNK: &str = "\x1b[36m";
+const COLOR_REMOVED: &str = "\x1b[31m";
+const COLOR_ADDED: &str = "\x1b[32m";
+
+/// Compares two lists of lines (including their line terminator) and produces the lines of a unified diff,
+/// with `n` lines of context.
+///
+/// Like in Python, the `---`/`+++` headers are only produced when there is a difference, and lines
+/// without a terminator are not terminated in the output.
+pub fn unified_diff<S: AsRef<str>>(
+    a: &[S],
+    b: &[S],
+    fromfile: &str,
+    tofile: &str,
+    n: usize,
+) -> Vec<String> {
+    let a: Vec<&str> = a.iter().map(AsRef::as_ref).collect();
+    let b: Vec<&str> = b.iter().map(AsRef::as_ref).collect();
+
+    let mut res: Vec<String> = vec![];
+    for group in SequenceMatcher::new(&a, &b).get_grouped_opcodes(n) {
+        if res.is_empty() {
+            res.push(format!("--- {fromfile}\n"));
+            res.push(format!("+++ {tofile}\n"));
+        }
+
+        // UNWRAP NOTE: Groups are never empty
+        let (first, last) = (group.first().unwrap(), group.last().unwrap());
+        res.push(format!(
+            "@@ -{} +{} @@\n",
+            _format_range_unified(first.i1, last.i2),
+            _format_range_unified(first.j1, last.j2)
+        ));
+
+        for opcode in group {
+            if opcode.tag == Tag::Equal {
+                for line in &a[opcode.i1..opcode.i2] {
+                    res.push(format!(" {line}"));
+                }
+                continue;
+            }
+            if opcode.tag == Tag::Replace || opcode.tag == Tag::Delete {
+                for line in &a[opcode.i1..opcode.i2] {
+                    res.push(format!("-{line}"));
+                }
+            }
+            if opcode.tag == Tag::Replace || opcode.tag == Tag::Insert {
+                for line in &b[opcode.j1..opcode.j2] {
+                    res.push(format!("+{line}"));
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// Splits content into lines like Python's `readlines` in text mode: line terminators are kept,
+/// and `\r\n`/`\r` are read as `\n`.
+pub fn split_lines(content: &str) -> Vec<String> {
+    content
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .split_inclusive('\n')
+        .map(String::from)
+        .collect()
+}
+
+/// Prints a regen-conf diff (without headers) on stdout, with the `---`/`+++` headers for `system_path`
+/// and `new_path`, the file it was compared to (`/dev/null` for none). Colours are only used when stdout is a terminal.
+///
+/// Nothing is printed when the diff is empty.
+pub fn print_diff(system_path: &Utf8Path, new_path: Option<&Utf8Path>, diff: &str) {
+    if diff.is_empty() {
+        return;
+    }
+
+    let color = std::io::stdout().is_terminal();
+    let mut stdout = std::io::stdout().lock();
+
+    let new_path = new_path.unwrap_or(Utf8Path::new("/dev/null"));
+    let headers = [format!("--- {system_path}"), format!("+++ {new_path}")];
+    let lines = headers.iter().map(String::as_str).chain(diff.lines());
+    for (i, line) in lines.enumerate() {
+        let res = if !color {
+            writeln!(stdout, "{line}")
+        } else {
+            let line_color = if i < 2 {
+                COLOR_HEADER
+            } else if line.starts_with("@@") {
+                COLOR_HUNK
+            } else if line.starts_with('-') {
+                COLOR_REMOVED
+            } else if line.starts_with('+') {
+                COLOR_ADDED
+            } else {
+                ""
+            };
+
+            if line_color.is_empty() {
+                writeln!(stdout, "{line}")
+            } else {
+                writeln!(stdout, "{line_color}{line}{COLOR_RESET}")
+            }
+        };
+
+        // Stdout was closed, nothing left to do
+        if res.is_err() {
+            return;
+        }
+    }
+}
+
+/// Python's `difflib._format_range_unified`, converting a range to the "ed" format.
+fn _format_range_unified(start: usize, stop: usize) -> String {
+    // Per the diff spec at http://www.unix.org/single_unix_specification/
+    let mut beginning = start + 1;
+    let length = stop - start;
+    if length == 1 {
+        return beginning.to_string();
+    }
+    if length == 0 {
+        // empty ranges begin at line just before the range
+        beginning -= 1;
+    }
+    format!("{beginning},{length}")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tag {
+    Equal,
+    Replace,
+    Delete,
+    Insert,
+}
+
+/// A change from `a[i1..i2]` to `b[j1..j2]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Opcode {
+    tag: Tag,
+    i1: usize,
+    i2: usize,
+    j1: usize,
+    j2: usize,
+}
+
+impl Opcode {
+    fn new(tag: Tag, i1: usize, i2: usize, j1: usize, j2: usize) -> Self {
+        Self {
+            tag,
+            i1,
+            i2,
+            j1,
+            j2,
+        }
+    }
+}
+
+/// Python's `difflib.SequenceMatcher`, without junk, but with the "autojunk" heuristic.
+struct SequenceMatcher<'a> {
+    a: &'a [&'a str],
+    b: &'a [&'a str],
+    /// For every line in `b`, the indexes where it appears
+    b2j: HashMap<&'a str, Vec<usize>>,
+}
+
+impl<'a> SequenceMatcher<'a> {
+    fn new(a: &'a [&'a str], b: &'a [&'a str]) -> Self {
+        let mut b2j: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (j, line) in b.iter().enumerate() {
+            b2j.entry(line).or_default().push(j);
+        }
+
+        // Purge popular elements that are not junk
+        if b.len() >= 200 {
+            let ntest = b.len() / 100 + 1;
+            b2j.retain(|_, indexes| indexes.len() <= ntest);
+        }
+
+        Self { a, b, b2j }
+    }
+
+    /// Finds the longest matching block in `a[alo..ahi]` and `b[blo..bhi]`, as `(i, j, size)`.
+    fn find_longest_match(
+        &self,
+        alo: usize,
+        ahi: usize,
+        blo: usize,
+        bhi: usize,
+    ) -> (usize, usize, usize) {
+        let (mut besti, mut bestj, mut bestsize) = (alo, blo, 0);
+
+        // j2len[j] = length of longest match ending with a[i-1] and b[j]
+        let mut j2len: HashMap<usize, usize> = HashMap::new();
+        for i in alo..ahi {
+            let mut newj2len: HashMap<usize, usize> = HashMap::new();
+            for &j in self
+                .b2j
+                .get(self.a[i])
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+            {
+                // a[i] matches b[j]
+                if j < blo {
+                    continue;
+                }
+                if j >= bhi {
+                    break;
+                }
+                let k = j
+                    .checked_sub(1)
+                    .and_then(|previous| j2len.get(&previous))
+                    .unwrap_or(&0)
+                    + 1;
+                newj2len.insert(j, k);
+                if k > bestsize {
+                    besti = i + 1 - k;
+                    bestj = j + 1 - k;
+                    bestsize = k;
+                }
+            }
+            j2len = newj2len;
+        }
+
+        // Extend the best match with popular elements, which are not in b2j
+        while besti > alo && bestj > blo && self.a[besti - 1] == self.b[bestj - 1] {
+            besti -= 1;
+            bestj -= 1;
+            bestsize += 1;
+        }
+        while besti + bestsize < ahi
+            && bestj + bestsize < bhi
+            && self.a[besti + bestsize] == self.b[bestj + bestsize]
+        {
+            bestsize += 1;
+        }
+
+        (besti, bestj, bestsize)
+    }
+
+    /// Lists the matching blocks as `(i, j, size)`, ending with a `(len(a), len(b), 0)` sentinel.
+    fn get_matching_blocks(&self) -> Vec<(usize, usize, usize)> {
+        let (la, lb) = (self.a.len(), self.b.len());
+
+        let mut queue = vec![(0, la, 0, lb)];
+        let mut matching_blocks: Vec<(usize, usize, usize)> = vec![];
+        while let Some((alo, ahi, blo, bhi)) = queue.pop() {
+            let (i, j, k) = self.find_longest_match(alo, ahi, blo, bhi);
+            if k > 0 {
+                matching_blocks.push((i, j, k));
+                if alo < i && blo < j {
+                    queue.push((alo, i, blo, j));
+                }
+                if i + k < ahi && j + k < bhi {
+                    queue.push((i + k, ahi, j + k, bhi));
+                }
+            }
+        }
+        matching_blocks.sort();
+
+        // Collapse adjacent blocks
+        let (mut i1, mut j1, mut k1) = (0, 0, 0);
+        let mut non_adjacent: Vec<(usize, usize, usize)> = vec![];
+        for (i2, j2, k2) in matching_blocks {
+            if i1 + k1 == i2 && j1 + k1 == j2 {
+                k1 += k2;
+            } else {
+                if k1 > 0 {
+                    non_adjacent.push((i1, j1, k1));
+                }
+                (i1, j1, k1) = (i2, j2, k2);
+            }
+        }
+        if k1 > 0 {
+            non_adjacent.push((i1, j1, k1));
+        }
+
+        non_adjacent.push((la, lb, 0));
+        non_adjacent
+    }
+
+    /// Lists the changes to turn `a` into `b`.
+    fn get_opcodes(&self) -> Vec<Opcode> {
+        let (mut i, mut j) = (0, 0);
+        let mut opcodes: Vec<Opcode> = vec![];
+        for (ai, bj, size) in self.get_matching_blocks() {
+            let tag = if i < ai && j < bj {
+                Some(Tag::Replace)
+            } else if i < ai {
+                Some(Tag::Delete)
+            } else if j < bj {
+                Some(Tag::Insert)
+            } else {
+                None
+            };
+            if let Some(tag) = tag {
+                opcodes.push(Opcode::new(tag, i, ai, j, bj));
+            }
+
+            (i, j) = (ai + size, bj + size);
+            // the list of matching blocks is terminated by a sentinel with size 0
+            if size > 0 {
+                opcodes.push(Opcode::new(Tag::Equal, ai, i, bj, j));
+            }
+        }
+        opcodes
+    }
+
+    /// Isolates change clusters by eliminating ranges with no changes, keeping up to `n` lines of context.
+    fn get_grouped_opcodes(&self, n: usize) -> Vec<Vec<Opcode>> {
+        let mut codes = self.get_opcodes();
+        if codes.is_empty() {
+            codes.push(Opcode::new(Tag::Equal, 0, 1, 0, 1));
+        }
+
+        // Fixup leading and trailing groups if they show no changes
+        if let Some(first) = codes.first_mut() {
+            if first.tag == Tag::Equal {
+                first.i1 = first.i1.max(first.i2.saturating_sub(n));
+                first.j1 = first.j1.max(first.j2.saturating_sub(n));
+            }
+        }
+        if let Some(last) = codes.last_mut() {
+            if last.tag == Tag::Equal {
+                last.i2 = last.i2.min(last.i1 + n);
+                last.j2 = last.j2.min(last.j1 + n);
+            }
+        }
+
+        let nn = n + n;
+        let mut groups: Vec<Vec<Opcode>> = vec![];
+        let mut group: Vec<Opcode> = vec![];
+        for mut code in codes {
+            // End the current group and start a new one whenever
+            // there is a large range with no changes.
+            if code.tag == Tag::Equal && code.i2 - code.i1 > nn {
+                group.push(Opcode::new(
+                    Tag::Equal,
+                    code.i1,
+                    code.i2.min(code.i1 + n),
+                    code.j1,
+                    code.j2.min(code.j1 + n),
+                ));
+                groups.push(std::mem::take(&mut group));
+                code.i1 = code.i1.max(code.i2.saturating_sub(n));
+                code.j1 = code.j1.max(code.j2.saturating_sub(n));
+            }
+            group.push(code);
+        }
+        match group.as_slice() {
+            // Nothing changed at all
+            [] => {}
+            [only] if only.tag == Tag::Equal => {}
+            _ => groups.push(group),
+        }
+
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(a: &str, b: &str, n: usize) -> String {
+        unified_diff(&split_lines(a), &split_lines(b), "a", "b", n).join("")
+    }
+
+    #[test]
+    fn unified_diff_identical() {
+        assert_eq!("", diff("a\nb\n", "a\nb\n", 3));
+        assert_eq!("", diff("", "", 3));
+    }
+
+    #[test]
+    fn unified_diff_hunks() {
+        let a = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let b = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n";
+        assert_eq!(
+            "--- a\n+++ b\n@@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n@@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n",
+            diff(a, b, 3)
+        );
+        assert_eq!(
+            "--- a\n+++ b\n@@ -3 +3 @@\n-3\n+three\n@@ -12,0 +13 @@\n+13\n",
+            diff(a, b, 0)
+        );
+    }
+
+    #[test]
+    fn unified_diff_new_and_removed_file() {
+        assert_eq!(
+            "--- a\n+++ b\n@@ -0,0 +1,2 @@\n+a\n+b\n",
+            diff("", "a\nb\n", 3)
+        );
+        assert_eq!("--- a\n+++ b\n@@ -1 +0,0 @@\n-a\n", diff("a\n", "", 3));
+    }
+
+    #[test]
+    fn unified_diff_no_trailing_newline() {
+        assert_eq!(
+            "--- a\n+++ b\n@@ -1,2 +1,2 @@\n a\n-b+b\n",
+            diff("a\nb", "a\nb\n", 3)
+        );
+    }
+
+    #[test]
+    fn unified_diff_autojunk() {
+        // Closing braces are "popular" in the second file, so they are not used to find matches
+        let a: Vec<String> = (0..210)
+            .map(|i| {
+                if i % 3 == 0 {
+                    "}\n".to_string()
+                } else {
+                    format!("line {i}\n")
+                }
+            })
+            .collect();
+        let mut b = a.clone();
+        b.splice(0..5, vec!["}\n".to_string(); 5]);
+        assert_eq!(
+            "--- a\n+++ b\n@@ -1,8 +1,8 @@\n }\n-line 1\n-line 2\n-}\n-line 4\n+}\n+}\n+}\n+}\n line 5\n }\n line 7\n",
+            unified_diff(&a, &b, "a", "b", 3).join("")
+        );
+    }
+}
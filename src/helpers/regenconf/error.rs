@@ -0,0 +1,191 @@
+use camino::Utf8PathBuf;
+use snafu::prelude::*;
+
+use crate::{error::Error, helpers::file::error::FileError};
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub))]
+pub enum RegenConfError {
+    // mod.rs (RegenConfFile::load)
+    // Python: an unreadable or invalid file is silently treated as empty
+    #[snafu(display("Failed to read configuration hashes from {path}"))]
+    RegenConfFileRead {
+        path: Utf8PathBuf,
+        #[snafu(source(from(FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // mod.rs (RegenConfFile::load)
+    #[snafu(display("Failed to parse configuration hashes from {path}, the file is corrupted"))]
+    RegenConfFileParse {
+        path: Utf8PathBuf,
+        source: serde_yaml_ng::Error,
+    },
+
+    // mod.rs (RelativeConfFile::from_absolute, RelativeConfFile::from_basedir_prefix)
+    #[snafu(display("Configuration file {path} is not in {basedir}"))]
+    RelativeConfFilePrefix {
+        path: Utf8PathBuf,
+        basedir: Utf8PathBuf,
+    },
+
+    // mod.rs (PendingConfFile::to_system_path)
+    #[snafu(display("Pending configuration file {path} is not in a category of {pending_dir}"))]
+    PendingConfFileCategory {
+        path: Utf8PathBuf,
+        pending_dir: Utf8PathBuf,
+    },
+
+    // mod.rs (PendingConfFile::from_path)
+    #[snafu(display("Failed to read pending configuration file {path}"))]
+    PendingConfFileMetadata {
+        path: Utf8PathBuf,
+        source: std::io::Error,
+    },
+
+    // mod.rs (PendingConfFile::from_path)
+    #[snafu(display("Failed to read the target of pending configuration symlink {path}"))]
+    PendingConfFileReadLink {
+        path: Utf8PathBuf,
+        #[snafu(source(from(FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // mod.rs (_get_pending_conf_in)
+    #[snafu(display("Failed to list pending categories in {path}"))]
+    GetPendingConfReadDir {
+        path: Utf8PathBuf,
+        #[snafu(source(from(FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // mod.rs (_get_pending_conf_in)
+    #[snafu(display(
+        "Failed to read pending changes for category {category} from directory {path}"
+    ))]
+    GetPendingConfGlob {
+        category: String,
+        path: Utf8PathBuf,
+        #[snafu(source(from(FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // mod.rs (_get_pending_conf_in)
+    #[snafu(display("Failed to remove empty pending directory {path} for category {category}"))]
+    GetPendingConfCleanEmpty {
+        category: String,
+        path: Utf8PathBuf,
+        source: std::io::Error,
+    },
+
+    // mod.rs (_calculate_hash)
+    #[snafu(display("Failed to read {path} to calculate its hash"))]
+    CalculateHash {
+        path: Utf8PathBuf,
+        #[snafu(source(from(FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // mod.rs (_get_files_diff)
+    #[snafu(display("Failed to read {path} to compare it"))]
+    FilesDiffRead {
+        path: Utf8PathBuf,
+        #[snafu(source(from(FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // apply.rs (regen_conf)
+    #[snafu(display("Failed to clean pending directory {path}"))]
+    RegenConfPendingClean {
+        path: Utf8PathBuf,
+        source: std::io::Error,
+    },
+
+    // apply.rs (regen_conf), backup.rs (restore_backup)
+    #[snafu(display("Failed to lock the configuration"))]
+    RegenConfLock {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // apply.rs (_thread_pool)
+    #[snafu(display("Failed to start the threads to regenerate categories in parallel"))]
+    RegenConfThreadPool { source: rayon::ThreadPoolBuildError },
+
+    // apply.rs (regen_conf_dry_run)
+    #[snafu(display("Failed to create a scratch directory for pending conf"))]
+    RegenConfDryRunScratch { source: std::io::Error },
+
+    // apply.rs (regen_conf_dry_run)
+    #[snafu(display("The scratch directory for pending conf is not a valid UTF-8 path"))]
+    RegenConfDryRunScratchPath { source: FileError },
+
+    // apply.rs (_run_pre_hooks)
+    #[snafu(display("Failed to create pending directory for category {category}"))]
+    RegenConfPendingMkdir {
+        category: String,
+        #[snafu(source(from(FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // apply.rs (_hooks_env)
+    #[snafu(display("Failed to list domains for the hooks environment"))]
+    RegenConfDomains {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // apply.rs (_run_pre_hooks)
+    // Python: YunohostError("regenconf_failed")
+    #[snafu(display("Failed to regenerate configuration for category(ies): {categories}"))]
+    RegenConfFailed { categories: String },
+
+    // apply.rs (regen_conf), backup.rs (restore_backup)
+    #[snafu(display("Failed to save configuration hashes for category {category}"))]
+    RegenConfSaveHashes {
+        category: String,
+        #[snafu(source(from(FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // apply.rs (regen_conf, regen_conf_dry_run)
+    #[snafu(display("Failed to process configuration file {path} for category {category}"))]
+    RegenConfProcessFile {
+        category: String,
+        path: Utf8PathBuf,
+        #[snafu(source(from(RegenConfError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // backup.rs (list_backups)
+    #[snafu(display("Failed to read the backup directory"))]
+    RegenConfBackupList {
+        #[snafu(source(from(FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // backup.rs (restore_backup)
+    #[snafu(display("No backup found for configuration file {file}{}", at.as_ref().map(|at| format!(" at {at}")).unwrap_or_default()))]
+    RegenConfBackupNotFound {
+        file: Utf8PathBuf,
+        at: Option<String>,
+    },
+
+    // backup.rs (restore_backup)
+    #[snafu(display("Failed to restore configuration file {file}"))]
+    RegenConfBackupRestore {
+        file: Utf8PathBuf,
+        #[snafu(source(from(FileError, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    // status.rs (regen_conf_status)
+    #[snafu(display("No configuration file is tracked for category {category}"))]
+    RegenConfUnknownCategory { category: String },
+
+    // cmd/tools/regen_conf
+    #[snafu(display(
+        "This regenconf version can only run after 0025_global_settings_to_configpanel migration."
+    ))]
+    SettingsNotMigrated,
+}
@@ -0,0 +1,253 @@
+//! Shared pieces of the native `conf_regen` hooks (see `hooks/conf_regen/`).
+//!
+//! Every hook binary has the same command-line interface, so that the regen-conf engine can call them
+//! like the Python/bash hooks: `HOOK init`, `HOOK pre "" "" PENDING_DIR` and `HOOK post "" "" FILES`.
+//! A hook only implements [`ConfRegenHook`], and calls [`run_hook`] from its `main`.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+
+use std::fs::write;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    error::Error,
+    helpers::file::{error::FileError, glob, path},
+};
+
+/// Where the Yunohost package ships the configuration of each category, eg. `/usr/share/yunohost/conf/slapd`.
+pub const YUNOHOST_CONF_DIR: &str = "/usr/share/yunohost/conf";
+
+/// The three phases of a `conf_regen` hook.
+pub trait ConfRegenHook {
+    /// Initial setup of the category, run once when Yunohost is installed.
+    fn init() -> Result<(), Error>;
+
+    /// Generates the pending configuration of the category in `pending_dir`.
+    fn pre(pending_dir: Utf8PathBuf) -> Result<(), Error>;
+
+    /// Applies side-effects once the configuration was regenerated. `regen_conf_files` contains the
+    /// comma-separated list of the system configuration files which changed, if any.
+    fn post(regen_conf_files: Option<String>) -> Result<(), Error>;
+}
+
+#[derive(Clone, Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct ConfRegenHookCli {
+    /// Enable debug logging
+    #[arg(short, long)]
+    debug: bool,
+    #[command(subcommand)]
+    command: ConfRegenHookCommand,
+}
+
+/// The regen-conf engine calls hooks like `HOOK pre "" "" PENDING_DIR` and `HOOK post "" "" FILES`
+/// where the two empty arguments are there for legacy reasons, so we only keep the last argument.
+#[derive(Clone, Debug, Subcommand)]
+enum ConfRegenHookCommand {
+    Init,
+    Pre {
+        #[arg(required = true)]
+        args: Vec<Utf8PathBuf>,
+    },
+    Post {
+        #[arg()]
+        args: Vec<String>,
+    },
+}
+
+/// Parses the command-line arguments, sets up logging, and runs the requested phase of hook `H`.
+pub fn run_hook<H: ConfRegenHook>() -> Result<(), Error> {
+    let cli = ConfRegenHookCli::parse();
+
+    if cli.debug {
+        pretty_env_logger::formatted_builder()
+            .filter_level(LevelFilter::Debug)
+            .init();
+    } else {
+        pretty_env_logger::formatted_builder()
+            .filter_level(LevelFilter::Info)
+            .init();
+    }
+
+    match cli.command {
+        ConfRegenHookCommand::Init => H::init(),
+        // UNWRAP NOTE: At least one argument is required by clap
+        ConfRegenHookCommand::Pre { args } => H::pre(args.last().unwrap().to_path_buf()),
+        ConfRegenHookCommand::Post { args } => {
+            H::post(args.last().filter(|files| !files.is_empty()).cloned())
+        }
+    }
+}
+
+/// Checks whether `system_conf` is among the comma-separated `regen_conf_files` passed to the `post` phase.
+pub fn conf_changed(regen_conf_files: Option<&str>, system_conf: &str) -> bool {
+    regen_conf_files.is_some_and(|files| files.split(',').any(|file| file == system_conf))
+}
+
+/// Checks whether the kernel has IPv6 enabled, so that services can listen on IPv6 addresses.
+pub fn ipv6_available() -> bool {
+    path("/proc/net/if_inet6").is_file()
+}
+
+/// Copies a configuration file shipped by Yunohost from `src` to `dest` in the pending dir, creating the
+/// parent directories. The permissions of `src` are kept.
+///
+/// Errors when:
+/// - creating the parent directories of `dest` failed
+/// - copying the file failed
+pub fn install_conf(src: &Utf8Path, dest: &Utf8Path) -> Result<(), FileError> {
+    // UNWRAP NOTE: dest is a file in the pending dir, so it has a parent
+    path(dest.parent().unwrap()).mkdir_p()?;
+    path(src).copy_file(&path(dest))
+}
+
+/// Copies all the files of `plain_dir`, shipped by Yunohost without templating, to the `dest` directory
+/// in the pending dir. Does nothing when `plain_dir` does not exist.
+///
+/// Errors when:
+/// - listing the files of `plain_dir` failed
+/// - copying a file failed
+pub fn install_plain_confs(plain_dir: &Utf8Path, dest: &Utf8Path) -> Result<(), FileError> {
+    if !path(plain_dir).is_dir() {
+        return Ok(());
+    }
+
+    for plain in glob(&format!("{plain_dir}/*"))? {
+        // UNWRAP NOTE: Globbed files always have a file name
+        install_conf(&plain, &dest.join(plain.file_name().unwrap()))?;
+    }
+    Ok(())
+}
+
+/// Removes the configuration of the domains which don't exist anymore. The configuration of a domain is
+/// the file `DOMAIN{suffix}` in `system_dir`, so every such file whose domain is not kept by `keep` gets an
+/// empty file in `pending_dir`, which makes regen-conf remove it.
+///
+/// Errors when:
+/// - listing the files of `system_dir` failed
+/// - creating the pending directory, or writing an empty file in it, failed
+pub fn remove_old_domain_confs<F: Fn(&str) -> bool>(
+    system_dir: &str,
+    suffix: &str,
+    pending_dir: &Utf8Path,
+    keep: F,
+) -> Result<(), FileError> {
+    let pending_dir = pending_dir.join(system_dir.trim_start_matches('/'));
+    // Domains have at least one dot, which excludes the other files of the directory
+    for system_conf in glob(&format!("{system_dir}/*.*{suffix}"))? {
+        // UNWRAP NOTE: Globbed files always have a file name
+        let file_name = system_conf.file_name().unwrap();
+        if !keep(file_name.trim_end_matches(suffix)) {
+            path(&pending_dir).mkdir_p()?;
+            path(pending_dir.join(file_name)).write("")?;
+        }
+    }
+    Ok(())
+}
+
+// Use me with complete name whatever.foo.d in /etc/systemd
+pub struct SystemdOverride {
+    dir: PathBuf,
+}
+
+impl SystemdOverride {
+    fn service_dir_name(name: &str) -> String {
+        if name.ends_with(".service.d") {
+            name.to_string()
+        } else if name.ends_with(".service") {
+            format!("{name}.d")
+        } else {
+            format!("{name}.service.d")
+        }
+    }
+
+    /// Handle ynh-override.conf for service *name* in `/etc/systemd/system`.
+    ///
+    /// For use in `pending_dir`, see [`Self::new_service_pending`].
+    /// Acceptable `name` forms:
+    /// - `SERVICE`
+    /// - `SERVICE.service`
+    /// - `SERVICE.service.d`
+    pub fn new_service(name: &str) -> Self {
+        let name = Self::service_dir_name(name);
+
+        Self {
+            dir: PathBuf::from(&format!("/etc/systemd/system/{name}")),
+        }
+    }
+
+    /// Handle ynh-override.conf for service *name* in `pending_dir`.
+    ///
+    /// For use on the OS globally, see [`Self::new_service`].
+    /// Acceptable `name` forms:
+    /// - `SERVICE`
+    /// - `SERVICE.service`
+    /// - `SERVICE.service.d`
+    pub fn new_service_pending<U: AsRef<Path>>(name: &str, pending_dir: U) -> Self {
+        let name = Self::service_dir_name(name);
+
+        Self {
+            dir: pending_dir
+                .as_ref()
+                .join(format!("etc/systemd/system/{name}")),
+        }
+    }
+
+    /// Handle ynh-override.conf for other systemd config *name*.
+    ///
+    /// For use in `pending_dir`, see [`Self::new_other_pending`].
+    /// The foler `name` is appended without modification to `pending_dir/etc/systemd/`,
+    /// then the ynh-override.conf is placed there.
+    pub fn new_other(name: &str) -> Self {
+        Self {
+            dir: PathBuf::from(&format!("/etc/systemd/{name}")),
+        }
+    }
+
+    /// Handle ynh-override.conf for other systemd config *name*.
+    ///
+    /// For use in OS globally, see [`Self::new_other`].
+    /// The foler `name` is appended without modification to `/etc/systemd/`,
+    /// then the ynh-override.conf is placed there.
+    pub fn new_other_pending<U: AsRef<Path>>(name: &str, pending_dir: U) -> Self {
+        Self {
+            dir: pending_dir.as_ref().join(format!("etc/systemd/{name}")),
+        }
+    }
+
+    pub fn pre(&self, content: &str) {
+        path(self.dir.to_str().unwrap()).mkdir_p().unwrap();
+        write(self.dir.join("ynh-override.conf"), content).unwrap();
+    }
+
+    pub fn post(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_domain_confs() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let system_dir = dir.join("system/etc/nginx/conf.d");
+        let pending_dir = dir.join("pending");
+        path(&system_dir).mkdir_p().unwrap();
+        for file in ["example.com.conf", "old.example.com.conf", "global.conf"] {
+            path(system_dir.join(file)).write("server {}").unwrap();
+        }
+
+        remove_old_domain_confs(system_dir.as_str(), ".conf", &pending_dir, |domain| {
+            domain == "example.com"
+        })
+        .unwrap();
+        let pending = pending_dir.join(system_dir.as_str().trim_start_matches('/'));
+        let mut files = glob(&format!("{pending}/*")).unwrap();
+        files.sort();
+        assert_eq!(vec![pending.join("old.example.com.conf")], files);
+        assert_eq!("", path(&files[0]).read().unwrap());
+    }
+}
@@ -0,0 +1,534 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use snafu::prelude::*;
+
+use std::collections::BTreeMap;
+use std::fs::remove_dir_all;
+use std::time::Duration;
+
+use crate::helpers::file::{
+    error::{FileError, YamlSnafu},
+    *,
+};
+
+pub mod error;
+use error::*;
+
+mod apply;
+pub use apply::*;
+mod backup;
+pub use backup::*;
+mod diff;
+pub use diff::*;
+pub mod hook;
+mod plan;
+pub use plan::*;
+mod status;
+pub use status::*;
+
+pub const BASE_CONF_DIR: &'static str = "/var/cache/yunohost/regenconf";
+pub const BACKUP_CONF_DIR: &'static str = "/var/cache/yunohost/regenconf/backup";
+pub const PENDING_CONF_DIR: &'static str = "/var/cache/yunohost/regenconf/pending";
+pub const REGEN_CONF_FILE: &'static str = "/etc/yunohost/regenconf.yml";
+/// How long to wait for other yunohost commands before changing the configuration.
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RegenConfFile {
+    #[serde(flatten)]
+    pub categories: BTreeMap<String, RegenCategoryConfFiles>,
+}
+
+impl RegenConfFile {
+    /// Loads the configuration hashes from [`REGEN_CONF_FILE`]. A missing or empty file contains no hashes.
+    ///
+    /// Errors when:
+    /// - reading the file failed
+    /// - the file is not valid YAML, or does not contain configuration hashes
+    pub fn load() -> Result<Self, RegenConfError> {
+        let regen_conf_file = path(REGEN_CONF_FILE);
+        if !regen_conf_file.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = regen_conf_file.read().context(RegenConfFileReadSnafu {
+            path: regen_conf_file.to_path_buf(),
+        })?;
+        if content.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        serde_yaml_ng::from_str(&content).context(RegenConfFileParseSnafu {
+            path: regen_conf_file.to_path_buf(),
+        })
+    }
+
+    /// Saves the configuration hashes to [`REGEN_CONF_FILE`], atomically.
+    ///
+    /// Errors when:
+    /// - the YAML serialization failed
+    /// - writing the file failed
+    pub fn save(&self) -> Result<(), FileError> {
+        let content = serde_yaml_ng::to_string(self).context(YamlSnafu)?;
+        path(REGEN_CONF_FILE).write_atomic(content)
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+/// We want to store as a relative path to avoid logic errors, but serialize/deserialize as an absolute path
+/// for yunohost compat
+/// TODO: maybe in future we want to make serialization/deserialization less work by storing the absolute Path
+/// and returning the relative path in some situations...
+pub struct RelativeConfFile {
+    pub path: Utf8PathBuf,
+}
+
+impl RelativeConfFile {
+    pub fn from_relative(path: Utf8PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Errors when:
+    /// - the path is not absolute
+    pub fn from_absolute(path: Utf8PathBuf) -> Result<Self, RegenConfError> {
+        Self::from_basedir_prefix(&path, Utf8Path::new("/"))
+    }
+
+    /// Errors when:
+    /// - the path is not in `prefix`
+    pub fn from_basedir_prefix(path: &Utf8Path, prefix: &Utf8Path) -> Result<Self, RegenConfError> {
+        let relative = path
+            .strip_prefix(prefix)
+            .ok()
+            .context(RelativeConfFilePrefixSnafu {
+                path: path.to_path_buf(),
+                basedir: prefix.to_path_buf(),
+            })?;
+        Ok(Self::from_relative(relative.to_path_buf()))
+    }
+
+    pub fn to_relative(&self) -> Utf8PathBuf {
+        self.path.clone()
+    }
+
+    pub fn to_absolute(&self) -> Utf8PathBuf {
+        Utf8PathBuf::from("/").join(&self.path)
+    }
+}
+
+impl Serialize for RelativeConfFile {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_absolute().as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for RelativeConfFile {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let path = Utf8PathBuf::from(&String::deserialize(deserializer)?);
+        Self::from_absolute(path).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A pending conf in PENDING_CONF_DIR, generated by the `pre` phase of a hook.
+///
+/// Like in the Python version, an empty pending file means the system conf should be removed. A pending
+/// symlink is installed as a symlink with the same target, instead of a copy of the target.
+///
+/// This serializes as the absolute path of the pending conf, which is the default output of
+/// `yunohost tools regen-conf --list-pending`. Use the [`PendingConfFile::into_pending_diff`] to
+/// generate the `--with-diff` output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PendingConfFile {
+    /// A regular file, whose content is copied to the system conf
+    Regular(Utf8PathBuf),
+    /// A symlink, created on the system with the same `target`
+    Symlink {
+        path: Utf8PathBuf,
+        target: Utf8PathBuf,
+    },
+    /// An empty file, requesting the removal of the system conf
+    Removal(Utf8PathBuf),
+}
+
+impl PendingConfFile {
+    /// Reads the type of the pending conf at `path`, or `None` when it's a directory.
+    ///
+    /// Errors when:
+    /// - `path` does not exist or could not be read
+    pub fn from_path(path: Utf8PathBuf) -> Result<Option<PendingConfFile>, RegenConfError> {
+        let metadata = std::fs::symlink_metadata(&path)
+            .context(PendingConfFileMetadataSnafu { path: path.clone() })?;
+
+        if metadata.is_symlink() {
+            let target = StrPath::from(path.as_path())
+                .read_link()
+                .context(PendingConfFileReadLinkSnafu { path: path.clone() })?
+                .to_path_buf();
+            Ok(Some(PendingConfFile::Symlink { path, target }))
+        } else if metadata.is_dir() {
+            Ok(None)
+        } else if metadata.len() == 0 {
+            Ok(Some(PendingConfFile::Removal(path)))
+        } else {
+            Ok(Some(PendingConfFile::Regular(path)))
+        }
+    }
+
+    /// The absolute path to the pending conf, in [`PENDING_CONF_DIR`].
+    pub fn path(&self) -> &Utf8Path {
+        match self {
+            Self::Regular(path) | Self::Removal(path) => path,
+            Self::Symlink { path, .. } => path,
+        }
+    }
+
+    /// The file containing the new content for the `system_conf`, or `None` if it should be removed.
+    ///
+    /// A relative symlink target is resolved from the directory of the system conf, where the symlink will be created.
+    pub fn content_path(&self, system_conf: &Utf8Path) -> Option<Utf8PathBuf> {
+        match self {
+            Self::Regular(path) => Some(path.clone()),
+            // UNWRAP NOTE: System conf is always an absolute path to a file, so it has a parent
+            Self::Symlink { target, .. } => Some(system_conf.parent().unwrap().join(target)),
+            Self::Removal(_) => None,
+        }
+    }
+
+    /// The hash of the new content for the `system_conf` (see [`PendingConfFile::content_path`]),
+    /// or `None` if it should be removed.
+    ///
+    /// A symlink to a directory or to a missing file has no content to hash, so like in Python its
+    /// hash is `None`, and it is compared to the system conf by its target instead.
+    ///
+    /// Errors when:
+    /// - the new content exists but could not be read
+    pub fn new_hash(&self, system_conf: &Utf8Path) -> Result<Option<String>, RegenConfError> {
+        match self.content_path(system_conf) {
+            Some(content_path) => _calculate_hash(&content_path),
+            None => Ok(None),
+        }
+    }
+
+    // We need to remove PENDING_CONF_DIR and the category
+    // /var/cache/yunohost/regenconf/pending/nginx/FOO
+    // => FOO
+    //
+    // Errors when:
+    // - the pending conf is not in a category of PENDING_CONF_DIR
+    pub fn to_system_path(&self) -> Result<RelativeConfFile, RegenConfError> {
+        let context = PendingConfFileCategorySnafu {
+            path: self.path().to_path_buf(),
+            pending_dir: Utf8PathBuf::from(PENDING_CONF_DIR),
+        };
+        let category_conffile = self
+            .path()
+            .strip_prefix(PENDING_CONF_DIR)
+            .ok()
+            .context(context.clone())?;
+        let conffile: Utf8PathBuf = category_conffile.components().skip(1).collect();
+        if conffile.as_str().is_empty() {
+            return context.fail();
+        }
+        Ok(RelativeConfFile::from_relative(conffile))
+    }
+
+    /// Compares the system conf to this pending conf, with `context` lines of context.
+    ///
+    /// Errors when:
+    /// - the pending conf is not in [`PENDING_CONF_DIR`]
+    /// - reading the pending or system conf failed
+    pub fn into_pending_diff(self, context: usize) -> Result<PendingConfDiff, RegenConfError> {
+        let system_conf = self.to_system_path()?.to_absolute();
+        Ok(PendingConfDiff {
+            diff: _get_files_diff(
+                &system_conf,
+                self.content_path(&system_conf).as_deref(),
+                context,
+            )?,
+            pending_conf: self,
+        })
+    }
+}
+
+impl Serialize for PendingConfFile {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.path().as_str())
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PendingConfDiff {
+    pub diff: String,
+    pub pending_conf: PendingConfFile,
+}
+
+/// The known configuration hashes for a category.
+///
+/// A file can be tracked with an empty (`null`) hash, for example when it was
+/// removed by the regen-conf, or manually removed before being forgotten about.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RegenCategoryConfFiles {
+    pub conffiles: BTreeMap<RelativeConfFile, Option<String>>,
+}
+
+pub fn _get_pending_conf(
+    categories: &[String],
+) -> Result<BTreeMap<String, BTreeMap<RelativeConfFile, PendingConfFile>>, RegenConfError> {
+    _get_pending_conf_in(Utf8Path::new(PENDING_CONF_DIR), categories)
+}
+
+/// Same as [`_get_pending_conf`], but in another directory than [`PENDING_CONF_DIR`].
+///
+/// Errors when:
+/// - listing the categories or their pending files failed
+/// - removing the directory of a category without pending files failed
+pub fn _get_pending_conf_in(
+    pending_dir: &Utf8Path,
+    categories: &[String],
+) -> Result<BTreeMap<String, BTreeMap<RelativeConfFile, PendingConfFile>>, RegenConfError> {
+    let mut res: BTreeMap<String, BTreeMap<RelativeConfFile, PendingConfFile>> = BTreeMap::new();
+
+    // No pending directory, nothing to see here.
+    if !path(pending_dir).is_dir() {
+        debug!("No such regen-conf pending directory: {pending_dir}");
+        return Ok(res);
+    }
+
+    // If no categories specified, populate
+    let categories = if categories.is_empty() {
+        // Only take file names
+        ReadDir::new(pending_dir)
+            .context(GetPendingConfReadDirSnafu {
+                path: pending_dir.to_path_buf(),
+            })?
+            .filenames()
+    } else {
+        categories.to_vec()
+    };
+
+    for name in categories {
+        let category_pending_path = pending_dir.join(&name);
+        if !path(&category_pending_path).is_dir() {
+            debug!("regen-conf: Skip non-dir {category_pending_path}");
+            continue;
+        }
+
+        let mut category_conf: BTreeMap<RelativeConfFile, PendingConfFile> = BTreeMap::new();
+
+        for path in
+            glob(&format!("{}/**/*", category_pending_path)).context(GetPendingConfGlobSnafu {
+                category: name.to_string(),
+                path: category_pending_path.clone(),
+            })?
+        {
+            // Remove the category_pending_path prefix from the entry
+            let index = RelativeConfFile::from_basedir_prefix(&path, &category_pending_path)?;
+
+            // Glob follows symlinks to directories, but the symlink itself is the pending conf
+            if index
+                .to_relative()
+                .ancestors()
+                .skip(1)
+                .any(|ancestor| category_pending_path.join(ancestor).is_symlink())
+            {
+                continue;
+            }
+
+            // Ignore folders, we only want files and symlinks
+            if let Some(pending) = PendingConfFile::from_path(path)? {
+                category_conf.insert(index, pending);
+            }
+        }
+
+        if category_conf.is_empty() {
+            remove_dir_all(&category_pending_path).context(GetPendingConfCleanEmptySnafu {
+                category: name.to_string(),
+                path: category_pending_path.clone(),
+            })?;
+        } else {
+            res.insert(name, category_conf);
+        }
+    }
+
+    Ok(res)
+}
+
+fn _get_regenconf_infos() -> Result<RegenConfFile, RegenConfError> {
+    RegenConfFile::load()
+}
+
+fn _get_regenconf_hashes(
+    regen_conf_file: &RegenConfFile,
+    category: &str,
+) -> RegenCategoryConfFiles {
+    if let Some(category_conffiles) = regen_conf_file.categories.get(category) {
+        if category_conffiles.conffiles.is_empty() {
+            debug!("No configuration files for category {category}.");
+            return RegenCategoryConfFiles::default();
+        }
+
+        return category_conffiles.to_owned();
+    } else {
+        debug!("category {category} not in categories.yml yet");
+        return RegenCategoryConfFiles::default();
+    }
+}
+
+/// Calculates the md5 hash of a file, or `None` if the file does not exist.
+/// Empty files are emptied out later... for now they have a hash
+///
+/// Like in Python, a symlink to a file is hashed by the content of the file, while a symlink to a
+/// directory or to a missing file, like a directory, has no hash.
+///
+/// Errors when:
+/// - the file exists but could not be read
+fn _calculate_hash(path: &Utf8Path) -> Result<Option<String>, RegenConfError> {
+    let path = StrPath::from(path);
+
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let content = path.read_bytes().context(CalculateHashSnafu {
+        path: path.to_path_buf(),
+    })?;
+    Ok(Some(format!("{:x}", md5::compute(content))))
+}
+
+/// Updates existing [`RegenConfFile`] with new `hashes` in `category`.
+/// How could hash be None if the file exists?
+fn _update_conf_hashes(
+    regen_conf_file: &mut RegenConfFile,
+    category: &str,
+    hashes: RegenCategoryConfFiles,
+) {
+    debug!("Updating conf hashes for '{category}' with: {hashes:?}");
+
+    regen_conf_file
+        .categories
+        .entry(category.to_string())
+        .or_default()
+        .conffiles = hashes.conffiles;
+}
+
+/// Produces the unified diff between two files with `context` lines of context, like
+/// the Python version: without the `---`/`+++` headers, and without trailing whitespace.
+///
+/// A missing file (or a directory), or no `new_file` at all, is compared as an empty file.
+///
+/// Errors when:
+/// - one of the files exists but could not be read
+fn _get_files_diff(
+    orig_file: &Utf8Path,
+    new_file: Option<&Utf8Path>,
+    context: usize,
+) -> Result<String, RegenConfError> {
+    let read_lines = |file: &Utf8Path| -> Result<Vec<String>, RegenConfError> {
+        let file = path(file);
+        if !file.is_file() {
+            return Ok(vec![]);
+        }
+        let content = file.read_bytes().context(FilesDiffReadSnafu {
+            path: file.to_path_buf(),
+        })?;
+        Ok(split_lines(&String::from_utf8_lossy(&content)))
+    };
+
+    let orig_lines = read_lines(orig_file)?;
+    let new_lines = match new_file {
+        Some(new_file) => read_lines(new_file)?,
+        None => vec![],
+    };
+
+    let diff = unified_diff(&orig_lines, &new_lines, "", "", context);
+    // Skip the headers
+    Ok(diff
+        .iter()
+        .skip(2)
+        .map(String::as_str)
+        .collect::<String>()
+        .trim_end()
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_conf_variants() {
+        let dir = tempfile::tempdir().unwrap();
+        let pending_dir = Utf8Path::from_path(dir.path()).unwrap();
+        let category_dir = pending_dir.join("nginx");
+        std::fs::create_dir_all(category_dir.join("etc/nginx/conf.d")).unwrap();
+        std::fs::write(category_dir.join("etc/nginx/nginx.conf"), "events {}\n").unwrap();
+        std::fs::write(category_dir.join("etc/nginx/conf.d/old.conf"), "").unwrap();
+        std::os::unix::fs::symlink(
+            "../sites-available/default",
+            category_dir.join("etc/nginx/conf.d/default"),
+        )
+        .unwrap();
+        // Files under a symlinked directory belong to the symlink
+        std::os::unix::fs::symlink(
+            category_dir.join("etc/nginx/conf.d"),
+            category_dir.join("etc/nginx/sites-enabled"),
+        )
+        .unwrap();
+
+        let pending = _get_pending_conf_in(pending_dir, &[]).unwrap();
+        let files: Vec<(Utf8PathBuf, PendingConfFile)> = pending["nginx"]
+            .iter()
+            .map(|(system_path, pending)| (system_path.to_absolute(), pending.clone()))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (
+                    Utf8PathBuf::from("/etc/nginx/conf.d/default"),
+                    PendingConfFile::Symlink {
+                        path: category_dir.join("etc/nginx/conf.d/default"),
+                        target: Utf8PathBuf::from("../sites-available/default"),
+                    }
+                ),
+                (
+                    Utf8PathBuf::from("/etc/nginx/conf.d/old.conf"),
+                    PendingConfFile::Removal(category_dir.join("etc/nginx/conf.d/old.conf"))
+                ),
+                (
+                    Utf8PathBuf::from("/etc/nginx/nginx.conf"),
+                    PendingConfFile::Regular(category_dir.join("etc/nginx/nginx.conf"))
+                ),
+                (
+                    Utf8PathBuf::from("/etc/nginx/sites-enabled"),
+                    PendingConfFile::Symlink {
+                        path: category_dir.join("etc/nginx/sites-enabled"),
+                        target: category_dir.join("etc/nginx/conf.d"),
+                    }
+                ),
+            ],
+            files
+        );
+
+        let (_, symlink) = &files[0];
+        assert_eq!(
+            Some(Utf8PathBuf::from(
+                "/etc/nginx/conf.d/../sites-available/default"
+            )),
+            symlink.content_path(Utf8Path::new("/etc/nginx/conf.d/default"))
+        );
+        let (_, removal) = &files[1];
+        assert_eq!(
+            None,
+            removal.content_path(Utf8Path::new("/etc/nginx/conf.d/old.conf"))
+        );
+    }
+}
@@ -0,0 +1,400 @@
+//! Deciding what regen-conf should do with every configuration file.
+//!
+//! The decision only depends on three hashes: the hash of the file currently on the system, the hash
+//! saved in [`REGEN_CONF_FILE`](super::REGEN_CONF_FILE) during the last regen-conf, and the hash of the
+//! newly generated pending file. A pending symlink to a directory or to a missing file has no hash, and is
+//! compared to the system conf by its target instead. This is kept separate from applying the changes so that `--dry-run` can
+//! report exactly what would happen.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+
+/// The status of a single configuration file after regen-conf, with the same
+/// names as the Python version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegenConfStatus {
+    /// The system conf did not exist and was created
+    Created,
+    /// The system conf was manually removed, and was created again (`--force`)
+    ForceCreated,
+    /// The system conf was removed as requested by an empty pending conf, or was manually removed
+    /// and is kept removed
+    Removed,
+    /// The conf is not generated anymore, and the system conf was removed (`--force`)
+    ForceRemoved,
+    /// The system conf is identical to the pending conf, and is now tracked
+    Managed,
+    /// The system conf was not tracked yet, and was replaced
+    New,
+    /// The system conf was not modified since last regen-conf, and was replaced
+    Updated,
+    /// The system conf was manually modified, and was replaced (`--force`)
+    ForceUpdated,
+    /// The system conf was manually modified, and is kept as is
+    Modified,
+    /// The conf is not generated anymore and the system conf is already gone
+    ForgetAboutIt,
+}
+
+/// The action regen-conf takes on a system configuration file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegenConfAction {
+    /// Install the pending conf where there was none
+    Create,
+    /// Replace the system conf with the pending conf
+    Update,
+    /// Remove the system conf
+    Remove,
+    /// Keep the system conf as is, because it was manually modified or removed
+    SkipManuallyModified,
+    /// Nothing to change on the system
+    Unchanged,
+}
+
+/// What regen-conf does with a single configuration file, and why.
+#[derive(Clone, Debug, Serialize)]
+pub struct RegenConfPlan {
+    pub action: RegenConfAction,
+    pub reason: &'static str,
+    /// The Python-compatible status, or `None` when the file is already up-to-date.
+    #[serde(skip)]
+    pub status: Option<RegenConfStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// The file the system conf was compared to for the `diff`, or `None` when compared to nothing.
+    #[serde(skip)]
+    pub diff_path: Option<Utf8PathBuf>,
+}
+
+impl RegenConfPlan {
+    fn new(action: RegenConfAction, status: Option<RegenConfStatus>, reason: &'static str) -> Self {
+        Self {
+            action,
+            reason,
+            status,
+            diff: None,
+            diff_path: None,
+        }
+    }
+
+    /// Whether the file is considered handled once the action is taken, in which case its new hash is saved.
+    /// Otherwise, the pending conf is kept back.
+    pub fn is_applied(&self) -> bool {
+        self.status.is_some() && self.action != RegenConfAction::SkipManuallyModified
+    }
+}
+
+/// Decides what to do with a configuration file, following the Python rules.
+///
+/// `new_hash` is `None` when the file is not generated anymore, but a hash was saved for it previously.
+/// When `force` is true, manually modified/removed files are overwritten.
+pub fn plan_conf_file(
+    system_hash: Option<&str>,
+    saved_hash: Option<&str>,
+    new_hash: Option<&str>,
+    force: bool,
+) -> RegenConfPlan {
+    use RegenConfAction::*;
+    use RegenConfStatus::*;
+
+    let Some(new_hash) = new_hash else {
+        // Configuration was previously managed by Yunohost but should now be removed/unmanaged
+        if system_hash.is_none() {
+            return RegenConfPlan::new(
+                Unchanged,
+                Some(ForgetAboutIt),
+                "conf is not generated anymore and was already removed, forgetting about it",
+            );
+        } else if force {
+            return RegenConfPlan::new(
+                Remove,
+                Some(ForceRemoved),
+                "conf is not generated anymore, removing it from the system",
+            );
+        } else {
+            return RegenConfPlan::new(
+                SkipManuallyModified,
+                Some(Modified),
+                "conf is not generated anymore but is still on the system, use --force to remove it",
+            );
+        }
+    };
+
+    let Some(system_hash) = system_hash else {
+        // System conf does not exist
+        if saved_hash.is_none() {
+            return RegenConfPlan::new(Create, Some(Created), "system conf does not exist yet");
+        } else if force {
+            return RegenConfPlan::new(
+                Create,
+                Some(ForceCreated),
+                "system conf was manually removed, creating it again",
+            );
+        } else {
+            return RegenConfPlan::new(
+                SkipManuallyModified,
+                Some(Removed),
+                "system conf was manually removed, use --force to create it again",
+            );
+        }
+    };
+
+    match saved_hash {
+        // System conf is not managed yet
+        None => {
+            if system_hash == new_hash {
+                RegenConfPlan::new(
+                    Unchanged,
+                    Some(Managed),
+                    "system conf is not managed yet but is identical to the new conf",
+                )
+            } else {
+                RegenConfPlan::new(
+                    Update,
+                    Some(New),
+                    "system conf is not managed yet and will be replaced",
+                )
+            }
+        }
+        // System conf has not been manually modified
+        Some(saved_hash) if saved_hash == system_hash => {
+            if system_hash != new_hash {
+                RegenConfPlan::new(
+                    Update,
+                    Some(Updated),
+                    "system conf was not manually modified",
+                )
+            } else {
+                RegenConfPlan::new(Unchanged, None, "system conf is already up-to-date")
+            }
+        }
+        // System conf has been manually modified
+        Some(_) => {
+            if system_hash == new_hash {
+                RegenConfPlan::new(
+                    Unchanged,
+                    Some(Managed),
+                    "system conf was manually modified but is identical to the new conf",
+                )
+            } else if force {
+                RegenConfPlan::new(
+                    Update,
+                    Some(ForceUpdated),
+                    "system conf was manually modified, overwriting it",
+                )
+            } else {
+                RegenConfPlan::new(
+                    SkipManuallyModified,
+                    Some(Modified),
+                    "system conf was manually modified, use --force to overwrite it",
+                )
+            }
+        }
+    }
+}
+
+/// Decides what to do with a configuration file whose pending conf is a symlink to `target`, which
+/// has no hash because it points to a directory or to a missing file. `system_target` is the target
+/// of the system conf, if it's a symlink.
+///
+/// Such a symlink is never tracked by a hash, so it is replaced as soon as it differs.
+pub fn plan_conf_symlink(
+    system_target: Option<&Utf8Path>,
+    system_exists: bool,
+    target: &Utf8Path,
+) -> RegenConfPlan {
+    use RegenConfAction::*;
+    use RegenConfStatus::*;
+
+    if system_target == Some(target) {
+        RegenConfPlan::new(
+            Unchanged,
+            None,
+            "system conf is already a symlink to the same target",
+        )
+    } else if !system_exists {
+        RegenConfPlan::new(Create, Some(Created), "system conf does not exist yet")
+    } else {
+        RegenConfPlan::new(
+            Update,
+            Some(Updated),
+            "system conf is not a symlink to the same target",
+        )
+    }
+}
+
+/// Decides what to do with a configuration file whose pending conf is empty, which requests
+/// its removal from the system.
+///
+/// Like for other changes, a manually modified file is only removed when `force` is true.
+pub fn plan_conf_removal(
+    system_hash: Option<&str>,
+    saved_hash: Option<&str>,
+    force: bool,
+) -> RegenConfPlan {
+    use RegenConfAction::*;
+    use RegenConfStatus::*;
+
+    let Some(system_hash) = system_hash else {
+        if saved_hash.is_none() {
+            return RegenConfPlan::new(Unchanged, None, "system conf is already removed");
+        } else {
+            return RegenConfPlan::new(
+                Unchanged,
+                Some(ForgetAboutIt),
+                "system conf should be removed and was already removed, forgetting about it",
+            );
+        }
+    };
+
+    if saved_hash == Some(system_hash) {
+        RegenConfPlan::new(
+            Remove,
+            Some(Removed),
+            "system conf should be removed and was not manually modified",
+        )
+    } else if force {
+        RegenConfPlan::new(
+            Remove,
+            Some(ForceRemoved),
+            "system conf should be removed but was manually modified, removing it",
+        )
+    } else {
+        RegenConfPlan::new(
+            SkipManuallyModified,
+            Some(Modified),
+            "system conf should be removed but was manually modified, use --force to remove it",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_symlink() {
+        let target = Utf8Path::new("/var/www/app");
+        let plan = plan_conf_symlink(Some(target), true, target);
+        assert_eq!(RegenConfAction::Unchanged, plan.action);
+        assert_eq!(None, plan.status);
+
+        let plan = plan_conf_symlink(None, false, target);
+        assert_eq!(RegenConfAction::Create, plan.action);
+        assert!(plan.is_applied());
+
+        let plan = plan_conf_symlink(Some(Utf8Path::new("/var/www/old")), true, target);
+        assert_eq!(RegenConfAction::Update, plan.action);
+        assert!(plan.is_applied());
+
+        let plan = plan_conf_symlink(None, true, target);
+        assert_eq!(RegenConfAction::Update, plan.action);
+    }
+
+    #[test]
+    fn plan_new_file() {
+        let plan = plan_conf_file(None, None, Some("new"), false);
+        assert_eq!(RegenConfAction::Create, plan.action);
+        assert_eq!(Some(RegenConfStatus::Created), plan.status);
+        assert!(plan.is_applied());
+    }
+
+    #[test]
+    fn plan_manually_removed() {
+        let plan = plan_conf_file(None, Some("saved"), Some("new"), false);
+        assert_eq!(RegenConfAction::SkipManuallyModified, plan.action);
+        assert_eq!(Some(RegenConfStatus::Removed), plan.status);
+        assert!(!plan.is_applied());
+    }
+
+    #[test]
+    fn plan_unmanaged() {
+        let plan = plan_conf_file(Some("new"), None, Some("new"), false);
+        assert_eq!(RegenConfAction::Unchanged, plan.action);
+        assert_eq!(Some(RegenConfStatus::Managed), plan.status);
+        assert!(plan.is_applied());
+
+        let plan = plan_conf_file(Some("system"), None, Some("new"), false);
+        assert_eq!(RegenConfAction::Update, plan.action);
+        assert_eq!(Some(RegenConfStatus::New), plan.status);
+    }
+
+    #[test]
+    fn plan_not_modified() {
+        let plan = plan_conf_file(Some("saved"), Some("saved"), Some("new"), false);
+        assert_eq!(RegenConfAction::Update, plan.action);
+        assert_eq!(Some(RegenConfStatus::Updated), plan.status);
+
+        let plan = plan_conf_file(Some("saved"), Some("saved"), Some("saved"), false);
+        assert_eq!(RegenConfAction::Unchanged, plan.action);
+        assert_eq!(None, plan.status);
+        assert!(!plan.is_applied());
+    }
+
+    #[test]
+    fn plan_manually_modified() {
+        let plan = plan_conf_file(Some("system"), Some("saved"), Some("new"), false);
+        assert_eq!(RegenConfAction::SkipManuallyModified, plan.action);
+        assert_eq!(Some(RegenConfStatus::Modified), plan.status);
+        assert!(!plan.is_applied());
+
+        let plan = plan_conf_file(Some("new"), Some("saved"), Some("new"), false);
+        assert_eq!(RegenConfAction::Unchanged, plan.action);
+        assert_eq!(Some(RegenConfStatus::Managed), plan.status);
+    }
+
+    #[test]
+    fn plan_stale() {
+        let plan = plan_conf_file(None, Some("saved"), None, false);
+        assert_eq!(RegenConfAction::Unchanged, plan.action);
+        assert_eq!(Some(RegenConfStatus::ForgetAboutIt), plan.status);
+        assert!(plan.is_applied());
+
+        let plan = plan_conf_file(Some("saved"), Some("saved"), None, false);
+        assert_eq!(RegenConfAction::SkipManuallyModified, plan.action);
+        assert_eq!(Some(RegenConfStatus::Modified), plan.status);
+    }
+
+    #[test]
+    fn plan_force() {
+        let plan = plan_conf_file(Some("system"), Some("saved"), Some("new"), true);
+        assert_eq!(RegenConfAction::Update, plan.action);
+        assert_eq!(Some(RegenConfStatus::ForceUpdated), plan.status);
+        assert!(plan.is_applied());
+
+        let plan = plan_conf_file(None, Some("saved"), Some("new"), true);
+        assert_eq!(RegenConfAction::Create, plan.action);
+        assert_eq!(Some(RegenConfStatus::ForceCreated), plan.status);
+
+        let plan = plan_conf_file(Some("saved"), Some("saved"), None, true);
+        assert_eq!(RegenConfAction::Remove, plan.action);
+        assert_eq!(Some(RegenConfStatus::ForceRemoved), plan.status);
+        assert!(plan.is_applied());
+    }
+
+    #[test]
+    fn plan_removal() {
+        let plan = plan_conf_removal(Some("saved"), Some("saved"), false);
+        assert_eq!(RegenConfAction::Remove, plan.action);
+        assert_eq!(Some(RegenConfStatus::Removed), plan.status);
+        assert!(plan.is_applied());
+
+        let plan = plan_conf_removal(Some("system"), Some("saved"), false);
+        assert_eq!(RegenConfAction::SkipManuallyModified, plan.action);
+        assert_eq!(Some(RegenConfStatus::Modified), plan.status);
+
+        let plan = plan_conf_removal(Some("system"), None, true);
+        assert_eq!(RegenConfAction::Remove, plan.action);
+        assert_eq!(Some(RegenConfStatus::ForceRemoved), plan.status);
+
+        let plan = plan_conf_removal(None, Some("saved"), false);
+        assert_eq!(Some(RegenConfStatus::ForgetAboutIt), plan.status);
+        assert!(plan.is_applied());
+
+        let plan = plan_conf_removal(None, None, false);
+        assert_eq!(RegenConfAction::Unchanged, plan.action);
+        assert_eq!(None, plan.status);
+    }
+}
@@ -0,0 +1,118 @@
+//! Comparing the system configuration files to the hashes saved in [`REGEN_CONF_FILE`].
+//!
+//! Unlike a regen-conf, this does not run any hook nor generate pending conf: it only reports
+//! which tracked files drifted since the last regen-conf.
+
+use serde::Serialize;
+use snafu::prelude::*;
+
+use std::collections::BTreeMap;
+
+use super::*;
+
+/// The state of a tracked system configuration file, compared to its saved hash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegenConfFileStatus {
+    /// The system conf matches the saved hash
+    Matches,
+    /// The system conf was manually modified since the last regen-conf
+    ManuallyModified,
+    /// The system conf was deleted since the last regen-conf
+    Deleted,
+    /// The file is tracked with an empty hash, because it was removed by the last regen-conf
+    EmptyHash,
+}
+
+/// The status of a tracked system configuration file, with the hashes it was decided from.
+#[derive(Clone, Debug, Serialize)]
+pub struct RegenConfFileState {
+    pub status: RegenConfFileStatus,
+    /// The hash saved in [`REGEN_CONF_FILE`]
+    pub saved_hash: Option<String>,
+    /// The hash of the file currently on the system, `None` when it does not exist
+    pub system_hash: Option<String>,
+}
+
+/// Reports the status of every file tracked in [`REGEN_CONF_FILE`], by category.
+///
+/// When `category` is set, only the files of this category are reported.
+///
+/// Errors when:
+/// - reading [`REGEN_CONF_FILE`] failed
+/// - the requested `category` is not tracked
+/// - one of the system conf exists but could not be read
+pub fn regen_conf_status(
+    category: Option<&str>,
+) -> Result<BTreeMap<String, BTreeMap<RelativeConfFile, RegenConfFileState>>, RegenConfError> {
+    let regen_conf_file = _get_regenconf_infos()?;
+
+    if let Some(category) = category {
+        ensure!(
+            regen_conf_file.categories.contains_key(category),
+            RegenConfUnknownCategorySnafu {
+                category: category.to_string()
+            }
+        );
+    }
+
+    let mut res: BTreeMap<String, BTreeMap<RelativeConfFile, RegenConfFileState>> = BTreeMap::new();
+
+    for (name, category_files) in &regen_conf_file.categories {
+        if category.is_some_and(|category| category != name) {
+            continue;
+        }
+
+        let mut category_states: BTreeMap<RelativeConfFile, RegenConfFileState> = BTreeMap::new();
+        for (system_path, saved_hash) in &category_files.conffiles {
+            let system_hash = _calculate_hash(&system_path.to_absolute())?;
+            category_states.insert(
+                system_path.clone(),
+                RegenConfFileState {
+                    status: _file_status(saved_hash.as_deref(), system_hash.as_deref()),
+                    saved_hash: saved_hash.clone(),
+                    system_hash,
+                },
+            );
+        }
+
+        res.insert(name.to_string(), category_states);
+    }
+
+    Ok(res)
+}
+
+fn _file_status(saved_hash: Option<&str>, system_hash: Option<&str>) -> RegenConfFileStatus {
+    match (saved_hash, system_hash) {
+        (None, _) => RegenConfFileStatus::EmptyHash,
+        (Some(_), None) => RegenConfFileStatus::Deleted,
+        (Some(saved), Some(system)) if saved == system => RegenConfFileStatus::Matches,
+        (Some(_), Some(_)) => RegenConfFileStatus::ManuallyModified,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_status() {
+        assert_eq!(
+            RegenConfFileStatus::Matches,
+            _file_status(Some("abc"), Some("abc"))
+        );
+        assert_eq!(
+            RegenConfFileStatus::ManuallyModified,
+            _file_status(Some("abc"), Some("def"))
+        );
+        assert_eq!(
+            RegenConfFileStatus::Deleted,
+            _file_status(Some("abc"), None)
+        );
+        assert_eq!(RegenConfFileStatus::EmptyHash, _file_status(None, None));
+        assert_eq!(
+            RegenConfFileStatus::EmptyHash,
+            _file_status(None, Some("abc"))
+        );
+    }
+}
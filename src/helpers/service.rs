@@ -46,6 +46,30 @@ impl SystemCtl {
         cmd("systemctl", &args).unwrap().status.success()
     }
 
+    /// Runs the `systemctl reload` command, for a `unit`, with potential `extra` params. Returns true
+    /// when the operation was successful (return code 0) and not aborted.
+    ///
+    /// Panics if running systemctl fails, not if the return code is non-zero.
+    pub fn reload(unit: &str, extra: &[&str]) -> bool {
+        let mut args: Vec<&str> = vec!["reload"];
+        args.extend(extra);
+        args.push(unit);
+        // UNWRAP NOTE: If systemctl fails to spawn, no need to continue anything...
+        cmd("systemctl", &args).unwrap().status.success()
+    }
+
+    /// Runs the `systemctl restart` command, for a `unit`, with potential `extra` params. Returns true
+    /// when the operation was successful (return code 0) and not aborted.
+    ///
+    /// Panics if running systemctl fails, not if the return code is non-zero.
+    pub fn restart(unit: &str, extra: &[&str]) -> bool {
+        let mut args: Vec<&str> = vec!["restart"];
+        args.extend(extra);
+        args.push(unit);
+        // UNWRAP NOTE: If systemctl fails to spawn, no need to continue anything...
+        cmd("systemctl", &args).unwrap().status.success()
+    }
+
     /// Runs the `systemctl start` command, for a `unit`, with potential `extra` params. Returns true
     /// when the operation was successful (return code 0) and not aborted.
     ///
@@ -105,4 +129,9 @@ impl SystemCtl {
         let output = cmd("systemctl", vec!["is-active", "--quiet", unit]).unwrap();
         output.status.success()
     }
+
+    pub fn is_enabled(unit: &str) -> bool {
+        let output = cmd("systemctl", vec!["is-enabled", "--quiet", unit]).unwrap();
+        output.status.success()
+    }
 }
@@ -51,6 +51,60 @@ impl SettingsConfigPanel {
     pub fn list(&mut self, mode: GetMode) -> Result<Value, ConfigPanelError> {
         self.panel.list(mode)
     }
+
+    /// Gets a single setting like `security.ssh.ssh_port`, legacy keys are supported.
+    ///
+    /// Errors when:
+    /// - the key is not a valid setting
+    pub fn get_value(&mut self, key: &str) -> Result<Value, ConfigPanelError> {
+        let filter_key = SettingsFilterKey::from_str(key)?;
+        self.get(&filter_key, GetMode::Classic)
+    }
+
+    /// Gets a boolean setting, which may be saved as a boolean, an integer or a string.
+    ///
+    /// Errors when:
+    /// - the key is not a valid setting
+    /// - the value is not a boolean
+    pub fn get_bool(&mut self, key: &str) -> Result<bool, ConfigPanelError> {
+        let value = self.get_value(key)?;
+        match &value {
+            Value::Bool(b) => Ok(*b),
+            Value::Number(n) if n.as_u64() == Some(0) => Ok(false),
+            Value::Number(n) if n.as_u64() == Some(1) => Ok(true),
+            Value::String(s) => match s.to_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => Ok(true),
+                "0" | "false" | "no" | "off" => Ok(false),
+                _ => Err(ConfigPanelError::SettingsValueType {
+                    key: key.to_string(),
+                    value,
+                }),
+            },
+            _ => Err(ConfigPanelError::SettingsValueType {
+                key: key.to_string(),
+                value,
+            }),
+        }
+    }
+
+    /// Gets a setting as a string. Numbers are formatted, and an unset setting is empty.
+    ///
+    /// Errors when:
+    /// - the key is not a valid setting
+    /// - the value is a list or a dictionary
+    pub fn get_string(&mut self, key: &str) -> Result<String, ConfigPanelError> {
+        let value = self.get_value(key)?;
+        match value {
+            Value::String(s) => Ok(s),
+            Value::Number(n) => Ok(n.to_string()),
+            Value::Bool(b) => Ok(b.to_string()),
+            Value::Null => Ok(String::new()),
+            value => Err(ConfigPanelError::SettingsValueType {
+                key: key.to_string(),
+                value,
+            }),
+        }
+    }
 }
 
 /// This is a special [`FilterKey`] where legacy settings key are supported.
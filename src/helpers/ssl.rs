@@ -0,0 +1,466 @@
+//! The local certification authority, and the self-signed certificates it signs.
+//!
+//! Like the Python `02-ssl` hook, this is done with the `openssl` command, in [`SSL_DIR`]. A
+//! [`SslPaths`] can be rooted in another directory than `/` so that certificates can be generated
+//! without touching the system.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use snafu::prelude::*;
+
+use crate::{
+    error::*,
+    helpers::{file::*, group::YunohostGroup, process::cmd},
+};
+
+/// The working directory of the local CA.
+pub const SSL_DIR: &str = "/usr/share/yunohost/ssl";
+/// Where the Yunohost package ships `openssl.cnf`.
+pub const SSL_TEMPLATE_DIR: &str = "/usr/share/yunohost/conf/ssl";
+/// The certificate for the default `yunohost.org` domain, before any real domain is added.
+pub const DEFAULT_CERT_DIR: &str = "/etc/yunohost/certs/yunohost.org";
+/// The local CA certificate, trusted by the system.
+pub const SYSTEM_CA: &str = "/etc/ssl/certs/ca-yunohost_crt.pem";
+/// The group allowed to read the certificates.
+pub const SSL_CERT_GROUP: &str = "ssl-cert";
+
+/// Who issued the certificate of a domain. In templates, it's `self_signed`, `lets_encrypt` or `other`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertCa {
+    /// Signed by the local CA
+    SelfSigned,
+    LetsEncrypt,
+    /// Installed manually by the admin
+    Other,
+}
+
+/// The paths of the local CA and certificates, relative to a root directory.
+#[derive(Clone, Debug)]
+pub struct SslPaths {
+    root: Utf8PathBuf,
+}
+
+impl SslPaths {
+    /// The paths on the system.
+    pub fn system() -> Self {
+        Self::with_root("/")
+    }
+
+    /// The paths inside `root`, as if it was the system root.
+    pub fn with_root<T: AsRef<Utf8Path>>(root: T) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Finds an absolute system path inside the root.
+    pub fn rooted<T: AsRef<str>>(&self, absolute: T) -> StrPath {
+        path(self.root.join(absolute.as_ref().trim_start_matches('/')))
+    }
+
+    pub fn ssl_dir(&self) -> StrPath {
+        self.rooted(SSL_DIR)
+    }
+
+    pub fn openssl_conf(&self) -> StrPath {
+        path(self.ssl_dir().join("openssl.cnf"))
+    }
+
+    pub fn ca_cert(&self) -> StrPath {
+        path(self.ssl_dir().join("ca/cacert.pem"))
+    }
+
+    pub fn default_cert_dir(&self) -> StrPath {
+        self.rooted(DEFAULT_CERT_DIR)
+    }
+
+    /// Renders the `openssl.cnf` template for this root.
+    ///
+    /// The template contains the absolute [`SSL_DIR`], which is replaced when the root is not `/`.
+    ///
+    /// Errors when:
+    /// - reading the template failed
+    pub fn openssl_conf_content(&self) -> Result<String, Error> {
+        let template = self.rooted(SSL_TEMPLATE_DIR).join("openssl.cnf");
+        let content = path(&template)
+            .read()
+            .context(SslFileSnafu { path: template })?;
+        Ok(content.replace(SSL_DIR, self.ssl_dir().as_str()))
+    }
+
+    /// Creates the working directories of the local CA, and installs its `openssl.cnf`.
+    ///
+    /// Errors when:
+    /// - creating the directories failed
+    /// - reading the template or writing `openssl.cnf` failed
+    pub fn install_openssl_conf(&self) -> Result<(), Error> {
+        for dir in ["ca", "certs", "crl", "newcerts"] {
+            let dir = path(self.ssl_dir().join(dir));
+            dir.mkdir_p().context(SslFileSnafu {
+                path: dir.to_path_buf(),
+            })?;
+        }
+
+        let openssl_conf = self.openssl_conf();
+        openssl_conf
+            .write(self.openssl_conf_content()?)
+            .and_then(|_| openssl_conf.chmod(0o644))
+            .context(SslFileSnafu {
+                path: openssl_conf.to_path_buf(),
+            })
+    }
+
+    /// Creates a new local CA for `domain`, replacing the previous one, and copies its certificate to [`SYSTEM_CA`].
+    ///
+    /// The system does not trust the new CA until `update-ca-certificates` is run.
+    ///
+    /// Errors when:
+    /// - creating the CA files failed
+    /// - an openssl command failed
+    pub fn regen_local_ca(&self, domain: &str) -> Result<(), Error> {
+        info!("Creating local certification authority with domain={domain}");
+
+        self.install_openssl_conf()?;
+        let ssl_dir = self.ssl_dir();
+        self.default_cert_dir().mkdir_p().context(SslFileSnafu {
+            path: self.default_cert_dir().to_path_buf(),
+        })?;
+
+        // Update the serial so that it's specific to this very instance
+        let serial = _openssl("generate the CA serial", &["rand", "-hex", "19"])?;
+        let index = path(ssl_dir.join("index.txt"));
+        path(ssl_dir.join("serial"))
+            .write(serial)
+            .and_then(|_| index.write(""))
+            .context(SslFileSnafu {
+                path: ssl_dir.to_path_buf(),
+            })?;
+
+        let ca_conf = path(ssl_dir.join("openssl.ca.cnf"));
+        ca_conf
+            .write(self.openssl_conf_content()?.replace("yunohost.org", domain))
+            .context(SslFileSnafu {
+                path: ca_conf.to_path_buf(),
+            })?;
+
+        let ca_cert = self.ca_cert();
+        let ca_key = path(ssl_dir.join("ca/cakey.pem"));
+        let organization = domain.rsplit_once('.').map_or(domain, |(org, _)| org);
+        _openssl(
+            "create the local CA",
+            &[
+                "req",
+                "-x509",
+                "-new",
+                "-config",
+                ca_conf.as_str(),
+                "-days",
+                "3650",
+                "-out",
+                ca_cert.as_str(),
+                "-keyout",
+                ca_key.as_str(),
+                "-nodes",
+                "-batch",
+                "-subj",
+                &format!("/CN={domain}/O={organization}"),
+            ],
+        )?;
+
+        let system_ca = self.rooted(SYSTEM_CA);
+        ca_cert
+            .chmod(0o640)
+            .and_then(|_| ca_key.chmod(0o640))
+            .and_then(|_| path(system_ca.parent().unwrap()).mkdir_p())
+            .and_then(|_| ca_cert.copy_file(&system_ca))
+            .context(SslFileSnafu {
+                path: ca_cert.to_path_buf(),
+            })?;
+
+        Ok(())
+    }
+
+    /// Reads the domain of the local CA, or `None` when there is no local CA yet.
+    ///
+    /// Errors when:
+    /// - the CA certificate could not be parsed
+    pub fn local_ca_domain(&self) -> Result<Option<String>, Error> {
+        let ca_cert = self.ca_cert();
+        if !ca_cert.is_file() {
+            return Ok(None);
+        }
+
+        let issuer = _cert_issuer("read the local CA domain", &ca_cert)?;
+        Ok(_issuer_field(&issuer, "CN"))
+    }
+
+    /// Finds who issued the certificate of `domain`. A domain without certificate is considered
+    /// self-signed, like in the Python version.
+    ///
+    /// Errors when:
+    /// - the certificate or the local CA could not be parsed
+    pub fn cert_ca(&self, domain: &str) -> Result<CertCa, Error> {
+        let cert = self.rooted(format!("/etc/yunohost/certs/{domain}/crt.pem"));
+        if !cert.is_file() {
+            return Ok(CertCa::SelfSigned);
+        }
+
+        let issuer = _cert_issuer("read the certificate issuer", &cert)?;
+        let issuer_cn = _issuer_field(&issuer, "CN");
+        if issuer_cn.as_deref() == Some("yunohost.org") || issuer_cn == self.local_ca_domain()? {
+            Ok(CertCa::SelfSigned)
+        } else if _issuer_field(&issuer, "O").as_deref() == Some("Let's Encrypt") {
+            Ok(CertCa::LetsEncrypt)
+        } else {
+            Ok(CertCa::Other)
+        }
+    }
+
+    /// Creates the self-signed certificate for the default `yunohost.org` domain, signed by the local CA,
+    /// in [`DEFAULT_CERT_DIR`]. Its `ca.pem` points to [`SYSTEM_CA`].
+    ///
+    /// Errors when:
+    /// - an openssl command failed
+    /// - copying the certificate and key failed
+    pub fn create_default_cert(&self) -> Result<(), Error> {
+        info!("Creating initial key and certificate");
+
+        let openssl_conf = self.openssl_conf();
+        let certs = path(self.ssl_dir().join("certs"));
+        let csr = path(certs.join("yunohost_csr.pem"));
+        let key = path(certs.join("yunohost_key.pem"));
+        let crt = path(certs.join("yunohost_crt.pem"));
+
+        _openssl(
+            "create the initial key",
+            &[
+                "req",
+                "-new",
+                "-config",
+                openssl_conf.as_str(),
+                "-out",
+                csr.as_str(),
+                "-keyout",
+                key.as_str(),
+                "-nodes",
+                "-batch",
+            ],
+        )?;
+        _openssl(
+            "sign the initial certificate",
+            &[
+                "ca",
+                "-config",
+                openssl_conf.as_str(),
+                "-days",
+                "730",
+                "-in",
+                csr.as_str(),
+                "-out",
+                crt.as_str(),
+                "-batch",
+            ],
+        )?;
+
+        let cert_dir = self.default_cert_dir();
+        key.chmod(0o640)
+            .and_then(|_| crt.chmod(0o640))
+            .and_then(|_| cert_dir.mkdir_p())
+            .and_then(|_| key.copy_file(&path(cert_dir.join("key.pem"))))
+            .and_then(|_| crt.copy_file(&path(cert_dir.join("crt.pem"))))
+            .and_then(|_| {
+                path(cert_dir.join("ca.pem")).symlink_to_target(&self.rooted(SYSTEM_CA), true)
+            })
+            .context(SslFileSnafu {
+                path: cert_dir.to_path_buf(),
+            })?;
+
+        Ok(())
+    }
+
+    /// Restricts the certificates in [`DEFAULT_CERT_DIR`] to root and the [`SSL_CERT_GROUP`], creating
+    /// the group if needed.
+    ///
+    /// Errors when:
+    /// - the group could not be created
+    /// - changing ownership or permissions failed
+    pub fn enforce_permissions(&self) -> Result<(), Error> {
+        YunohostGroup::ensure_exists(SSL_CERT_GROUP)?;
+
+        let cert_dir = self.default_cert_dir();
+        cert_dir
+            .chown_recurse("root", SSL_CERT_GROUP)
+            .and_then(|_| cert_dir.chown_and_mode(0o750, "root", Some(SSL_CERT_GROUP)))
+            .context(SslFileSnafu {
+                path: cert_dir.to_path_buf(),
+            })?;
+
+        let ssl_dir = self.ssl_dir();
+        ssl_dir
+            .chown_and_mode(0o750, "root", Some("root"))
+            .context(SslFileSnafu {
+                path: ssl_dir.to_path_buf(),
+            })?;
+        // Services not running as root need to read the default certificate, but not the CA key
+        let certs = path(ssl_dir.join("certs"));
+        certs
+            .chown_and_mode(0o750, "root", Some(SSL_CERT_GROUP))
+            .context(SslFileSnafu {
+                path: certs.to_path_buf(),
+            })?;
+
+        Ok(())
+    }
+}
+
+/// Reads the issuer of the certificate `cert`, in RFC2253 format (eg. `CN=R3,O=Let's Encrypt,C=US`).
+///
+/// Errors when:
+/// - openssl could not parse the certificate
+fn _cert_issuer(step: &str, cert: &StrPath) -> Result<String, Error> {
+    let issuer = _openssl(
+        step,
+        &[
+            "x509",
+            "-in",
+            cert.as_str(),
+            "-noout",
+            "-issuer",
+            "-nameopt",
+            "RFC2253",
+        ],
+    )?;
+
+    Ok(issuer.trim().trim_start_matches("issuer=").to_string())
+}
+
+/// Finds a `field` like `CN` in an RFC2253 issuer.
+fn _issuer_field(issuer: &str, field: &str) -> Option<String> {
+    issuer
+        .split(',')
+        .find_map(|part| part.strip_prefix(field)?.strip_prefix('='))
+        .map(String::from)
+}
+
+/// Runs `openssl` with `args` to `step`, returning its standard output.
+///
+/// Errors when:
+/// - openssl could not be run, or failed
+fn _openssl(step: &str, args: &[&str]) -> Result<String, Error> {
+    debug!("Running openssl to {step}");
+    let output = cmd("openssl", args).context(SslOpensslRunSnafu {
+        step: step.to_string(),
+    })?;
+
+    ensure!(
+        output.status.success(),
+        SslOpensslSnafu {
+            step: step.to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        }
+    );
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPENSSL_CNF: &str = "
+[ ca ]
+default_ca = Yunohost
+
+[ Yunohost ]
+dir = /usr/share/yunohost/ssl
+certs = $dir/certs
+new_certs_dir = $dir/newcerts
+database = $dir/index.txt
+serial = $dir/serial
+certificate = $dir/ca/cacert.pem
+private_key = $dir/ca/cakey.pem
+default_md = sha256
+policy = policy_anything
+
+[ policy_anything ]
+commonName = supplied
+organizationName = optional
+
+[ req ]
+default_bits = 2048
+default_md = sha256
+distinguished_name = req_distinguished_name
+prompt = no
+
+[ req_distinguished_name ]
+commonName = yunohost.org
+";
+
+    #[test]
+    fn local_ca_and_default_cert() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = SslPaths::with_root(Utf8Path::from_path(root.path()).unwrap());
+        let template_dir = paths.rooted(SSL_TEMPLATE_DIR);
+        template_dir.mkdir_p().unwrap();
+        path(template_dir.join("openssl.cnf"))
+            .write(OPENSSL_CNF)
+            .unwrap();
+
+        assert_eq!(None, paths.local_ca_domain().unwrap());
+
+        paths.regen_local_ca("example.tld").unwrap();
+        assert!(paths
+            .openssl_conf()
+            .read()
+            .unwrap()
+            .contains(&format!("dir = {}", paths.ssl_dir())));
+        assert_eq!(
+            Some("example.tld".to_string()),
+            paths.local_ca_domain().unwrap()
+        );
+        assert_eq!(
+            paths.ca_cert().read().unwrap(),
+            paths.rooted(SYSTEM_CA).read().unwrap()
+        );
+
+        paths.create_default_cert().unwrap();
+        let cert_dir = paths.default_cert_dir();
+        assert!(path(cert_dir.join("key.pem")).is_file());
+        assert_eq!(
+            paths.rooted(SYSTEM_CA),
+            path(cert_dir.join("ca.pem")).read_link().unwrap()
+        );
+
+        let subject = _openssl(
+            "read the certificate",
+            &[
+                "x509",
+                "-in",
+                cert_dir.join("crt.pem").as_str(),
+                "-noout",
+                "-subject",
+                "-issuer",
+                "-nameopt",
+                "RFC2253",
+            ],
+        )
+        .unwrap();
+        assert!(subject.contains("subject=CN=yunohost.org"));
+        assert!(subject.contains("issuer=O=example,CN=example.tld"));
+
+        assert_eq!(CertCa::SelfSigned, paths.cert_ca("yunohost.org").unwrap());
+        assert_eq!(CertCa::SelfSigned, paths.cert_ca("missing.tld").unwrap());
+    }
+
+    #[test]
+    fn issuer_field() {
+        let issuer = "CN=R3,O=Let's Encrypt,C=US";
+        assert_eq!(Some("R3".to_string()), _issuer_field(issuer, "CN"));
+        assert_eq!(
+            Some("Let's Encrypt".to_string()),
+            _issuer_field(issuer, "O")
+        );
+        assert_eq!(None, _issuer_field(issuer, "OU"));
+    }
+}
@@ -0,0 +1,208 @@
+//! Jinja templates of the regen-conf hooks.
+//!
+//! The templates of each regen-conf category are in the `conf/<category>` directory of this repository,
+//! with a `.j2` extension. They are embedded in the binaries, so that they don't need to be installed
+//! on the server. Other templates shipped by the Python package in `/usr/share/yunohost/conf/<category>/`
+//! are read from there. They are rendered with
+//! [minijinja](https://docs.rs/minijinja), which supports the conditionals, loops and includes of Jinja2.
+//!
+//! The variables of a template come from a context struct implementing [`Serialize`], built by
+//! the hook from the settings, domains and users. Using a variable which is not in the context
+//! is an error, so that a typo doesn't silently produce a broken configuration.
+//!
+//! The hooks which have no variables to fill in don't use templates: 06-slapd, 09-nslcd, 34-mysql and
+//! 46-nsswitch install the files shipped by the Python package unchanged with
+//! [`install_conf`](crate::helpers::regenconf::hook::install_conf), 02-ssl installs the `openssl.cnf`
+//! of the local CA, where only the CA directory is changed for the tests, and 35-postgresql and
+//! 36-redis keep the configuration shipped by Debian.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use minijinja::{path_loader, Environment, UndefinedBehavior};
+use serde::Serialize;
+use snafu::prelude::*;
+
+use crate::{
+    error::*,
+    helpers::{file::*, regenconf::hook::YUNOHOST_CONF_DIR},
+};
+
+/// Embeds the template `name` of `category` from the `conf` directory of this repository.
+macro_rules! embed {
+    ($category:literal, $name:literal) => {
+        (
+            $category,
+            $name,
+            include_str!(concat!("../../conf/", $category, "/", $name)),
+        )
+    };
+}
+
+/// The templates embedded in the binaries, as `(category, name, source)`.
+const EMBEDDED: &[(&str, &str, &str)] = &[
+    embed!("apt", "ban_packages.j2"),
+    embed!("apt", "extra_php_version.j2"),
+    embed!("apt", "repository.list.j2"),
+    embed!("apt", "yarn.j2"),
+    embed!("dnsmasq", "dnsmasq.conf.j2"),
+    embed!("dnsmasq", "domain.tpl.j2"),
+    embed!("dovecot", "dovecot-ldap.conf.j2"),
+    embed!("dovecot", "dovecot.conf.j2"),
+    embed!("dovecot", "dovecot.sieve.j2"),
+    embed!("dovecot", "post-ext.conf.j2"),
+    embed!("dovecot", "pre-ext.conf.j2"),
+    embed!("fail2ban", "yunohost-jails.conf.j2"),
+    embed!("mdns", "mdns.yml.j2"),
+    embed!("metronome", "domain.tpl.cfg.lua.j2"),
+    embed!("metronome", "metronome.cfg.lua.j2"),
+    embed!("nginx", "global.conf.j2"),
+    embed!("nginx", "security.conf.inc.j2"),
+    embed!("nginx", "server.tpl.conf.j2"),
+    embed!("nginx", "webadmin_allowlist.j2"),
+    embed!("nginx", "yunohost_admin.conf.inc.j2"),
+    embed!("nginx", "yunohost_admin.conf.j2"),
+    embed!("nginx", "yunohost_api.conf.inc.j2"),
+    embed!("postfix", "main.cf.j2"),
+    embed!("postfix", "master.cf.j2"),
+    embed!("postfix", "sni.j2"),
+    embed!("ssh", "sshd_config.j2"),
+    embed!("yunohost", "logind.override.conf.j2"),
+    embed!("yunohost", "nftables.override.conf.j2"),
+    embed!("yunohost", "ntp.override.conf.j2"),
+    embed!("yunohost", "yunohost-certificate-renew.j2"),
+    embed!("yunohost", "yunohost-diagnosis.cron.j2"),
+    embed!("yunohost", "yunohost-dyndns.cron.j2"),
+    embed!("yunohost", "yunohost-fetch-apps-catalog.j2"),
+];
+
+/// The templates of a regen-conf category.
+pub struct Templates {
+    dir: Utf8PathBuf,
+    env: Environment<'static>,
+}
+
+impl Templates {
+    /// Loads the embedded templates of `category`. The other templates are read lazily from
+    /// [`YUNOHOST_CONF_DIR`], when they are rendered.
+    pub fn new(category: &str) -> Self {
+        let mut templates = Self::from_dir(Utf8Path::new(YUNOHOST_CONF_DIR).join(category));
+        for (_, name, source) in EMBEDDED.iter().filter(|(c, ..)| *c == category) {
+            // UNWRAP NOTE: The embedded templates are checked by the embedded test below
+            templates.env.add_template(name, source).unwrap();
+        }
+        templates
+    }
+
+    /// Loads the templates from `dir`, for example from the `conf` directory of this repository in the tests.
+    pub fn from_dir<P: Into<Utf8PathBuf>>(dir: P) -> Self {
+        let dir = dir.into();
+
+        let mut env = Environment::new();
+        env.set_loader(path_loader(&dir));
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        // Block tags can be on their own line without leaving blank lines in the configuration
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        env.set_keep_trailing_newline(true);
+
+        Self { dir, env }
+    }
+
+    /// Renders the template `name` with the variables of `context`. Use `&()` for templates without variables.
+    ///
+    /// Errors when:
+    /// - the template, or a template it includes, does not exist or is invalid
+    /// - the template uses a variable which is not in `context`
+    pub fn render<C: Serialize>(&self, name: &str, context: &C) -> Result<String, Error> {
+        self.env
+            .get_template(name)
+            .and_then(|template| template.render(context))
+            .context(TemplateRenderSnafu {
+                path: self.dir.join(name),
+            })
+    }
+
+    /// Renders the template `name` to `dest`, creating the parent directories of `dest`.
+    ///
+    /// Errors when:
+    /// - rendering the template failed
+    /// - writing `dest` failed
+    pub fn render_to<C: Serialize>(
+        &self,
+        name: &str,
+        context: &C,
+        dest: &Utf8Path,
+    ) -> Result<(), Error> {
+        let content = self.render(name, context)?;
+        // UNWRAP NOTE: dest is a file in the pending dir, so it has a parent
+        path(dest.parent().unwrap())
+            .mkdir_p()
+            .and_then(|_| path(dest).write(content))
+            .context(FileSnafu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Context {
+        domains: Vec<&'static str>,
+        ipv6: bool,
+    }
+
+    #[test]
+    fn render() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        path(dir.join("listen.j2"))
+            .write("    listen 80;\n{% if ipv6 %}\n    listen [::]:80;\n{% endif %}\n")
+            .unwrap();
+        path(dir.join("server.j2"))
+            .write("{% for domain in domains %}\nserver {\n    server_name {{ domain }};\n{% include \"listen.j2\" %}\n}\n{% endfor %}\n")
+            .unwrap();
+        path(dir.join("typo.j2")).write("{{ domian }}").unwrap();
+
+        let templates = Templates::from_dir(dir);
+        let context = Context {
+            domains: vec!["a.example", "b.example"],
+            ipv6: false,
+        };
+        assert_eq!(
+            "server {\n    server_name a.example;\n    listen 80;\n}\nserver {\n    server_name b.example;\n    listen 80;\n}\n",
+            templates.render("server.j2", &context).unwrap()
+        );
+
+        let context = Context {
+            domains: vec!["a.example"],
+            ipv6: true,
+        };
+        templates
+            .render_to("listen.j2", &context, &dir.join("out/listen.conf"))
+            .unwrap();
+        assert_eq!(
+            "    listen 80;\n    listen [::]:80;\n",
+            path(dir.join("out/listen.conf")).read().unwrap()
+        );
+
+        assert!(templates.render("typo.j2", &context).is_err());
+        assert!(templates.render("missing.j2", &()).is_err());
+    }
+
+    #[test]
+    fn embedded() {
+        // Every template of the repository is embedded, and is valid
+        let conf_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/conf");
+        let mut files = glob(&format!("{conf_dir}/*/*.j2")).unwrap();
+        files.sort();
+        let embedded: Vec<Utf8PathBuf> = EMBEDDED
+            .iter()
+            .map(|(category, name, _)| Utf8Path::new(conf_dir).join(category).join(name))
+            .collect();
+        assert_eq!(files, embedded);
+
+        for (category, name, _) in EMBEDDED {
+            assert!(Templates::new(category).env.get_template(name).is_ok());
+        }
+    }
+}
@@ -1,10 +1,30 @@
-use ldap3::{Scope, SearchEntry};
-// use snafu::prelude::*;
+use ldap3::{ldap_escape, Mod, Scope, SearchEntry};
+use regex::Regex;
+use snafu::prelude::*;
 use tokio::runtime::Builder as RuntimeBuilder;
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::str::FromStr;
+use std::time::Duration;
 
-use crate::{error::*, helpers::ldap::*};
+use crate::{
+    error::*,
+    helpers::{
+        credentials::*, domain::*, file::*, hook::hook_callback, ldap::*, lock::MoulinetteLock,
+        process::*, settings::*,
+    },
+};
+
+/// How long to wait for other yunohost commands before changing the users.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+/// The range of the uidNumber/gidNumber of new users. LXC limits uids to 65536 by default.
+const USER_IDS: std::ops::RangeInclusive<u32> = 1001..=65000;
+/// The group of all the Yunohost users, which most permissions are given to.
+const ALL_USERS_GROUP: &str = "all_users";
+/// The group of the administrators, which must always have at least one member.
+const ADMINS_GROUP: &str = "admins";
+/// The mailboxes reserved for the system on the main domain.
+const RESERVED_MAILBOXES: [&str; 5] = ["root", "admin", "webmaster", "postmaster", "abuse"];
 
 /// A specific user to query information about.
 ///
@@ -40,26 +60,54 @@ impl UserQuery {
     }
 }
 
+/// The changes to apply to a user with [`YunohostUser::update`]. Fields left to `None` or empty
+/// are not changed.
+#[derive(Clone, Debug, Default)]
+pub struct UserUpdate {
+    pub fullname: Option<String>,
+    /// The new main email address, which may be one of the current aliases
+    pub mail: Option<String>,
+    pub add_mailalias: Vec<String>,
+    pub remove_mailalias: Vec<String>,
+    pub add_mailforward: Vec<String>,
+    pub remove_mailforward: Vec<String>,
+    pub mailbox_quota: Option<String>,
+    pub change_password: Option<Password>,
+    pub login_shell: Option<String>,
+}
+
 /// A user on the Yunohost system.
 ///
 /// More specifically, an entry with `username` *uid* in the `ou=users` in the
 /// Yunohost LDAP database.
 ///
 /// The user information is populated only with requested [`UserAttr`] attributes, so make sure they are requested
-/// when loading the users from LDAP. Only the `username` user field (`uid` attribute) is mandatory.
+/// when loading the users from LDAP. Only the `username` user field (`uid` attribute) is mandatory: the other fields
+/// are `None` or empty when they were not requested, or when the LDAP entry doesn't have them, like users created
+/// by hand.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct YunohostUser {
     pub username: String,
-    pub fullname: String,
-    pub mail: String,
-    #[serde(rename = "loginShell")]
-    pub login_shell: String,
-    #[serde(rename = "mail-aliases")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fullname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mail: Option<String>,
+    #[serde(
+        rename = "loginShell",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub login_shell: Option<String>,
+    #[serde(rename = "mail-aliases", default)]
     pub mail_aliases: Vec<String>,
-    #[serde(rename = "mail-forward")]
+    #[serde(rename = "mail-forward", default)]
     pub mail_forward: Vec<String>,
-    #[serde(rename = "mailbox-quota")]
-    pub mailbox_quota: String,
+    #[serde(
+        rename = "mailbox-quota",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mailbox_quota: Option<String>,
 }
 
 impl YunohostUser {
@@ -108,18 +156,105 @@ impl YunohostUser {
 
     pub fn list(attrs: Option<Vec<UserAttr>>) -> Result<Vec<Self>, Error> {
         // Default attributes, unless some attrs were requested
-        let attrs: Vec<String> = attrs
-            .unwrap_or(vec![
-                UserAttr::Fullname,
-                UserAttr::Mail,
-                UserAttr::Username,
-                UserAttr::MailForward,
-                UserAttr::MailboxQuota,
-                UserAttr::Shell,
-            ])
-            .into_iter()
-            .map(|attr| attr.to_ldap_attr().to_string())
-            .collect();
+        let attrs = attrs.unwrap_or(vec![
+            UserAttr::Fullname,
+            UserAttr::Mail,
+            UserAttr::Username,
+            UserAttr::MailForward,
+            UserAttr::MailboxQuota,
+            UserAttr::Shell,
+        ]);
+
+        let mut new_list: Vec<YunohostUser> = vec![];
+        for entry in Self::list_entries(&attrs)? {
+            new_list.push(entry.try_into()?);
+        }
+
+        Ok(new_list)
+    }
+
+    /// Lists the LDAP entries of the users, with only the LDAP attributes of `attrs`, for the
+    /// callers which need other fields than [`YunohostUser`] has. The `uid` attribute is always
+    /// requested, and the attributes missing from an entry are not in its `attrs`.
+    ///
+    /// Errors when:
+    /// - querying LDAP failed
+    pub fn list_entries(attrs: &[UserAttr]) -> Result<Vec<SearchEntry>, Error> {
+        let mut ldap_attrs: Vec<&str> = vec![UserAttr::Username.to_ldap_attr()];
+        for attr in attrs.iter().map(UserAttr::to_ldap_attr) {
+            if !ldap_attrs.contains(&attr) {
+                ldap_attrs.push(attr);
+            }
+        }
+
+        let rt = RuntimeBuilder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let ldap = YunohostLDAP::new(1000).await?;
+
+            ldap.list(
+                "ou=users,dc=yunohost,dc=org",
+                Scope::OneLevel,
+                "(&(objectclass=person)(!(uid=root))(!(uid=nobody)))",
+                ldap_attrs,
+            )
+            .await
+        })
+    }
+
+    /// Creates a new user in LDAP, with its primary group, and adds it to the `all_users` group.
+    /// The new user gets a mailbox on `domain`, or on the main domain, and the `post_user_create`
+    /// hooks are run.
+    ///
+    /// Errors when:
+    /// - the username, fullname or mailbox quota are invalid
+    /// - the password is not allowed by the `security.password.user.strength` setting
+    /// - the domain does not exist, or the mailbox is reserved
+    /// - the username or the mailbox is already used
+    /// - another yunohost command is still running
+    /// - no uidNumber/gidNumber is available
+    /// - changing the LDAP database failed
+    pub fn create(
+        username: &Username,
+        fullname: &str,
+        domain: Option<&str>,
+        password: &Password,
+        mailbox_quota: &str,
+    ) -> Result<Self, Error> {
+        let username = username.as_str();
+        let fullname = fullname.trim();
+        _validate_username(username)?;
+        _validate_fullname(fullname)?;
+        _validate_mailbox_quota(mailbox_quota)?;
+
+        password.validate(_password_strength("user")?)?;
+
+        let main_domain = YunohostDomain::main()?;
+        let mail = format!("{username}@{}", domain.unwrap_or(&main_domain));
+        _check_mail_domain(&mail, &YunohostDomain::list(false)?, &main_domain)?;
+
+        let _lock = MoulinetteLock::acquire(LOCK_TIMEOUT).context(UserCreateLockSnafu)?;
+
+        let (system_users, mut used_ids) = _getent("passwd")?;
+        ensure!(
+            !system_users.contains(username),
+            UserCreateSystemUserExistsSnafu { username }
+        );
+        used_ids.extend(_getent("group")?.1);
+        let id = _free_id(&used_ids)
+            .context(UserCreateNoFreeIdSnafu)?
+            .to_string();
+
+        let (firstname, lastname) = _split_fullname(fullname);
+        let user_dn = format!("uid={username},ou=users,dc=yunohost,dc=org");
+        let group_dn = format!("cn={username},ou=groups,dc=yunohost,dc=org");
+        let all_users_dn = format!("cn={ALL_USERS_GROUP},ou=groups,dc=yunohost,dc=org");
+        let home = format!("/home/{username}");
+        let password_hash = password.ldap_hash()?;
 
         let rt = RuntimeBuilder::new_current_thread()
             .enable_io()
@@ -127,32 +262,491 @@ impl YunohostUser {
             .build()
             .unwrap();
 
-        let user_list = rt.block_on(async {
+        rt.block_on(async {
             let ldap = YunohostLDAP::new(1000).await?;
 
-            let user_list = ldap
+            for (attribute, value) in [("uid", username), ("mail", &mail), ("cn", username)] {
+                _ensure_unique(&ldap, attribute, value).await?;
+            }
+
+            ldap.add(
+                &user_dn,
+                vec![
+                    (
+                        "objectClass",
+                        hashset! {
+                            "mailAccount",
+                            "inetOrgPerson",
+                            "posixAccount",
+                            "userPermissionYnh"
+                        },
+                    ),
+                    ("givenName", hashset! {firstname.as_str()}),
+                    ("sn", hashset! {lastname.as_str()}),
+                    ("displayName", hashset! {fullname}),
+                    ("cn", hashset! {fullname}),
+                    ("uid", hashset! {username}),
+                    ("mail", hashset! {mail.as_str()}),
+                    ("maildrop", hashset! {username}),
+                    ("mailuserquota", hashset! {mailbox_quota}),
+                    ("userPassword", hashset! {password_hash.as_str()}),
+                    ("gidNumber", hashset! {id.as_str()}),
+                    ("uidNumber", hashset! {id.as_str()}),
+                    ("homeDirectory", hashset! {home.as_str()}),
+                    ("loginShell", hashset! {"/bin/bash"}),
+                ],
+            )
+            .await?;
+
+            // The primary group of the user only contains the user
+            ldap.add(
+                &group_dn,
+                vec![
+                    (
+                        "objectClass",
+                        hashset! {"top", "groupOfNamesYnh", "posixGroup"},
+                    ),
+                    ("cn", hashset! {username}),
+                    ("gidNumber", hashset! {id.as_str()}),
+                    ("member", hashset! {user_dn.as_str()}),
+                    ("memberUid", hashset! {username}),
+                ],
+            )
+            .await?;
+
+            ldap.modify(
+                &all_users_dn,
+                vec![
+                    Mod::Add("member", hashset! {user_dn.as_str()}),
+                    Mod::Add("memberUid", hashset! {username}),
+                ],
+            )
+            .await?;
+
+            // Python: permission_sync_to_user, for the groups of the new user only
+            let permissions = ldap
                 .list(
-                    "ou=users,dc=yunohost,dc=org",
+                    "ou=permission,dc=yunohost,dc=org",
                     Scope::OneLevel,
-                    "(&(objectclass=person)(!(uid=root))(!(uid=nobody)))",
-                    attrs,
+                    &format!(
+                        "(&(objectclass=permissionYnh)(|(groupPermission={})(groupPermission={})))",
+                        ldap_escape(&all_users_dn),
+                        ldap_escape(&group_dn)
+                    ),
+                    vec!["1.1"],
                 )
                 .await?;
+            for permission in permissions {
+                ldap.modify(
+                    &permission.dn,
+                    vec![
+                        Mod::Add("inheritPermission", hashset! {user_dn.as_str()}),
+                        Mod::Add("memberUid", hashset! {username}),
+                    ],
+                )
+                .await?;
+            }
 
-            Ok(user_list)
+            Ok(())
         })?;
 
-        let mut new_list: Vec<YunohostUser> = vec![];
-        for entry in user_list {
-            new_list.push(entry.try_into()?);
+        // Make sure the new user and group are visible to the system
+        for database in ["passwd", "group"] {
+            let _ = cmd("nscd", ["-i", database]);
         }
 
-        Ok(new_list)
+        if !cmd("mkhomedir_helper", [username]).is_ok_and(|res| res.status.success())
+            && !path(&home).is_dir()
+        {
+            warn!("Failed to create the home folder {home} of user {username}");
+        }
+        // The home of a user is not readable by the other users
+        if !cmd(
+            "setfacl",
+            ["-m", &format!("g:{ALL_USERS_GROUP}:---"), &home],
+        )
+        .is_ok_and(|res| res.status.success())
+        {
+            warn!("Failed to protect the home folder {home} of user {username}");
+        }
+
+        let env: BTreeMap<String, String> = btreemap! {
+            "YNH_USER_USERNAME".to_string() => username.to_string(),
+            "YNH_USER_MAIL".to_string() => mail.clone(),
+            "YNH_USER_PASSWORD".to_string() => password.as_str().to_string(),
+            "YNH_USER_FIRSTNAME".to_string() => firstname,
+            "YNH_USER_LASTNAME".to_string() => lastname,
+        };
+        hook_callback("post_user_create", &[username, &mail], &env);
+
+        info!("User {username} created");
+        Ok(Self {
+            username: username.to_string(),
+            fullname: Some(fullname.to_string()),
+            mail: Some(mail),
+            login_shell: Some("/bin/bash".to_string()),
+            mail_aliases: vec![],
+            mail_forward: vec![],
+            mailbox_quota: Some(mailbox_quota.to_string()),
+        })
+    }
+
+    /// Deletes a user from LDAP, with its primary group, and removes it from the other groups and
+    /// the permissions. With `purge`, the home folder and the mailbox of the user are removed too.
+    /// Then the `post_user_delete` hooks are run.
+    ///
+    /// The steps are applied one after the other, so when a step fails, the error tells which step
+    /// failed, the previous steps having been applied already.
+    ///
+    /// Errors when:
+    /// - the user does not exist
+    /// - the user is the last member of the admins group
+    /// - another yunohost command is still running
+    /// - one of the steps failed
+    pub fn delete(username: &Username, purge: bool) -> Result<(), Error> {
+        let username = username.as_str();
+        let user_dn = format!("uid={username},ou=users,dc=yunohost,dc=org");
+        let group_dn = format!("cn={username},ou=groups,dc=yunohost,dc=org");
+        let step = |step: &str| UserDeleteStepSnafu {
+            username,
+            step: step.to_string(),
+        };
+
+        let _lock = MoulinetteLock::acquire(LOCK_TIMEOUT).context(UserDeleteLockSnafu)?;
+
+        let rt = RuntimeBuilder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let ldap = YunohostLDAP::new(1000)
+                .await
+                .context(step("connecting to LDAP"))?;
+
+            let user = ldap
+                .search_checked(
+                    "ou=users,dc=yunohost,dc=org",
+                    Scope::OneLevel,
+                    &format!("(uid={})", ldap_escape(username)),
+                    vec!["1.1"],
+                )
+                .await
+                .context(step("looking up the user"))?;
+            ensure!(!user.is_empty(), UserUnknownSnafu { username });
+
+            let admins = ldap
+                .search_checked(
+                    "ou=groups,dc=yunohost,dc=org",
+                    Scope::OneLevel,
+                    &format!("(cn={ADMINS_GROUP})"),
+                    vec!["member"],
+                )
+                .await
+                .context(step("looking up the admins"))?;
+            let (user_admin, other_admins): (Vec<&String>, Vec<&String>) = admins
+                .iter()
+                .flat_map(|group| group.attrs.get("member").into_iter().flatten())
+                .partition(|admin| admin.eq_ignore_ascii_case(&user_dn));
+            ensure!(
+                user_admin.is_empty() || !other_admins.is_empty(),
+                UserDeleteLastAdminSnafu { username }
+            );
+
+            // The primary group is deleted below
+            let groups = ldap
+                .search_checked(
+                    "ou=groups,dc=yunohost,dc=org",
+                    Scope::OneLevel,
+                    &format!(
+                        "(&(objectclass=groupOfNamesYnh)(member={})(!(cn={})))",
+                        ldap_escape(&user_dn),
+                        ldap_escape(username)
+                    ),
+                    vec!["cn", "member", "memberUid"],
+                )
+                .await
+                .context(step("looking up the groups of the user"))?;
+            for group in groups {
+                let mods =
+                    _remove_values(&group, &[("member", &user_dn), ("memberUid", username)]);
+                ldap.modify(&group.dn, mods)
+                    .await
+                    .context(step(&format!("removing the user from group {}", group.dn)))?;
+            }
+
+            let permissions = ldap
+                .search_checked(
+                    "ou=permission,dc=yunohost,dc=org",
+                    Scope::OneLevel,
+                    &format!(
+                        "(&(objectclass=permissionYnh)(|(inheritPermission={})(groupPermission={})))",
+                        ldap_escape(&user_dn),
+                        ldap_escape(&group_dn)
+                    ),
+                    vec!["inheritPermission", "groupPermission", "memberUid"],
+                )
+                .await
+                .context(step("looking up the permissions of the user"))?;
+            for permission in permissions {
+                let mods = _remove_values(
+                    &permission,
+                    &[
+                        ("inheritPermission", &user_dn),
+                        ("groupPermission", &group_dn),
+                        ("memberUid", username),
+                    ],
+                );
+                ldap.modify(&permission.dn, mods).await.context(step(&format!(
+                    "removing the user from permission {}",
+                    permission.dn
+                )))?;
+            }
+
+            // The primary group may be missing after a partial deletion
+            let primary_group = ldap
+                .search_checked(
+                    "ou=groups,dc=yunohost,dc=org",
+                    Scope::OneLevel,
+                    &format!("(cn={})", ldap_escape(username)),
+                    vec!["1.1"],
+                )
+                .await
+                .context(step("looking up the primary group"))?;
+            if !primary_group.is_empty() {
+                ldap.delete(&group_dn)
+                    .await
+                    .context(step("deleting the primary group"))?;
+            }
+
+            ldap.delete(&user_dn)
+                .await
+                .context(step("deleting the user entry"))?;
+
+            Ok(())
+        })?;
+
+        // Make sure the deleted user and group are not visible to the system anymore
+        for database in ["passwd", "group"] {
+            let _ = cmd("nscd", ["-i", database]);
+        }
+
+        if purge {
+            for dir in [format!("/home/{username}"), format!("/var/mail/{username}")] {
+                if path(&dir).exists() {
+                    std::fs::remove_dir_all(&dir).context(UserDeletePurgeSnafu {
+                        username,
+                        path: dir.clone(),
+                    })?;
+                }
+            }
+        }
+
+        // Like Python, which gives the purge flag as a boolean
+        let purge = if purge { "True" } else { "False" };
+        hook_callback("post_user_delete", &[username, purge], &BTreeMap::new());
+
+        info!("User {username} deleted");
+        Ok(())
+    }
+
+    /// Applies the changes of `update` to a user in LDAP, in a single modification, then runs the
+    /// `post_user_update` hooks with the changed values.
+    ///
+    /// Errors when:
+    /// - the user does not exist
+    /// - the fullname, an email address, the mailbox quota or the login shell are invalid
+    /// - the password is not allowed by the `security.password.{admin,user}.strength` setting
+    /// - an email address is on an unknown domain, is reserved, or is used by another user
+    /// - an alias or a forward to remove is not set on the user
+    /// - another yunohost command is still running
+    /// - changing the LDAP database failed
+    pub fn update(username: &Username, update: &UserUpdate) -> Result<(), Error> {
+        let username = username.as_str();
+        let user_dn = format!("uid={username},ou=users,dc=yunohost,dc=org");
+        let admins_dn = format!("cn={ADMINS_GROUP},ou=groups,dc=yunohost,dc=org");
+
+        let fullname = update.fullname.as_deref().map(str::trim);
+        if let Some(fullname) = fullname {
+            _validate_fullname(fullname)?;
+        }
+        for mail in update.mail.iter().chain(&update.add_mailalias) {
+            _validate_mail(mail, false)?;
+        }
+        for mail in &update.add_mailforward {
+            _validate_mail(mail, true)?;
+        }
+        if let Some(mailbox_quota) = &update.mailbox_quota {
+            _validate_mailbox_quota(mailbox_quota)?;
+        }
+        if let Some(shell) = &update.login_shell {
+            ensure!(
+                _list_shells()?.contains(shell) && path(shell).is_file(),
+                UserUpdateShellInvalidSnafu { shell }
+            );
+        }
+
+        let (domains, main_domain) = if update.mail.is_some() || !update.add_mailalias.is_empty() {
+            (YunohostDomain::list(false)?, YunohostDomain::main()?)
+        } else {
+            (vec![], String::new())
+        };
+
+        let _lock = MoulinetteLock::acquire(LOCK_TIMEOUT).context(UserUpdateLockSnafu)?;
+
+        let rt = RuntimeBuilder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        let mut env = btreemap! {
+            "YNH_USER_USERNAME".to_string() => username.to_string(),
+        };
+
+        rt.block_on(async {
+            let ldap = YunohostLDAP::new(1000).await?;
+
+            let Some(user) = ldap
+                .list(
+                    "ou=users,dc=yunohost,dc=org",
+                    Scope::OneLevel,
+                    &format!("(uid={})", ldap_escape(username)),
+                    vec!["mail", "maildrop", "memberOf"],
+                )
+                .await?
+                .pop()
+            else {
+                return UserUnknownSnafu { username }.fail();
+            };
+            let values = |attr: &str| user.attrs.get(attr).cloned().unwrap_or_default();
+
+            let mut mods: Vec<Mod<String>> = vec![];
+
+            if let Some(fullname) = fullname {
+                let (firstname, lastname) = _split_fullname(fullname);
+                mods.push(Mod::Replace(
+                    "givenName".to_string(),
+                    hashset! {firstname.clone()},
+                ));
+                mods.push(Mod::Replace("sn".to_string(), hashset! {lastname.clone()}));
+                for attr in ["cn", "displayName"] {
+                    mods.push(Mod::Replace(
+                        attr.to_string(),
+                        hashset! {fullname.to_string()},
+                    ));
+                }
+                env.insert("YNH_USER_FIRSTNAME".to_string(), firstname);
+                env.insert("YNH_USER_LASTNAME".to_string(), lastname);
+            }
+
+            if let Some(password) = &update.change_password {
+                let profile = if values("memberOf")
+                    .iter()
+                    .any(|group| group.eq_ignore_ascii_case(&admins_dn))
+                {
+                    "admin"
+                } else {
+                    "user"
+                };
+                password.validate(_password_strength(profile)?)?;
+                mods.push(Mod::Replace(
+                    "userPassword".to_string(),
+                    hashset! {password.ldap_hash()?},
+                ));
+                env.insert(
+                    "YNH_USER_PASSWORD".to_string(),
+                    password.as_str().to_string(),
+                );
+            }
+
+            let mut mails = values("mail");
+            if let Some(mail) = &update.mail {
+                // The new main address may be one of the addresses of the user
+                if !mails.contains(mail) {
+                    _ensure_unique(&ldap, "mail", mail).await?;
+                }
+                _check_mail_domain(mail, &domains, &main_domain)?;
+                _set_main_mail(&mut mails, mail);
+            }
+            for mail in &update.add_mailalias {
+                if mails.contains(mail) {
+                    continue;
+                }
+                _ensure_unique(&ldap, "mail", mail).await?;
+                _check_mail_domain(mail, &domains, &main_domain)?;
+                mails.push(mail.clone());
+            }
+            for mail in &update.remove_mailalias {
+                let position = mails.iter().skip(1).position(|m| m == mail);
+                ensure!(position.is_some(), UserUpdateMailAliasRemoveSnafu { mail });
+                // UNWRAP NOTE: The position was just checked
+                mails.remove(position.unwrap() + 1);
+            }
+            if update.mail.is_some()
+                || !update.add_mailalias.is_empty()
+                || !update.remove_mailalias.is_empty()
+            {
+                env.insert("YNH_USER_MAILS".to_string(), mails.join(","));
+                mods.extend(_replace_ordered("mail", &mails));
+            }
+
+            let mut maildrop = values("maildrop");
+            for mail in &update.add_mailforward {
+                if !maildrop.iter().skip(1).any(|m| m == mail) {
+                    maildrop.push(mail.clone());
+                }
+            }
+            for mail in &update.remove_mailforward {
+                let position = maildrop.iter().skip(1).position(|m| m == mail);
+                ensure!(
+                    position.is_some(),
+                    UserUpdateMailForwardRemoveSnafu { mail }
+                );
+                // UNWRAP NOTE: The position was just checked
+                maildrop.remove(position.unwrap() + 1);
+            }
+            if !update.add_mailforward.is_empty() || !update.remove_mailforward.is_empty() {
+                env.insert("YNH_USER_MAILFORWARDS".to_string(), maildrop.join(","));
+                mods.extend(_replace_ordered("maildrop", &maildrop));
+            }
+
+            if let Some(mailbox_quota) = &update.mailbox_quota {
+                mods.push(Mod::Replace(
+                    "mailuserquota".to_string(),
+                    hashset! {mailbox_quota.clone()},
+                ));
+                env.insert("YNH_USER_MAILQUOTA".to_string(), mailbox_quota.clone());
+            }
+
+            if let Some(shell) = &update.login_shell {
+                mods.push(Mod::Replace(
+                    "loginShell".to_string(),
+                    hashset! {shell.clone()},
+                ));
+                env.insert("YNH_USER_LOGINSHELL".to_string(), shell.clone());
+            }
+
+            if !mods.is_empty() {
+                ldap.modify(&user_dn, mods).await?;
+            }
+
+            Ok(())
+        })?;
+
+        hook_callback("post_user_update", &[], &env);
+
+        info!("User {username} updated");
+        Ok(())
     }
 
     /// Shorthand method for querying the list of usernames only
     pub fn usernames() -> Result<Vec<String>, Error> {
-        Ok(Self::list(None)?.into_iter().map(|x| x.username).collect())
+        Ok(Self::list(Some(vec![UserAttr::Username]))?
+            .into_iter()
+            .map(|x| x.username)
+            .collect())
     }
 
     pub fn name_from_dn(dn: &str) -> String {
@@ -160,72 +754,280 @@ impl YunohostUser {
             .trim_end_matches(",ou=users,dc=yunohost,dc=org")
             .to_string()
     }
+
+    /// Reads the username from the `uid` attribute of a user entry.
+    ///
+    /// Errors when:
+    /// - the entry has no `uid` attribute
+    pub fn uid(user: &SearchEntry) -> Result<String, Error> {
+        user.attrs
+            .get(UserAttr::Username.to_ldap_attr())
+            .and_then(|values| values.first())
+            .cloned()
+            .context(UserAttributeMissingSnafu {
+                user: &user.dn,
+                attribute: UserAttr::Username.to_ldap_attr(),
+            })
+    }
 }
 
 impl TryFrom<SearchEntry> for YunohostUser {
     type Error = Error;
 
+    /// Loads the fields of the attributes in the LDAP entry, leaving the others empty.
+    ///
+    /// Errors when:
+    /// - the entry has no `uid` attribute
     fn try_from(user: SearchEntry) -> Result<Self, Self::Error> {
-        let mut mail_aliases: Vec<String> = vec![];
-        for addr in user
-            .attrs
-            .get(UserAttr::MailAlias.to_ldap_attr())
+        let values = |attr: UserAttr| -> &[String] {
+            user.attrs
+                .get(attr.to_ldap_attr())
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+        };
+        let first = |attr: UserAttr| values(attr).first().cloned();
+
+        Ok(Self {
+            username: Self::uid(&user)?,
+            fullname: first(UserAttr::Fullname),
+            mail: first(UserAttr::Mail),
+            login_shell: first(UserAttr::Shell),
+            mail_aliases: values(UserAttr::MailAlias)
+                .iter()
+                .skip(1)
+                .cloned()
+                .collect(),
+            mail_forward: values(UserAttr::MailForward)
+                .iter()
+                .skip(1)
+                .cloned()
+                .collect(),
+            mailbox_quota: first(UserAttr::MailboxQuota),
+        })
+    }
+}
+
+/// Checks that a username only contains lowercase letters, digits and underscores, like the
+/// `pattern_username` pattern of the Python actions map.
+///
+/// Errors when:
+/// - the username is invalid
+fn _validate_username(username: &str) -> Result<(), Error> {
+    // UNWRAP NOTE: The regex cannot fail because it's well-known.
+    ensure!(
+        Regex::new(r"^[a-z0-9_]+$").unwrap().is_match(username),
+        UserUsernameInvalidSnafu { username }
+    );
+    Ok(())
+}
+
+/// Checks that a fullname contains letters or digits, only separated by spaces or `,.'-`, like the
+/// `pattern_fullname` pattern of the Python actions map.
+///
+/// Errors when:
+/// - the fullname is invalid
+fn _validate_fullname(fullname: &str) -> Result<(), Error> {
+    // UNWRAP NOTE: The regex cannot fail because it's well-known.
+    ensure!(
+        Regex::new(r"^([^\W_]{1,30}[ ,.'-]{0,3})+$")
             .unwrap()
-            .into_iter()
-            .skip(1)
-        {
-            mail_aliases.push(addr.to_string());
+            .is_match(fullname),
+        UserFullnameInvalidSnafu { fullname }
+    );
+    Ok(())
+}
+
+/// Checks that a mailbox quota is `0` or a size like `500M`, like the `pattern_mailbox_quota`
+/// pattern of the Python actions map.
+///
+/// Errors when:
+/// - the mailbox quota is invalid
+fn _validate_mailbox_quota(mailbox_quota: &str) -> Result<(), Error> {
+    // UNWRAP NOTE: The regex cannot fail because it's well-known.
+    ensure!(
+        Regex::new(r"^(\d+[bkMGT]|0)$")
+            .unwrap()
+            .is_match(mailbox_quota),
+        UserMailboxQuotaInvalidSnafu { mailbox_quota }
+    );
+    Ok(())
+}
+
+/// Checks the format of an email address, like the `pattern_email` pattern of the Python actions
+/// map. Forwards may also contain a `+`, like `pattern_email_forward`.
+///
+/// Errors when:
+/// - the email address is invalid
+fn _validate_mail(mail: &str, forward: bool) -> Result<(), Error> {
+    let local_part = if forward { r"[\w\+.-]+" } else { r"[\w.-]+" };
+    // UNWRAP NOTE: The regex cannot fail because it's well-known.
+    let re = Regex::new(&format!(
+        r"^{local_part}@([^\W_A-Z]+([-]*[^\W_A-Z]+)*\.)+((xn--)?[^\W_]{{2,}})$"
+    ))
+    .unwrap();
+    ensure!(re.is_match(mail), UserMailInvalidSnafu { mail });
+    Ok(())
+}
+
+/// Checks that the domain of `mail` is one of `domains`, and that `mail` is not one of the
+/// [`RESERVED_MAILBOXES`] of `main_domain`.
+///
+/// Errors when:
+/// - the domain is not handled by the server
+/// - the email address is reserved
+fn _check_mail_domain(mail: &str, domains: &[String], main_domain: &str) -> Result<(), Error> {
+    let domain = mail.split_once('@').map(|(_, domain)| domain).unwrap_or("");
+    ensure!(
+        domains.iter().any(|d| d == domain),
+        UserMailDomainUnknownSnafu { domain }
+    );
+    ensure!(
+        !RESERVED_MAILBOXES
+            .iter()
+            .any(|mailbox| mail == format!("{mailbox}@{main_domain}")),
+        UserMailUnavailableSnafu { mail }
+    );
+    Ok(())
+}
+
+/// Checks that no LDAP entry has `value` for `attribute`, like Python's `ldap.validate_uniqueness`.
+///
+/// Errors when:
+/// - querying LDAP failed
+/// - an entry already has this value
+async fn _ensure_unique(ldap: &YunohostLDAP, attribute: &str, value: &str) -> Result<(), Error> {
+    let conflicts = ldap
+        .search_checked(
+            "dc=yunohost,dc=org",
+            Scope::Subtree,
+            &format!("({attribute}={})", ldap_escape(value)),
+            vec!["1.1"],
+        )
+        .await?;
+    ensure!(
+        conflicts.is_empty(),
+        UserAttributeExistsSnafu { attribute, value }
+    );
+    Ok(())
+}
+
+/// Reads the minimum password strength of `profile` (`user` or `admin`) from the settings.
+///
+/// Errors when:
+/// - reading the settings failed
+fn _password_strength(profile: &str) -> Result<i64, Error> {
+    let strength = SettingsConfigPanel::new()
+        .and_then(|mut settings| {
+            settings.get_string(&format!("security.password.{profile}.strength"))
+        })
+        .context(ConfigPanelSnafu)
+        .context(UserPasswordSettingsSnafu)?;
+    // An invalid setting falls back to the default strength
+    Ok(strength.parse().unwrap_or(1))
+}
+
+/// Splits a validated fullname into the firstname and lastname of LDAP, like Python.
+fn _split_fullname(fullname: &str) -> (String, String) {
+    let mut names = fullname.split_whitespace();
+    let firstname = names.next().unwrap_or_default().to_string();
+    let mut lastname = names.collect::<Vec<&str>>().join(" ");
+    if lastname.is_empty() {
+        // Because LDAP requires the sn attribute, but accepts a single whitespace
+        lastname = " ".to_string();
+    }
+    (firstname, lastname)
+}
+
+/// Makes `mail` the main address of the user, which is the first of `mails`. Like Python, the
+/// previous main address is replaced, and `mail` is not an alias anymore.
+fn _set_main_mail(mails: &mut Vec<String>, mail: &str) {
+    if let Some(position) = mails.iter().skip(1).position(|m| m == mail) {
+        mails.remove(position + 1);
+    }
+    match mails.first_mut() {
+        Some(main) => *main = mail.to_string(),
+        None => mails.push(mail.to_string()),
+    }
+}
+
+/// Builds the modifications replacing all the values of `attr` with `values`, keeping their order.
+/// ldap3 gives the values of an attribute as a set, but the first `mail` is the main address of
+/// the user and the first `maildrop` is its mailbox, so the values are added one by one.
+fn _replace_ordered(attr: &str, values: &[String]) -> Vec<Mod<String>> {
+    let mut values = values.iter();
+    let mut mods = vec![Mod::Replace(
+        attr.to_string(),
+        values.next().cloned().into_iter().collect(),
+    )];
+    mods.extend(values.map(|value| Mod::Add(attr.to_string(), hashset! {value.clone()})));
+    mods
+}
+
+/// Lists the login shells allowed in `/etc/shells`.
+///
+/// Errors when:
+/// - reading `/etc/shells` failed
+fn _list_shells() -> Result<Vec<String>, Error> {
+    Ok(path("/etc/shells")
+        .read_lines()
+        .context(FileSnafu)?
+        .into_iter()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect())
+}
+
+/// Lists the names and ids of a database of getent, like `passwd` or `group`. The LDAP users and
+/// groups are included, through nslcd.
+///
+/// Errors when:
+/// - getent failed
+fn _getent(database: &str) -> Result<(BTreeSet<String>, BTreeSet<u32>), Error> {
+    let res = cmd("getent", [database]).context(UserCreateGetentRunSnafu { database })?;
+    ensure!(
+        res.status.success(),
+        UserCreateGetentSnafu {
+            database,
+            stderr: String::from_utf8_lossy(&res.stderr).to_string(),
         }
+    );
 
-        let mut mail_forward: Vec<String> = vec![];
-        for addr in user
+    let mut names = BTreeSet::new();
+    let mut ids = BTreeSet::new();
+    for line in String::from_utf8_lossy(&res.stdout).lines() {
+        let mut fields = line.split(':');
+        if let Some(name) = fields.next() {
+            names.insert(name.to_string());
+        }
+        if let Some(id) = fields.nth(1).and_then(|id| id.parse().ok()) {
+            ids.insert(id);
+        }
+    }
+    Ok((names, ids))
+}
+
+/// Builds the modifications removing `values` from the attributes of `entry`. Only the values that
+/// `entry` actually has are removed, because removing a missing value is an LDAP error.
+fn _remove_values<'a>(entry: &SearchEntry, values: &[(&'a str, &'a str)]) -> Vec<Mod<&'a str>> {
+    let mut mods = vec![];
+    for (attr, value) in values {
+        let present = entry
             .attrs
-            .get(UserAttr::MailForward.to_ldap_attr())
-            .unwrap()
+            .get(*attr)
             .into_iter()
-            .skip(1)
-        {
-            mail_forward.push(addr.to_string());
+            .flatten()
+            .any(|existing| existing.eq_ignore_ascii_case(value));
+        if present {
+            mods.push(Mod::Delete(*attr, hashset! {*value}));
         }
-
-        Ok(Self {
-            username: Self::name_from_dn(&user.dn),
-            fullname: user
-                .attrs
-                .get(UserAttr::Fullname.to_ldap_attr())
-                .unwrap()
-                .into_iter()
-                .next()
-                .unwrap()
-                .to_string(),
-            mail: user
-                .attrs
-                .get(UserAttr::Mail.to_ldap_attr())
-                .unwrap()
-                .into_iter()
-                .next()
-                .unwrap()
-                .to_string(),
-            login_shell: user
-                .attrs
-                .get(UserAttr::Shell.to_ldap_attr())
-                .unwrap()
-                .into_iter()
-                .next()
-                .unwrap()
-                .to_string(),
-            mail_aliases,
-            mail_forward,
-            mailbox_quota: user
-                .attrs
-                .get(UserAttr::MailboxQuota.to_ldap_attr())
-                .unwrap()
-                .into_iter()
-                .next()
-                .unwrap()
-                .to_string(),
-        })
     }
+    mods
+}
+
+/// Finds the lowest id in [`USER_IDS`] which is not in `used_ids`, to use both as uidNumber and
+/// gidNumber of a new user.
+fn _free_id(used_ids: &BTreeSet<u32>) -> Option<u32> {
+    USER_IDS.into_iter().find(|id| !used_ids.contains(id))
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -304,3 +1106,147 @@ impl UserAttr {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_user() {
+        assert!(_validate_username("alice_2").is_ok());
+        assert!(_validate_username("Alice").is_err());
+        assert!(_validate_username("alice.l").is_err());
+        assert!(_validate_fullname("Alice Liddell").is_ok());
+        assert!(_validate_fullname("Alice O'Hara-Smith").is_ok());
+        assert!(_validate_fullname(" ").is_err());
+        assert!(_validate_fullname("Alice <script>").is_err());
+        assert!(_validate_mailbox_quota("0").is_ok());
+        assert!(_validate_mailbox_quota("500M").is_ok());
+        assert!(_validate_mailbox_quota("500").is_err());
+        assert!(_validate_mailbox_quota("5G0").is_err());
+    }
+
+    #[test]
+    fn try_from_entry() {
+        let entry = SearchEntry {
+            dn: "uid=alice,ou=users,dc=yunohost,dc=org".to_string(),
+            attrs: hashmap! {
+                "uid".to_string() => vec!["alice".to_string()],
+                "cn".to_string() => vec!["Alice Liddell".to_string()],
+                "mail".to_string() => vec![
+                    "alice@example.com".to_string(),
+                    "a@example.com".to_string(),
+                ],
+            },
+            bin_attrs: hashmap! {},
+        };
+        let user = YunohostUser::try_from(entry.clone()).unwrap();
+        assert_eq!("alice", user.username);
+        assert_eq!(Some("Alice Liddell".to_string()), user.fullname);
+        assert_eq!(Some("alice@example.com".to_string()), user.mail);
+        assert_eq!(vec!["a@example.com"], user.mail_aliases);
+        assert_eq!(None, user.login_shell);
+        assert!(user.mail_forward.is_empty());
+        assert_eq!(None, user.mailbox_quota);
+
+        let mut entry = entry;
+        entry.attrs.remove("uid");
+        assert!(matches!(
+            YunohostUser::try_from(entry),
+            Err(Error::UserAttributeMissing { user, attribute })
+                if user == "uid=alice,ou=users,dc=yunohost,dc=org" && attribute == "uid"
+        ));
+    }
+
+    #[test]
+    fn validate_mail() {
+        assert!(_validate_mail("alice@example.com", false).is_ok());
+        assert!(_validate_mail("alice.l-2@sub.example.com", false).is_ok());
+        assert!(_validate_mail("alice+news@example.com", false).is_err());
+        assert!(_validate_mail("alice+news@example.com", true).is_ok());
+        assert!(_validate_mail("alice@Example.com", false).is_err());
+        assert!(_validate_mail("alice@localhost", false).is_err());
+        assert!(_validate_mail("alice", true).is_err());
+    }
+
+    #[test]
+    fn check_mail_domain() {
+        let domains = ["example.com", "example.org"].map(String::from);
+        assert!(_check_mail_domain("alice@example.org", &domains, "example.com").is_ok());
+        assert!(_check_mail_domain("root@example.org", &domains, "example.com").is_ok());
+        assert!(_check_mail_domain("root@example.com", &domains, "example.com").is_err());
+        assert!(_check_mail_domain("alice@example.net", &domains, "example.com").is_err());
+    }
+
+    #[test]
+    fn set_main_mail() {
+        let mut mails = ["alice@example.com", "a@example.com", "b@example.com"]
+            .map(String::from)
+            .to_vec();
+        _set_main_mail(&mut mails, "b@example.com");
+        assert_eq!(vec!["b@example.com", "a@example.com"], mails);
+
+        _set_main_mail(&mut mails, "b@example.com");
+        assert_eq!(vec!["b@example.com", "a@example.com"], mails);
+
+        _set_main_mail(&mut mails, "new@example.com");
+        assert_eq!(vec!["new@example.com", "a@example.com"], mails);
+
+        let mut mails = vec![];
+        _set_main_mail(&mut mails, "new@example.com");
+        assert_eq!(vec!["new@example.com"], mails);
+    }
+
+    #[test]
+    fn replace_ordered() {
+        let mails = ["alice@example.com", "a@example.com", "b@example.com"].map(String::from);
+        assert_eq!(
+            vec![
+                Mod::Replace("mail".to_string(), hashset! {mails[0].clone()}),
+                Mod::Add("mail".to_string(), hashset! {mails[1].clone()}),
+                Mod::Add("mail".to_string(), hashset! {mails[2].clone()}),
+            ],
+            _replace_ordered("mail", &mails)
+        );
+
+        assert_eq!(
+            vec![Mod::Replace("maildrop".to_string(), hashset! {})],
+            _replace_ordered("maildrop", &[])
+        );
+    }
+
+    #[test]
+    fn remove_values() {
+        let entry = SearchEntry {
+            dn: "cn=mail.main,ou=permission,dc=yunohost,dc=org".to_string(),
+            attrs: hashmap! {
+                "inheritPermission".to_string() => vec![
+                    "uid=alice,ou=users,dc=yunohost,dc=org".to_string(),
+                    "uid=bob,ou=users,dc=yunohost,dc=org".to_string(),
+                ],
+                "memberUid".to_string() => vec!["bob".to_string()],
+            },
+            bin_attrs: hashmap! {},
+        };
+        let mods = _remove_values(
+            &entry,
+            &[
+                ("inheritPermission", "uid=alice,ou=users,dc=yunohost,dc=org"),
+                ("groupPermission", "cn=alice,ou=groups,dc=yunohost,dc=org"),
+                ("memberUid", "alice"),
+            ],
+        );
+        assert_eq!(1, mods.len());
+        assert!(matches!(
+            &mods[0],
+            Mod::Delete("inheritPermission", values) if values.contains("uid=alice,ou=users,dc=yunohost,dc=org")
+        ));
+    }
+
+    #[test]
+    fn free_id() {
+        assert_eq!(Some(1001), _free_id(&BTreeSet::from([0, 1000])));
+        assert_eq!(Some(1003), _free_id(&BTreeSet::from([1001, 1002, 1004])));
+        assert_eq!(None, _free_id(&USER_IDS.collect()));
+    }
+}
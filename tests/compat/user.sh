@@ -4,3 +4,4 @@ source /tmp/yunohost-compat/__helpers.sh
 
 benchPythonRust json "user" list --json
 benchPythonRust json "user" info --json test2
+benchPythonRust json "user" list --json --fields username mail mail-alias mail-forward groups shell home-path